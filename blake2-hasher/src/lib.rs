@@ -0,0 +1,156 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Hasher implementation for the Blake2b-256 hash.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use hash256_std_hasher::Hash256StdHasher;
+use hash_db::Hasher;
+
+/// Length of the hashes, in bytes.
+const LENGTH: usize = 32;
+
+/// Concrete `Hasher` impl for the Blake2b hash with 32-byte output.
+///
+/// With the `triehash` feature, it implements `triehash::KeyedHasher` by personalizing the
+/// hash, so e.g. `triehash::Keyed<Blake2Hasher, D>` computes domain-separated trie roots.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Blake2Hasher;
+
+impl Blake2Hasher {
+	/// Hashes `x` with the personalization `personal`, which separates the hashes of different
+	/// uses of Blake2b without changing the hashed data.
+	///
+	/// # Panics
+	///
+	/// Panics if `personal` is longer than 16 bytes, the size of the personalization parameter.
+	pub fn hash_personalized(personal: &[u8], x: &[u8]) -> [u8; LENGTH] {
+		let mut out = [0; LENGTH];
+		out.copy_from_slice(params(personal).hash(x).as_bytes());
+		out
+	}
+}
+
+/// Parameters of Blake2b-256 with the personalization `personal`.
+fn params(personal: &[u8]) -> blake2b_simd::Params {
+	assert!(personal.len() <= blake2b_simd::PERSONALBYTES, "Blake2b personalizations have at most 16 bytes");
+	let mut params = blake2b_simd::Params::new();
+	params.hash_length(LENGTH).personal(personal);
+	params
+}
+
+impl Hasher for Blake2Hasher {
+	type Out = [u8; LENGTH];
+	type StdHasher = Hash256StdHasher;
+	const LENGTH: usize = LENGTH;
+
+	fn hash(x: &[u8]) -> Self::Out {
+		let mut out = [0; LENGTH];
+		out.copy_from_slice(blake2b_simd::Params::new().hash_length(LENGTH).hash(x).as_bytes());
+		out
+	}
+}
+
+/// State of a Blake2b-256 computation, enabled by the `triehash` feature.
+#[cfg(feature = "triehash")]
+pub struct Blake2Builder(blake2b_simd::State);
+
+#[cfg(feature = "triehash")]
+impl triehash::HashBuilder for Blake2Builder {
+	type Out = [u8; LENGTH];
+
+	fn update(&mut self, data: &[u8]) {
+		self.0.update(data);
+	}
+
+	fn finalize(self) -> [u8; LENGTH] {
+		let mut out = [0; LENGTH];
+		out.copy_from_slice(self.0.finalize().as_bytes());
+		out
+	}
+}
+
+#[cfg(feature = "triehash")]
+impl triehash::IncrementalHasher for Blake2Hasher {
+	type Builder = Blake2Builder;
+
+	fn builder() -> Blake2Builder {
+		Blake2Builder(blake2b_simd::Params::new().hash_length(LENGTH).to_state())
+	}
+}
+
+/// The key is the personalization of the hash, so it has at most 16 bytes.
+#[cfg(feature = "triehash")]
+impl triehash::KeyedHasher for Blake2Hasher {
+	fn keyed_builder(key: &[u8]) -> Blake2Builder {
+		Blake2Builder(params(key).to_state())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn blake2b_256_of_empty_input() {
+		assert_eq!(
+			Blake2Hasher::hash(&[]),
+			[
+				0x0e, 0x57, 0x51, 0xc0, 0x26, 0xe5, 0x43, 0xb2, 0xe8, 0xab, 0x2e, 0xb0, 0x60, 0x99, 0xda, 0xa1, 0xd1,
+				0xe5, 0xdf, 0x47, 0x77, 0x8f, 0x77, 0x87, 0xfa, 0xab, 0x45, 0xcd, 0xf1, 0x2f, 0xe3, 0xa8,
+			]
+		);
+	}
+
+	#[test]
+	fn personalized_blake2b_256() {
+		assert_eq!(
+			Blake2Hasher::hash_personalized(b"trie-node", b"reindeer"),
+			[
+				0xa6, 0xa2, 0xb6, 0x4d, 0x09, 0xf1, 0xe4, 0x39, 0x55, 0xc6, 0xdf, 0xcc, 0x68, 0xa7, 0x17, 0x97, 0xe5,
+				0x9a, 0x8f, 0x7e, 0xe6, 0x67, 0x77, 0x5d, 0x06, 0x8e, 0xae, 0xae, 0x73, 0x88, 0x46, 0xa0
+			]
+		);
+		assert_eq!(Blake2Hasher::hash_personalized(b"", b"reindeer"), Blake2Hasher::hash(b"reindeer"));
+	}
+
+	#[test]
+	#[should_panic(expected = "at most 16 bytes")]
+	fn rejects_long_personalizations() {
+		Blake2Hasher::hash_personalized(&[0; 17], b"reindeer");
+	}
+
+	#[cfg(feature = "triehash")]
+	#[test]
+	fn keyed_hashes_are_personalized() {
+		use triehash::{HashBuilder, HashDomain, IncrementalHasher, Keyed};
+
+		struct TrieNode;
+
+		impl HashDomain for TrieNode {
+			const KEY: &'static [u8] = b"trie-node";
+		}
+
+		assert_eq!(
+			Keyed::<Blake2Hasher, TrieNode>::hash(b"reindeer"),
+			Blake2Hasher::hash_personalized(b"trie-node", b"reindeer")
+		);
+		let mut builder = Blake2Hasher::builder();
+		builder.update(b"rein");
+		builder.update(b"deer");
+		assert_eq!(builder.finalize(), Blake2Hasher::hash(b"reindeer"));
+	}
+
+	#[test]
+	fn computes_trie_roots() {
+		let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+		let root = triehash::trie_root::<Blake2Hasher, _, _, _>(v.clone());
+		assert_eq!(root, Blake2Hasher::hash(&triehash::unhashed_trie::<Blake2Hasher, _, _, _>(v)));
+	}
+}
@@ -0,0 +1,90 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Hasher implementation for any `digest::Digest`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use digest::generic_array::typenum::Unsigned;
+use digest::{Digest, Output};
+use hash_db::Hasher;
+use plain_hasher::PlainHasher;
+
+/// `Hasher` computing hashes with the digest `D`, e.g. `DigestHasher<sha2::Sha512>`.
+///
+/// Hashes are `GenericArray`s of the output size of `D`.
+///
+/// ```
+/// use digest_hasher::DigestHasher;
+/// use hash_db::Hasher;
+/// use sha2::{Digest, Sha512};
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy")];
+/// let root = triehash::trie_root::<DigestHasher<Sha512>, _, _, _>(v.clone());
+/// assert_eq!(root.len(), 64);
+/// assert_eq!(root, Sha512::digest(&triehash::unhashed_trie::<DigestHasher<Sha512>, _, _, _>(v)));
+/// ```
+pub struct DigestHasher<D>(PhantomData<fn() -> D>);
+
+impl<D> Default for DigestHasher<D> {
+	fn default() -> Self {
+		DigestHasher(PhantomData)
+	}
+}
+
+impl<D> Clone for DigestHasher<D> {
+	fn clone(&self) -> Self {
+		DigestHasher(PhantomData)
+	}
+}
+
+impl<D> fmt::Debug for DigestHasher<D> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str("DigestHasher")
+	}
+}
+
+impl<D> PartialEq for DigestHasher<D> {
+	fn eq(&self, _: &Self) -> bool {
+		true
+	}
+}
+
+impl<D: Digest> Hasher for DigestHasher<D>
+where
+	Output<D>: Copy,
+{
+	type Out = Output<D>;
+	type StdHasher = PlainHasher;
+	const LENGTH: usize = D::OutputSize::USIZE;
+
+	fn hash(x: &[u8]) -> Self::Out {
+		D::digest(x)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::DigestHasher;
+	use hash_db::Hasher;
+	use sha256_hasher::Sha256Hasher;
+
+	#[test]
+	fn matches_dedicated_hasher() {
+		type Sha256 = DigestHasher<sha2::Sha256>;
+		assert_eq!(Sha256::LENGTH, 32);
+		assert_eq!(Sha256::hash(b"reindeer")[..], Sha256Hasher::hash(b"reindeer")[..]);
+
+		let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+		let root = triehash::trie_root::<Sha256, _, _, _>(v.clone());
+		assert_eq!(root[..], triehash::trie_root::<Sha256Hasher, _, _, _>(v)[..]);
+	}
+}
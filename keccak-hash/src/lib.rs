@@ -12,8 +12,14 @@
 use std::io;
 
 pub use primitive_types::H256;
+#[cfg(feature = "asm")]
+use sponge::{Hasher, Keccak};
+#[cfg(not(feature = "asm"))]
 use tiny_keccak::{Hasher, Keccak};
 
+#[cfg(feature = "asm")]
+mod sponge;
+
 /// Get the KECCAK (i.e. Keccak) hash of the empty bytes string.
 pub const KECCAK_EMPTY: H256 = H256([
 	0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7, 0x03, 0xc0, 0xe5, 0x00, 0xb6,
@@ -119,6 +125,49 @@ pub fn write_keccak<T: AsRef<[u8]>>(s: T, dest: &mut [u8]) {
 	keccak256.finalize(dest);
 }
 
+/// Concrete `hash_db::Hasher` impl for the Keccak-256 hash, with the hashes of
+/// `keccak_hasher::KeccakHasher`.
+///
+/// It hashes with the backend of the crate, so with the `asm` feature trie roots computed with
+/// it, e.g. by `triehash::trie_root::<keccak_hash::KeccakHasher, _, _, _>`, use the assembly
+/// Keccak-f[1600] of the `keccak` crate. That is only faster on aarch64 CPUs with the SHA3
+/// extension, other targets run a portable implementation about as fast as `tiny-keccak`.
+#[cfg(feature = "hasher")]
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct KeccakHasher;
+
+#[cfg(feature = "hasher")]
+impl hash_db::Hasher for KeccakHasher {
+	type Out = H256;
+	type StdHasher = plain_hasher::PlainHasher;
+	const LENGTH: usize = 32;
+
+	fn hash(x: &[u8]) -> Self::Out {
+		keccak(x)
+	}
+}
+
+/// Concrete `hash_db::Hasher` impl for the Keccak-512 hash, with 64-byte output.
+///
+/// Tries hashed with it inline the nodes shorter than the 64-byte hash into their parents,
+/// unlike the tries hashed with Keccak-256, which inline the nodes shorter than 32 bytes.
+#[cfg(feature = "hasher")]
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Keccak512Hasher;
+
+#[cfg(feature = "hasher")]
+impl hash_db::Hasher for Keccak512Hasher {
+	type Out = primitive_types::H512;
+	type StdHasher = plain_hasher::PlainHasher;
+	const LENGTH: usize = 64;
+
+	fn hash(x: &[u8]) -> Self::Out {
+		let mut out = primitive_types::H512::zero();
+		keccak_512(x, out.as_bytes_mut());
+		out
+	}
+}
+
 #[cfg(feature = "std")]
 pub fn keccak_pipe(r: &mut dyn io::BufRead, w: &mut dyn io::Write) -> Result<H256, io::Error> {
 	let mut output = [0u8; 32];
@@ -182,6 +231,60 @@ mod tests {
 		assert_eq!(dest, expected.as_ref());
 	}
 
+	#[cfg(all(feature = "std", feature = "hasher"))]
+	#[test]
+	fn keccak_hasher() {
+		use hash_db::Hasher;
+
+		assert_eq!(KeccakHasher::hash(&[]), KECCAK_EMPTY);
+
+		let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+		assert_eq!(
+			format!("{:x}", triehash::trie_root::<KeccakHasher, _, _, _>(v)),
+			"8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3",
+		);
+	}
+
+	#[cfg(all(feature = "std", feature = "hasher"))]
+	#[test]
+	fn keccak512_hasher() {
+		use hash_db::Hasher;
+
+		assert_eq!(
+			format!("{:x}", Keccak512Hasher::hash(&[])),
+			"0eab42de4c3ceb9235fc91acffe746b29c29a8c366b7c60e4e67c466f36a4304c00fa9caf9d87976ba469bcbe06713b435f091ef2769fb160cdab33d3670680e",
+		);
+
+		let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+		let root = triehash::trie_root::<Keccak512Hasher, _, _, _>(v.clone());
+		assert_eq!(root, Keccak512Hasher::hash(&triehash::unhashed_trie::<Keccak512Hasher, _, _, _>(v)));
+	}
+
+	#[cfg(all(feature = "std", feature = "hasher"))]
+	#[test]
+	fn keccak512_tries_inline_nodes_shorter_than_64_bytes() {
+		use hash_db::Hasher;
+
+		// leaves of 43 bytes, inlined into the branch rather than referenced by hash
+		let leaf = |value: u8| {
+			let mut leaf = vec![0xea, 0x30, 0xa8];
+			leaf.extend_from_slice(&[value; 40]);
+			leaf
+		};
+		let mut branch = vec![0xf8, 0x65, 0x80];
+		branch.extend_from_slice(&leaf(1));
+		branch.extend_from_slice(&leaf(2));
+		branch.extend_from_slice(&[0x80; 14]);
+
+		let v = vec![([0x10u8], [1u8; 40]), ([0x20], [2; 40])];
+		let root = triehash::trie_root::<Keccak512Hasher, _, _, _>(v);
+		assert_eq!(root, Keccak512Hasher::hash(&branch));
+		assert_eq!(
+			format!("{:x}", root),
+			"e58bb74c16cd1e18351bda12ff23ec5fac9c75f3d331a62b8b72e66089b6c5b8b7eda69860015cf2e59d8c6b440e2eb15dc699ef57683863b341033d63db8674",
+		);
+	}
+
 	#[cfg(feature = "std")]
 	#[test]
 	fn should_keccak_a_file() {
@@ -0,0 +1,133 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Keccak sponge on the Keccak-f[1600] permutation of the `keccak` crate, which has optimized
+//! backends (e.g. ARMv8 assembly), with the API of `tiny_keccak::Keccak`.
+
+/// Keccak sponge, absorbing the input and squeezing any number of output bytes.
+pub struct Keccak {
+	state: [u64; 25],
+	/// Bytes absorbed (or squeezed) per permutation.
+	rate: usize,
+	/// Position in the current block, in bytes.
+	offset: usize,
+}
+
+/// Hashing API of `tiny_keccak`.
+pub trait Hasher {
+	/// Absorbs `input`.
+	fn update(&mut self, input: &[u8]);
+
+	/// Pads the input and fills `output` with the hash.
+	fn finalize(self, output: &mut [u8]);
+}
+
+impl Keccak {
+	/// Keccak with 256 bits of output.
+	pub fn v256() -> Keccak {
+		Keccak { state: [0; 25], rate: 136, offset: 0 }
+	}
+
+	/// Keccak with 512 bits of output.
+	pub fn v512() -> Keccak {
+		Keccak { state: [0; 25], rate: 72, offset: 0 }
+	}
+
+	/// XORs `byte` into the state at byte position `pos`.
+	fn xor_byte(&mut self, pos: usize, byte: u8) {
+		self.state[pos / 8] ^= u64::from(byte) << (8 * (pos % 8));
+	}
+}
+
+impl Hasher for Keccak {
+	fn update(&mut self, mut input: &[u8]) {
+		while !input.is_empty() {
+			let len = core::cmp::min(self.rate - self.offset, input.len());
+			let (block, rest) = input.split_at(len);
+			if self.offset & 7 == 0 {
+				let lanes = block.chunks_exact(8);
+				let tail = lanes.remainder();
+				let first = self.offset / 8;
+				for (lane, bytes) in lanes.enumerate() {
+					let mut word = [0; 8];
+					word.copy_from_slice(bytes);
+					self.state[first + lane] ^= u64::from_le_bytes(word);
+				}
+				let start = self.offset + len - tail.len();
+				for (i, &byte) in tail.iter().enumerate() {
+					self.xor_byte(start + i, byte);
+				}
+			} else {
+				for (i, &byte) in block.iter().enumerate() {
+					self.xor_byte(self.offset + i, byte);
+				}
+			}
+			self.offset += len;
+			if self.offset == self.rate {
+				keccak::f1600(&mut self.state);
+				self.offset = 0;
+			}
+			input = rest;
+		}
+	}
+
+	fn finalize(mut self, output: &mut [u8]) {
+		self.xor_byte(self.offset, 0x01);
+		self.xor_byte(self.rate - 1, 0x80);
+		keccak::f1600(&mut self.state);
+
+		for (i, chunk) in output.chunks_mut(self.rate).enumerate() {
+			if i > 0 {
+				keccak::f1600(&mut self.state);
+			}
+			for (pos, byte) in chunk.iter_mut().enumerate() {
+				*byte = (self.state[pos / 8] >> (8 * (pos % 8))) as u8;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Hasher, Keccak};
+
+	/// Hashes `input` in parts of `part` bytes, into `len` bytes of output with both sponges.
+	fn both(v256: bool, input: &[u8], part: usize, len: usize) -> ([u8; 300], [u8; 300]) {
+		let (mut ours, mut theirs) = if v256 {
+			(Keccak::v256(), tiny_keccak::Keccak::v256())
+		} else {
+			(Keccak::v512(), tiny_keccak::Keccak::v512())
+		};
+		for part in input.chunks(part) {
+			ours.update(part);
+			tiny_keccak::Hasher::update(&mut theirs, part);
+		}
+		let (mut a, mut b) = ([0; 300], [0; 300]);
+		ours.finalize(&mut a[..len]);
+		tiny_keccak::Hasher::finalize(theirs, &mut b[..len]);
+		(a, b)
+	}
+
+	#[test]
+	fn matches_tiny_keccak() {
+		let mut input = [0u8; 500];
+		for (i, byte) in input.iter_mut().enumerate() {
+			*byte = (i * 7) as u8;
+		}
+		for &v256 in &[true, false] {
+			for &len in &[0, 1, 71, 72, 135, 136, 137, 500] {
+				for &part in &[1, 3, 8, 136, 500] {
+					for &out in &[32, 64, 300] {
+						let (a, b) = both(v256, &input[..len], part, out);
+						assert_eq!(&a[..], &b[..], "v256 {} len {} part {} out {}", v256, len, part, out);
+					}
+				}
+			}
+		}
+	}
+}
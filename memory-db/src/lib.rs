@@ -0,0 +1,614 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reference-counted in-memory `HashDB`.
+
+use hash_db::{AsHashDB, HashDB, HashDBRef, Hasher, Prefix};
+use kvdb::DBTransaction;
+use parity_util_mem::{MallocShallowSizeOf, MallocSizeOf, MallocSizeOfOps};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::mem;
+
+mod map;
+mod overlay;
+mod plain;
+mod sync;
+
+pub use map::MemoryMap;
+pub use overlay::OverlayDB;
+pub use plain::PlainMemoryDB;
+pub use sync::SyncMemoryDB;
+
+/// `HashDB` whose entries can be enumerated, e.g. by debugging tools looking for nodes left
+/// behind after pruning.
+pub trait HashDBIter<H: Hasher, T>: HashDB<H, T> {
+	/// Calls `f` with the key, value and reference count of every entry, including those whose
+	/// count isn't positive.
+	fn for_each(&self, f: &mut dyn FnMut(&H::Out, &T, i32));
+
+	/// Keys of all entries with their reference counts.
+	fn keys(&self) -> HashMap<H::Out, i32> {
+		let mut keys = HashMap::new();
+		self.for_each(&mut |key, _, rc| {
+			keys.insert(*key, rc);
+		});
+		keys
+	}
+}
+
+/// `HashDB` exposing the reference counts of its entries, e.g. the backing database of
+/// `OverlayDB`, which adds its changes to them.
+pub trait HashDBRaw<H: Hasher, T>: HashDB<H, T> {
+	/// Value and reference count of `key`, even if the count isn't positive.
+	fn get_raw(&self, key: &H::Out, prefix: Prefix) -> Option<(T, i32)>;
+}
+
+/// `HashDB` keeping its values in memory, keyed by their hash.
+///
+/// Every value has a reference count: inserting a value increments it and removing the value
+/// decrements it, possibly below zero. A value is only visible while its count is positive, so
+/// removals can be recorded before the matching insertions, e.g. when the database is an overlay
+/// of changes to be applied to a persistent database. Entries with a zero count are kept until
+/// `purge` is called.
+///
+/// Prefixes are ignored: values are only keyed by their hash.
+///
+/// Changes made after a `checkpoint` are journaled, so they can be undone by `revert_to`.
+/// Checkpoints nest, e.g. for the state changes of nested calls.
+///
+/// Entries are stored in a `MemoryMap`, a `HashMap` by default. A `BTreeMap` enumerates them in
+/// the order of their keys, e.g. for deterministic output, and a `HashMap` with
+/// `plain_hasher::PlainHasher` avoids rehashing keys which are already hashes.
+///
+/// ```
+/// use hash_db::{HashDB, EMPTY_PREFIX};
+/// use keccak_hasher::KeccakHasher;
+/// use memory_db::MemoryDB;
+///
+/// let mut db = MemoryDB::<KeccakHasher>::default();
+/// let key = db.insert(EMPTY_PREFIX, b"reindeer");
+/// assert_eq!(db.get(&key, EMPTY_PREFIX), Some(b"reindeer".to_vec()));
+///
+/// db.remove(&key, EMPTY_PREFIX);
+/// assert!(!db.contains(&key, EMPTY_PREFIX));
+/// db.remove(&key, EMPTY_PREFIX);
+/// assert_eq!(db.raw(&key), Some((&b"reindeer".to_vec(), -1)));
+/// ```
+///
+/// With another map:
+///
+/// ```
+/// use hash_db::{HashDB, EMPTY_PREFIX};
+/// use keccak_hasher::KeccakHasher;
+/// use memory_db::{HashDBIter, MemoryDB};
+/// use std::collections::BTreeMap;
+///
+/// let mut db = MemoryDB::<KeccakHasher, BTreeMap<_, _>>::default();
+/// db.insert(EMPTY_PREFIX, b"reindeer");
+/// db.insert(EMPTY_PREFIX, b"puppy");
+///
+/// let mut keys = Vec::new();
+/// db.for_each(&mut |key, _, _| keys.push(*key));
+/// assert!(keys[0] < keys[1]);
+/// ```
+pub struct MemoryDB<H: Hasher, M = HashMap<<H as Hasher>::Out, (Vec<u8>, i32)>> {
+	data: M,
+	/// Changes since the first open checkpoint.
+	journal: Vec<JournalEntry<H::Out>>,
+	/// Id of each open checkpoint, with the length of the journal when it was taken.
+	checkpoints: Vec<(u64, usize)>,
+	/// Id of the next checkpoint.
+	next_checkpoint: u64,
+	_hasher: PhantomData<fn() -> H>,
+}
+
+/// Change to an entry of `MemoryDB`, recorded to be undone by `MemoryDB::revert_to`.
+#[derive(Clone)]
+enum JournalEntry<K> {
+	/// Reference count of `key` incremented, replacing `value` if it wasn't positive.
+	Emplaced { key: K, created: bool, replaced: Option<Vec<u8>> },
+	/// Reference count of `key` decremented.
+	Removed { key: K, created: bool },
+}
+
+/// Point `MemoryDB` can be reverted to, returned by `MemoryDB::checkpoint`.
+///
+/// Checkpoints have unique ids, so a checkpoint which has been closed isn't mistaken for one
+/// taken later at the same depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+	/// Position in the open checkpoints.
+	index: usize,
+	id: u64,
+}
+
+impl<H: Hasher, M: MemoryMap<H::Out, (Vec<u8>, i32)>> Default for MemoryDB<H, M> {
+	fn default() -> Self {
+		MemoryDB {
+			data: M::default(),
+			journal: Vec::new(),
+			checkpoints: Vec::new(),
+			next_checkpoint: 0,
+			_hasher: PhantomData,
+		}
+	}
+}
+
+impl<H: Hasher, M: Clone> Clone for MemoryDB<H, M> {
+	fn clone(&self) -> Self {
+		MemoryDB {
+			data: self.data.clone(),
+			journal: self.journal.clone(),
+			checkpoints: self.checkpoints.clone(),
+			next_checkpoint: self.next_checkpoint,
+			_hasher: PhantomData,
+		}
+	}
+}
+
+impl<H: Hasher, M: MemoryMap<H::Out, (Vec<u8>, i32)>> MemoryDB<H, M> {
+	/// Creates an empty database.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Value and reference count of `key`, even if the count isn't positive.
+	pub fn raw(&self, key: &H::Out) -> Option<(&Vec<u8>, i32)> {
+		self.data.get(key).map(|(value, rc)| (value, *rc))
+	}
+
+	/// Number of entries, including those whose reference count isn't positive.
+	pub fn len(&self) -> usize {
+		self.data.len()
+	}
+
+	/// Returns true if there are no entries.
+	pub fn is_empty(&self) -> bool {
+		self.data.is_empty()
+	}
+
+	/// Removes all entries and open checkpoints.
+	pub fn clear(&mut self) {
+		self.data.clear();
+		self.discard_checkpoints();
+	}
+
+	/// Starts journaling changes, so they can be undone with `revert_to`.
+	///
+	/// ```
+	/// use hash_db::{HashDB, EMPTY_PREFIX};
+	/// use keccak_hasher::KeccakHasher;
+	/// use memory_db::MemoryDB;
+	///
+	/// let mut db = MemoryDB::<KeccakHasher>::new();
+	/// let doe = db.insert(EMPTY_PREFIX, b"doe");
+	///
+	/// let call = db.checkpoint();
+	/// let dog = db.insert(EMPTY_PREFIX, b"dog");
+	/// let nested_call = db.checkpoint();
+	/// db.remove(&doe, EMPTY_PREFIX);
+	/// db.revert_to(nested_call);
+	/// assert!(db.contains(&doe, EMPTY_PREFIX));
+	///
+	/// db.revert_to(call);
+	/// assert!(!db.contains(&dog, EMPTY_PREFIX));
+	/// ```
+	pub fn checkpoint(&mut self) -> Checkpoint {
+		let checkpoint = Checkpoint { index: self.checkpoints.len(), id: self.next_checkpoint };
+		self.next_checkpoint += 1;
+		self.checkpoints.push((checkpoint.id, self.journal.len()));
+		checkpoint
+	}
+
+	/// Closes `checkpoint` and the checkpoints taken after it, returning the length of the
+	/// journal when it was taken.
+	fn close(&mut self, checkpoint: Checkpoint) -> usize {
+		match self.checkpoints.get(checkpoint.index) {
+			Some(&(id, start)) if id == checkpoint.id => {
+				self.checkpoints.truncate(checkpoint.index);
+				start
+			}
+			_ => panic!("checkpoint is no longer open"),
+		}
+	}
+
+	/// Undoes the changes since `checkpoint` was taken, and closes it and the checkpoints taken
+	/// after it.
+	///
+	/// # Panics
+	///
+	/// If `checkpoint` is no longer open.
+	pub fn revert_to(&mut self, checkpoint: Checkpoint) {
+		let start = self.close(checkpoint);
+		for entry in self.journal.drain(start..).rev() {
+			let (key, created, rc, replaced) = match entry {
+				JournalEntry::Emplaced { key, created, replaced } => (key, created, -1, replaced),
+				JournalEntry::Removed { key, created } => (key, created, 1, None),
+			};
+			if created {
+				self.data.remove(&key);
+			} else if let Some((value, old_rc)) = self.data.get_mut(&key) {
+				*old_rc += rc;
+				if let Some(replaced) = replaced {
+					*value = replaced;
+				}
+			}
+		}
+	}
+
+	/// Closes `checkpoint` and the checkpoints taken after it, keeping their changes. They are
+	/// still undone when an earlier checkpoint is reverted to.
+	///
+	/// # Panics
+	///
+	/// If `checkpoint` is no longer open.
+	pub fn commit(&mut self, checkpoint: Checkpoint) {
+		self.close(checkpoint);
+		if self.checkpoints.is_empty() {
+			self.journal.clear();
+		}
+	}
+
+	/// Closes all checkpoints, keeping the changes.
+	fn discard_checkpoints(&mut self) {
+		self.checkpoints.clear();
+		self.journal.clear();
+	}
+
+	/// Heap memory used by the entries, in bytes.
+	pub fn mem_used(&self) -> usize {
+		parity_util_mem::malloc_size(self)
+	}
+
+	/// Shrinks the capacity of the map of entries as much as possible, e.g. after `purge`.
+	pub fn shrink_to_fit(&mut self) {
+		self.data.shrink_to_fit();
+	}
+
+	/// Removes the entries whose reference count is zero, and closes all checkpoints.
+	pub fn purge(&mut self) {
+		self.data.retain(|_, (_, rc)| *rc != 0);
+		self.discard_checkpoints();
+	}
+
+	/// Removes and returns all entries with their reference counts, and closes all checkpoints.
+	pub fn drain(&mut self) -> M {
+		self.discard_checkpoints();
+		mem::take(&mut self.data)
+	}
+
+	/// Removes all entries and adds them to `transaction`, in `column`: values with a positive
+	/// reference count are written, and keys with a negative count are deleted.
+	///
+	/// Reference counts are not written, so the resulting transaction suits backing databases
+	/// which store every value at most once.
+	///
+	/// ```
+	/// use hash_db::{HashDB, EMPTY_PREFIX};
+	/// use keccak_hasher::KeccakHasher;
+	/// use kvdb::{DBTransaction, KeyValueDB};
+	/// use memory_db::MemoryDB;
+	///
+	/// let backing = kvdb_memorydb::create(1);
+	/// let mut db = MemoryDB::<KeccakHasher>::new();
+	/// let key = db.insert(EMPTY_PREFIX, b"reindeer");
+	///
+	/// let mut transaction = DBTransaction::new();
+	/// db.drain_into(&mut transaction, 0);
+	/// backing.write(transaction).unwrap();
+	/// assert_eq!(backing.get(0, &key).unwrap(), Some(b"reindeer".to_vec()));
+	/// assert!(db.is_empty());
+	/// ```
+	pub fn drain_into(&mut self, transaction: &mut DBTransaction, column: u32) {
+		for (key, (value, rc)) in self.drain() {
+			if rc > 0 {
+				transaction.put_vec(column, key.as_ref(), value);
+			} else if rc < 0 {
+				transaction.delete(column, key.as_ref());
+			}
+		}
+	}
+
+	/// Adds the entries of `other` to this database, summing the reference counts of the
+	/// values in both, and closes all checkpoints.
+	pub fn consolidate(&mut self, mut other: Self) {
+		self.discard_checkpoints();
+		for (key, (value, rc)) in other.drain() {
+			match self.data.get_mut(&key) {
+				Some((old_value, old_rc)) => {
+					// a removal recorded before the insertion holds no value
+					if *old_rc < 0 {
+						*old_value = value;
+					}
+					*old_rc += rc;
+				}
+				None => {
+					self.data.insert(key, (value, rc));
+				}
+			}
+		}
+	}
+}
+
+impl<H: Hasher, M: MemoryMap<H::Out, (Vec<u8>, i32)>> MallocSizeOf for MemoryDB<H, M> {
+	fn size_of(&self, ops: &mut MallocSizeOfOps) -> usize {
+		// keys and reference counts are stored inline
+		let mut size = self.data.shallow_size_of(ops);
+		self.data.for_each(|_, (value, _)| size += value.size_of(ops));
+		size += self.journal.shallow_size_of(ops) + self.checkpoints.shallow_size_of(ops);
+		for entry in &self.journal {
+			if let JournalEntry::Emplaced { replaced: Some(value), .. } = entry {
+				size += value.size_of(ops);
+			}
+		}
+		size
+	}
+}
+
+impl<H: Hasher, M: MemoryMap<H::Out, (Vec<u8>, i32)>> HashDB<H, Vec<u8>> for MemoryDB<H, M> {
+	fn get(&self, key: &H::Out, _prefix: Prefix) -> Option<Vec<u8>> {
+		match self.data.get(key) {
+			Some((value, rc)) if *rc > 0 => Some(value.clone()),
+			_ => None,
+		}
+	}
+
+	fn contains(&self, key: &H::Out, _prefix: Prefix) -> bool {
+		matches!(self.data.get(key), Some((_, rc)) if *rc > 0)
+	}
+
+	fn insert(&mut self, prefix: Prefix, value: &[u8]) -> H::Out {
+		let key = H::hash(value);
+		self.emplace(key, prefix, value.to_vec());
+		key
+	}
+
+	fn emplace(&mut self, key: H::Out, _prefix: Prefix, value: Vec<u8>) {
+		let journaled = !self.checkpoints.is_empty();
+		let entry = match self.data.get_mut(&key) {
+			Some((old_value, rc)) => {
+				let replaced = if *rc <= 0 { Some(mem::replace(old_value, value)) } else { None };
+				*rc += 1;
+				JournalEntry::Emplaced { key, created: false, replaced: replaced.filter(|_| journaled) }
+			}
+			None => {
+				self.data.insert(key, (value, 1));
+				JournalEntry::Emplaced { key, created: true, replaced: None }
+			}
+		};
+		if journaled {
+			self.journal.push(entry);
+		}
+	}
+
+	fn remove(&mut self, key: &H::Out, _prefix: Prefix) {
+		let created = match self.data.get_mut(key) {
+			Some((_, rc)) => {
+				*rc -= 1;
+				false
+			}
+			None => {
+				self.data.insert(*key, (Vec::new(), -1));
+				true
+			}
+		};
+		if !self.checkpoints.is_empty() {
+			self.journal.push(JournalEntry::Removed { key: *key, created });
+		}
+	}
+}
+
+impl<H: Hasher, M: MemoryMap<H::Out, (Vec<u8>, i32)>> HashDBIter<H, Vec<u8>> for MemoryDB<H, M> {
+	fn for_each(&self, f: &mut dyn FnMut(&H::Out, &Vec<u8>, i32)) {
+		self.data.for_each(|key, (value, rc)| f(key, value, *rc));
+	}
+}
+
+impl<H: Hasher, M: MemoryMap<H::Out, (Vec<u8>, i32)>> HashDBRaw<H, Vec<u8>> for MemoryDB<H, M> {
+	fn get_raw(&self, key: &H::Out, _prefix: Prefix) -> Option<(Vec<u8>, i32)> {
+		self.raw(key).map(|(value, rc)| (value.clone(), rc))
+	}
+}
+
+impl<H: Hasher, M: MemoryMap<H::Out, (Vec<u8>, i32)>> HashDBRef<H, Vec<u8>> for MemoryDB<H, M> {
+	fn get(&self, key: &H::Out, prefix: Prefix) -> Option<Vec<u8>> {
+		HashDB::get(self, key, prefix)
+	}
+
+	fn contains(&self, key: &H::Out, prefix: Prefix) -> bool {
+		HashDB::contains(self, key, prefix)
+	}
+}
+
+impl<H: Hasher, M: MemoryMap<H::Out, (Vec<u8>, i32)>> AsHashDB<H, Vec<u8>> for MemoryDB<H, M> {
+	fn as_hash_db(&self) -> &dyn HashDB<H, Vec<u8>> {
+		self
+	}
+
+	fn as_hash_db_mut<'a>(&'a mut self) -> &'a mut (dyn HashDB<H, Vec<u8>> + 'a) {
+		self
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use hash_db::EMPTY_PREFIX;
+	use keccak_hasher::KeccakHasher;
+	use plain_hasher::PlainHasher;
+	use std::collections::BTreeMap;
+	use std::hash::BuildHasherDefault;
+
+	#[test]
+	fn counts_references() {
+		let mut db = MemoryDB::<KeccakHasher>::new();
+		let key = db.insert(EMPTY_PREFIX, b"doe");
+		db.insert(EMPTY_PREFIX, b"doe");
+		db.remove(&key, EMPTY_PREFIX);
+		assert_eq!(HashDB::get(&db, &key, EMPTY_PREFIX), Some(b"doe".to_vec()));
+		db.remove(&key, EMPTY_PREFIX);
+		assert!(!HashDB::contains(&db, &key, EMPTY_PREFIX));
+
+		assert_eq!(db.len(), 1);
+		db.purge();
+		assert!(db.is_empty());
+	}
+
+	#[test]
+	fn insertion_after_removal_restores_the_value() {
+		let mut db = MemoryDB::<KeccakHasher>::new();
+		let key = KeccakHasher::hash(b"doe");
+		db.remove(&key, EMPTY_PREFIX);
+		db.insert(EMPTY_PREFIX, b"doe");
+		assert_eq!(db.raw(&key), Some((&b"doe".to_vec(), 0)));
+		db.insert(EMPTY_PREFIX, b"doe");
+		assert_eq!(HashDB::get(&db, &key, EMPTY_PREFIX), Some(b"doe".to_vec()));
+	}
+
+	#[test]
+	fn consolidates_reference_counts() {
+		let mut removals = MemoryDB::<KeccakHasher>::new();
+		let key = KeccakHasher::hash(b"doe");
+		removals.remove(&key, EMPTY_PREFIX);
+
+		let mut db = MemoryDB::<KeccakHasher>::new();
+		db.insert(EMPTY_PREFIX, b"doe");
+		db.insert(EMPTY_PREFIX, b"dog");
+		removals.consolidate(db.clone());
+		assert_eq!(removals.raw(&key), Some((&b"doe".to_vec(), 0)));
+		assert!(HashDB::contains(&removals, &KeccakHasher::hash(b"dog"), EMPTY_PREFIX));
+
+		db.consolidate(removals);
+		assert_eq!(db.raw(&key), Some((&b"doe".to_vec(), 1)));
+	}
+
+	#[test]
+	fn reports_memory_of_values() {
+		let mut db = MemoryDB::<KeccakHasher>::new();
+		let empty = db.mem_used();
+		let key = db.insert(EMPTY_PREFIX, &[0; 1024]);
+		let used = db.mem_used();
+		assert!(used >= empty + 1024);
+
+		db.remove(&key, EMPTY_PREFIX);
+		db.purge();
+		db.shrink_to_fit();
+		assert!(db.mem_used() < used);
+	}
+
+	#[test]
+	fn drains_insertions_and_deletions() {
+		let mut db = MemoryDB::<KeccakHasher>::new();
+		let inserted = db.insert(EMPTY_PREFIX, b"doe");
+		let unchanged = db.insert(EMPTY_PREFIX, b"dog");
+		db.remove(&unchanged, EMPTY_PREFIX);
+		let deleted = KeccakHasher::hash(b"cat");
+		db.remove(&deleted, EMPTY_PREFIX);
+
+		let mut transaction = DBTransaction::new();
+		db.drain_into(&mut transaction, 3);
+		let mut ops = transaction.ops.iter().map(|op| (op.col(), op.key().to_vec())).collect::<Vec<_>>();
+		ops.sort();
+		let mut expected = vec![(3, inserted.to_vec()), (3, deleted.to_vec())];
+		expected.sort();
+		assert_eq!(ops, expected);
+		assert!(db.is_empty());
+	}
+
+	#[test]
+	fn lists_keys_with_reference_counts() {
+		let mut db = MemoryDB::<KeccakHasher>::new();
+		let doe = db.insert(EMPTY_PREFIX, b"doe");
+		let dog = db.insert(EMPTY_PREFIX, b"dog");
+		db.remove(&dog, EMPTY_PREFIX);
+		let keys = db.keys();
+		assert_eq!(keys.len(), 2);
+		assert_eq!((keys[&doe], keys[&dog]), (1, 0));
+	}
+
+	#[test]
+	fn reverts_to_checkpoints() {
+		let mut db = MemoryDB::<KeccakHasher>::new();
+		let doe = db.insert(EMPTY_PREFIX, b"doe");
+		let dog = KeccakHasher::hash(b"dog");
+		db.remove(&dog, EMPTY_PREFIX);
+		db.remove(&doe, EMPTY_PREFIX);
+
+		let checkpoint = db.checkpoint();
+		db.emplace(doe, EMPTY_PREFIX, b"doe".to_vec());
+		db.insert(EMPTY_PREFIX, b"dog");
+		db.insert(EMPTY_PREFIX, b"cat");
+		let nested = db.checkpoint();
+		db.remove(&doe, EMPTY_PREFIX);
+		db.remove(&KeccakHasher::hash(b"cow"), EMPTY_PREFIX);
+		db.commit(nested);
+		assert_eq!(db.raw(&dog), Some((&b"dog".to_vec(), 0)));
+		assert_eq!(db.len(), 4);
+
+		db.revert_to(checkpoint);
+		assert_eq!(db.raw(&doe), Some((&b"doe".to_vec(), 0)));
+		assert_eq!(db.raw(&dog), Some((&Vec::new(), -1)));
+		assert_eq!(db.len(), 2);
+
+		// changes are no longer journaled once all checkpoints are closed
+		let checkpoint = db.checkpoint();
+		db.insert(EMPTY_PREFIX, b"cat");
+		db.commit(checkpoint);
+		let checkpoint = db.checkpoint();
+		db.revert_to(checkpoint);
+		assert_eq!(db.len(), 3);
+	}
+
+	#[test]
+	#[should_panic(expected = "checkpoint is no longer open")]
+	fn rejects_closed_checkpoints() {
+		let mut db = MemoryDB::<KeccakHasher>::new();
+		let stale = db.checkpoint();
+		db.commit(stale);
+
+		// the new checkpoint is at the same depth as the closed one
+		db.checkpoint();
+		db.insert(EMPTY_PREFIX, b"doe");
+		db.revert_to(stale);
+	}
+
+	#[test]
+	fn supports_other_maps() {
+		type PlainHashMap<K, V> = HashMap<K, V, BuildHasherDefault<PlainHasher>>;
+
+		fn check<M: MemoryMap<[u8; 32], (Vec<u8>, i32)>>() {
+			let mut db = MemoryDB::<KeccakHasher, M>::default();
+			let key = db.insert(EMPTY_PREFIX, b"doe");
+			db.remove(&key, EMPTY_PREFIX);
+			db.remove(&KeccakHasher::hash(b"dog"), EMPTY_PREFIX);
+			assert_eq!(db.keys().len(), 2);
+			assert!(db.mem_used() > 0);
+
+			db.purge();
+			db.shrink_to_fit();
+			assert_eq!(db.len(), 1);
+			assert!(db.drain().into_iter().all(|(_, (_, rc))| rc == -1));
+		}
+
+		check::<PlainHashMap<_, _>>();
+		check::<hashbrown::HashMap<_, _>>();
+		check::<BTreeMap<_, _>>();
+	}
+
+	#[test]
+	fn stores_trie_nodes() {
+		for input in triehash::test_support::test_inputs::<KeccakHasher>() {
+			let mut db = MemoryDB::<KeccakHasher>::new();
+			let root = triehash::trie_root_into_db::<KeccakHasher, _, _, _, _>(input.clone(), &mut db);
+			for (key, value) in input.into_iter().collect::<BTreeMap<_, _>>() {
+				assert!(triehash::verify_proof_in_db::<KeccakHasher, _>(&root, &key, Some(&value), &db));
+			}
+		}
+	}
+}
@@ -0,0 +1,183 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Maps holding the entries of `MemoryDB`.
+
+use parity_util_mem::{MallocShallowSizeOf, MallocSizeOfOps};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+
+/// Map from keys to values, used by `MemoryDB` to store its entries.
+///
+/// Maps are `Send` and `Sync`, as `HashDB` requires.
+///
+/// Implemented for `std::collections::HashMap` with any `BuildHasher`, e.g.
+/// `BuildHasherDefault<plain_hasher::PlainHasher>` for keys which are already hashes, for
+/// `hashbrown::HashMap` and for `BTreeMap`, which iterates in the order of the keys.
+pub trait MemoryMap<K, V>: Default + IntoIterator<Item = (K, V)> + Send + Sync {
+	/// Value stored under `key`.
+	fn get(&self, key: &K) -> Option<&V>;
+
+	/// Mutable reference to the value stored under `key`.
+	fn get_mut(&mut self, key: &K) -> Option<&mut V>;
+
+	/// Stores `value` under `key`, returning the value it replaces.
+	fn insert(&mut self, key: K, value: V) -> Option<V>;
+
+	/// Removes `key`, returning its value.
+	fn remove(&mut self, key: &K) -> Option<V>;
+
+	/// Number of entries.
+	fn len(&self) -> usize;
+
+	/// Returns true if there are no entries.
+	fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Removes all entries.
+	fn clear(&mut self);
+
+	/// Keeps only the entries for which `f` returns true.
+	fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, f: F);
+
+	/// Calls `f` with every entry.
+	fn for_each<F: FnMut(&K, &V)>(&self, f: F);
+
+	/// Releases unused capacity, if the map preallocates any. Does nothing by default.
+	fn shrink_to_fit(&mut self) {}
+
+	/// Heap memory used by the map itself, excluding memory owned by its keys and values.
+	fn shallow_size_of(&self, ops: &mut MallocSizeOfOps) -> usize;
+}
+
+impl<K: Hash + Eq + Send + Sync, V: Send + Sync, S: BuildHasher + Default + Send + Sync> MemoryMap<K, V>
+	for HashMap<K, V, S>
+{
+	fn get(&self, key: &K) -> Option<&V> {
+		HashMap::get(self, key)
+	}
+
+	fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+		HashMap::get_mut(self, key)
+	}
+
+	fn insert(&mut self, key: K, value: V) -> Option<V> {
+		HashMap::insert(self, key, value)
+	}
+
+	fn remove(&mut self, key: &K) -> Option<V> {
+		HashMap::remove(self, key)
+	}
+
+	fn len(&self) -> usize {
+		HashMap::len(self)
+	}
+
+	fn clear(&mut self) {
+		HashMap::clear(self)
+	}
+
+	fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+		HashMap::retain(self, |key, value| f(key, value))
+	}
+
+	fn for_each<F: FnMut(&K, &V)>(&self, mut f: F) {
+		self.iter().for_each(|(key, value)| f(key, value))
+	}
+
+	fn shrink_to_fit(&mut self) {
+		HashMap::shrink_to_fit(self)
+	}
+
+	fn shallow_size_of(&self, ops: &mut MallocSizeOfOps) -> usize {
+		MallocShallowSizeOf::shallow_size_of(self, ops)
+	}
+}
+
+impl<K: Hash + Eq + Send + Sync, V: Send + Sync, S: BuildHasher + Default + Send + Sync> MemoryMap<K, V>
+	for hashbrown::HashMap<K, V, S>
+{
+	fn get(&self, key: &K) -> Option<&V> {
+		hashbrown::HashMap::get(self, key)
+	}
+
+	fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+		hashbrown::HashMap::get_mut(self, key)
+	}
+
+	fn insert(&mut self, key: K, value: V) -> Option<V> {
+		hashbrown::HashMap::insert(self, key, value)
+	}
+
+	fn remove(&mut self, key: &K) -> Option<V> {
+		hashbrown::HashMap::remove(self, key)
+	}
+
+	fn len(&self) -> usize {
+		hashbrown::HashMap::len(self)
+	}
+
+	fn clear(&mut self) {
+		hashbrown::HashMap::clear(self)
+	}
+
+	fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+		hashbrown::HashMap::retain(self, |key, value| f(key, value))
+	}
+
+	fn for_each<F: FnMut(&K, &V)>(&self, mut f: F) {
+		self.iter().for_each(|(key, value)| f(key, value))
+	}
+
+	fn shrink_to_fit(&mut self) {
+		hashbrown::HashMap::shrink_to_fit(self)
+	}
+
+	fn shallow_size_of(&self, ops: &mut MallocSizeOfOps) -> usize {
+		MallocShallowSizeOf::shallow_size_of(self, ops)
+	}
+}
+
+impl<K: Ord + Send + Sync, V: Send + Sync> MemoryMap<K, V> for BTreeMap<K, V> {
+	fn get(&self, key: &K) -> Option<&V> {
+		BTreeMap::get(self, key)
+	}
+
+	fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+		BTreeMap::get_mut(self, key)
+	}
+
+	fn insert(&mut self, key: K, value: V) -> Option<V> {
+		BTreeMap::insert(self, key, value)
+	}
+
+	fn remove(&mut self, key: &K) -> Option<V> {
+		BTreeMap::remove(self, key)
+	}
+
+	fn len(&self) -> usize {
+		BTreeMap::len(self)
+	}
+
+	fn clear(&mut self) {
+		BTreeMap::clear(self)
+	}
+
+	fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+		BTreeMap::retain(self, |key, value| f(key, value))
+	}
+
+	fn for_each<F: FnMut(&K, &V)>(&self, mut f: F) {
+		self.iter().for_each(|(key, value)| f(key, value))
+	}
+
+	fn shallow_size_of(&self, ops: &mut MallocSizeOfOps) -> usize {
+		MallocShallowSizeOf::shallow_size_of(self, ops)
+	}
+}
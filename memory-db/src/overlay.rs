@@ -0,0 +1,318 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Buffering changes to a `HashDB` in memory.
+
+use crate::{HashDBIter, HashDBRaw, MemoryDB};
+use hash_db::{AsHashDB, HashDB, HashDBRef, Hasher, Prefix};
+
+/// `HashDB` buffering insertions and removals in a `MemoryDB` over the backing database `B`,
+/// which is only written to by `commit`.
+///
+/// The reference count of a key is its count in the backing database plus the insertions and
+/// minus the removals since the last commit, and the key is present while that sum is positive.
+/// Like in `MemoryDB`, prefixes are not kept: changes are committed with an empty prefix.
+///
+/// Overlays created with `new` read the counts of the backing database with `HashDBRaw`, which
+/// only memory-db's databases implement. Any other `HashDB`, e.g. one stored in a key-value
+/// database, is read with `get` and `contains` by overlays created with `new_uncounted`, which
+/// take it to hold every key it contains once: a key removed more often than inserted since the
+/// last commit is absent, even if the backing database counts more references.
+///
+/// ```
+/// use hash_db::{HashDB, EMPTY_PREFIX};
+/// use keccak_hasher::KeccakHasher;
+/// use memory_db::{MemoryDB, OverlayDB};
+///
+/// let mut db = OverlayDB::new(MemoryDB::<KeccakHasher>::new());
+/// let key = db.insert(EMPTY_PREFIX, b"reindeer");
+/// assert!(db.contains(&key, EMPTY_PREFIX));
+/// db.revert();
+/// assert!(!db.contains(&key, EMPTY_PREFIX));
+///
+/// db.insert(EMPTY_PREFIX, b"reindeer");
+/// db.commit();
+/// assert!(db.backing().contains(&key, EMPTY_PREFIX));
+/// ```
+pub struct OverlayDB<H: Hasher, B> {
+	overlay: MemoryDB<H>,
+	backing: B,
+	/// `HashDBRaw::get_raw` of the backing database, unless it is read without reference counts.
+	get_raw: Option<GetRaw<H, B>>,
+}
+
+/// Reads the value and reference count of a key from the backing database `B`.
+type GetRaw<H, B> = fn(&B, &<H as Hasher>::Out, Prefix) -> Option<(Vec<u8>, i32)>;
+
+impl<H: Hasher, B: HashDBRaw<H, Vec<u8>>> OverlayDB<H, B> {
+	/// Creates an overlay without changes over `backing`, adding the changes to the reference
+	/// counts of `backing`.
+	pub fn new(backing: B) -> Self {
+		OverlayDB { overlay: MemoryDB::new(), backing, get_raw: Some(B::get_raw) }
+	}
+}
+
+impl<H: Hasher, B: HashDB<H, Vec<u8>>> OverlayDB<H, B> {
+	/// Creates an overlay without changes over `backing`, which is taken to hold every key it
+	/// contains once.
+	pub fn new_uncounted(backing: B) -> Self {
+		OverlayDB { overlay: MemoryDB::new(), backing, get_raw: None }
+	}
+
+	/// The backing database.
+	pub fn backing(&self) -> &B {
+		&self.backing
+	}
+
+	/// The changes since the last commit.
+	pub fn overlay(&self) -> &MemoryDB<H> {
+		&self.overlay
+	}
+
+	/// Applies the changes to the backing database, repeating every insertion or removal as
+	/// many times as the reference count records.
+	pub fn commit(&mut self) {
+		for (key, (value, rc)) in self.overlay.drain() {
+			if rc > 0 {
+				for _ in 0..rc {
+					self.backing.emplace(key, hash_db::EMPTY_PREFIX, value.clone());
+				}
+			} else {
+				for _ in rc..0 {
+					self.backing.remove(&key, hash_db::EMPTY_PREFIX);
+				}
+			}
+		}
+	}
+
+	/// Value and reference count of `key` with the changes since the last commit applied, given
+	/// the `get_raw` of the backing database.
+	fn entry(&self, get_raw: GetRaw<H, B>, key: &H::Out, prefix: Prefix) -> Option<(Vec<u8>, i32)> {
+		let backing = get_raw(&self.backing, key, prefix);
+		match (self.overlay.raw(key), backing) {
+			(None, backing) => backing,
+			(Some((value, rc)), None) => Some((value.clone(), rc)),
+			// a removal recorded before any insertion holds no value
+			(Some((value, rc)), Some((backing_value, backing_rc))) => {
+				Some((if rc > 0 { value.clone() } else { backing_value }, rc + backing_rc))
+			}
+		}
+	}
+
+	/// Discards the changes since the last commit.
+	pub fn revert(&mut self) {
+		self.overlay.clear();
+	}
+
+	/// Returns the backing database, discarding the changes since the last commit.
+	pub fn into_backing(self) -> B {
+		self.backing
+	}
+}
+
+impl<H: Hasher, B: HashDB<H, Vec<u8>>> HashDB<H, Vec<u8>> for OverlayDB<H, B> {
+	fn get(&self, key: &H::Out, prefix: Prefix) -> Option<Vec<u8>> {
+		match (self.overlay.raw(key), self.get_raw) {
+			(None, _) | (Some((_, 0)), _) => self.backing.get(key, prefix),
+			(Some(_), Some(get_raw)) => {
+				self.entry(get_raw, key, prefix).filter(|(_, rc)| *rc > 0).map(|(value, _)| value)
+			}
+			// the key is held once by the backing database
+			(Some((value, rc)), None) => Some(value.clone()).filter(|_| rc > 0),
+		}
+	}
+
+	fn contains(&self, key: &H::Out, prefix: Prefix) -> bool {
+		match (self.overlay.raw(key), self.get_raw) {
+			(None, _) | (Some((_, 0)), _) => self.backing.contains(key, prefix),
+			(Some(_), Some(get_raw)) => matches!(self.entry(get_raw, key, prefix), Some((_, rc)) if rc > 0),
+			(Some((_, rc)), None) => rc > 0,
+		}
+	}
+
+	fn insert(&mut self, prefix: Prefix, value: &[u8]) -> H::Out {
+		self.overlay.insert(prefix, value)
+	}
+
+	fn emplace(&mut self, key: H::Out, prefix: Prefix, value: Vec<u8>) {
+		self.overlay.emplace(key, prefix, value)
+	}
+
+	fn remove(&mut self, key: &H::Out, prefix: Prefix) {
+		self.overlay.remove(key, prefix)
+	}
+}
+
+/// Lists the entries of the backing database with the changes applied.
+impl<H: Hasher, B: HashDBIter<H, Vec<u8>> + HashDBRaw<H, Vec<u8>>> HashDBIter<H, Vec<u8>> for OverlayDB<H, B> {
+	fn for_each(&self, f: &mut dyn FnMut(&H::Out, &Vec<u8>, i32)) {
+		self.backing.for_each(&mut |key, value, rc| match self.overlay.raw(key) {
+			Some((changed, change)) => f(key, if rc > 0 { value } else { changed }, rc + change),
+			None => f(key, value, rc),
+		});
+		let backing = self.backing.keys();
+		self.overlay.for_each(&mut |key, value, rc| {
+			if !backing.contains_key(key) {
+				f(key, value, rc)
+			}
+		});
+	}
+}
+
+impl<H: Hasher, B: HashDBRaw<H, Vec<u8>>> HashDBRaw<H, Vec<u8>> for OverlayDB<H, B> {
+	fn get_raw(&self, key: &H::Out, prefix: Prefix) -> Option<(Vec<u8>, i32)> {
+		self.entry(B::get_raw, key, prefix)
+	}
+}
+
+impl<H: Hasher, B: HashDB<H, Vec<u8>>> HashDBRef<H, Vec<u8>> for OverlayDB<H, B> {
+	fn get(&self, key: &H::Out, prefix: Prefix) -> Option<Vec<u8>> {
+		HashDB::get(self, key, prefix)
+	}
+
+	fn contains(&self, key: &H::Out, prefix: Prefix) -> bool {
+		HashDB::contains(self, key, prefix)
+	}
+}
+
+impl<H: Hasher, B: HashDB<H, Vec<u8>>> AsHashDB<H, Vec<u8>> for OverlayDB<H, B> {
+	fn as_hash_db(&self) -> &dyn HashDB<H, Vec<u8>> {
+		self
+	}
+
+	fn as_hash_db_mut<'a>(&'a mut self) -> &'a mut (dyn HashDB<H, Vec<u8>> + 'a) {
+		self
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::OverlayDB;
+	use crate::{HashDBIter, HashDBRaw, MemoryDB};
+	use hash_db::{AsHashDB, HashDB, Hasher, Prefix, EMPTY_PREFIX};
+	use keccak_hasher::KeccakHasher;
+
+	/// `HashDB` without `HashDBRaw`, like one stored in a key-value database.
+	struct PlainDB(MemoryDB<KeccakHasher>);
+
+	impl HashDB<KeccakHasher, Vec<u8>> for PlainDB {
+		fn get(&self, key: &<KeccakHasher as Hasher>::Out, prefix: Prefix) -> Option<Vec<u8>> {
+			self.0.get(key, prefix)
+		}
+
+		fn contains(&self, key: &<KeccakHasher as Hasher>::Out, prefix: Prefix) -> bool {
+			self.0.contains(key, prefix)
+		}
+
+		fn insert(&mut self, prefix: Prefix, value: &[u8]) -> <KeccakHasher as Hasher>::Out {
+			self.0.insert(prefix, value)
+		}
+
+		fn emplace(&mut self, key: <KeccakHasher as Hasher>::Out, prefix: Prefix, value: Vec<u8>) {
+			self.0.emplace(key, prefix, value)
+		}
+
+		fn remove(&mut self, key: &<KeccakHasher as Hasher>::Out, prefix: Prefix) {
+			self.0.remove(key, prefix)
+		}
+	}
+
+	impl AsHashDB<KeccakHasher, Vec<u8>> for PlainDB {
+		fn as_hash_db(&self) -> &dyn HashDB<KeccakHasher, Vec<u8>> {
+			self
+		}
+
+		fn as_hash_db_mut<'a>(&'a mut self) -> &'a mut (dyn HashDB<KeccakHasher, Vec<u8>> + 'a) {
+			self
+		}
+	}
+
+	#[test]
+	fn removals_hide_backing_values_until_reverted() {
+		let mut backing = MemoryDB::<KeccakHasher>::new();
+		let key = backing.insert(EMPTY_PREFIX, b"doe");
+		let mut db = OverlayDB::new(backing);
+		assert_eq!(db.get(&key, EMPTY_PREFIX), Some(b"doe".to_vec()));
+
+		db.remove(&key, EMPTY_PREFIX);
+		assert!(!db.contains(&key, EMPTY_PREFIX));
+		db.revert();
+		assert!(db.contains(&key, EMPTY_PREFIX));
+
+		db.remove(&key, EMPTY_PREFIX);
+		db.commit();
+		assert!(db.overlay().is_empty());
+		assert!(!db.backing().contains(&key, EMPTY_PREFIX));
+	}
+
+	#[test]
+	fn removals_are_added_to_backing_reference_counts() {
+		let mut backing = MemoryDB::<KeccakHasher>::new();
+		let key = backing.insert(EMPTY_PREFIX, b"doe");
+		backing.insert(EMPTY_PREFIX, b"doe");
+		let mut db = OverlayDB::new(backing);
+
+		// the node is still referenced once
+		db.remove(&key, EMPTY_PREFIX);
+		assert_eq!(db.get(&key, EMPTY_PREFIX), Some(b"doe".to_vec()));
+		assert!(db.contains(&key, EMPTY_PREFIX));
+
+		db.remove(&key, EMPTY_PREFIX);
+		assert!(!db.contains(&key, EMPTY_PREFIX));
+		db.insert(EMPTY_PREFIX, b"doe");
+		assert_eq!(db.get_raw(&key, EMPTY_PREFIX), Some((b"doe".to_vec(), 1)));
+
+		db.commit();
+		assert_eq!(db.backing().raw(&key), Some((&b"doe".to_vec(), 1)));
+	}
+
+	#[test]
+	fn commits_reference_counts() {
+		let mut db = OverlayDB::new(MemoryDB::<KeccakHasher>::new());
+		let key = db.insert(EMPTY_PREFIX, b"doe");
+		db.insert(EMPTY_PREFIX, b"doe");
+		db.commit();
+		assert_eq!(db.backing().raw(&key), Some((&b"doe".to_vec(), 2)));
+		assert_eq!(db.into_backing().len(), 1);
+	}
+
+	#[test]
+	fn lists_keys_with_changes_applied() {
+		let mut backing = MemoryDB::<KeccakHasher>::new();
+		let doe = backing.insert(EMPTY_PREFIX, b"doe");
+		let mut db = OverlayDB::new(backing);
+		db.remove(&doe, EMPTY_PREFIX);
+		let dog = db.insert(EMPTY_PREFIX, b"dog");
+		let keys = db.keys();
+		assert_eq!(keys.len(), 2);
+		assert_eq!((keys[&doe], keys[&dog]), (0, 1));
+	}
+
+	#[test]
+	fn reads_backings_without_reference_counts() {
+		let mut backing = MemoryDB::<KeccakHasher>::new();
+		let doe = backing.insert(EMPTY_PREFIX, b"doe");
+		backing.insert(EMPTY_PREFIX, b"doe");
+		let mut db = OverlayDB::new_uncounted(PlainDB(backing));
+		assert_eq!(db.get(&doe, EMPTY_PREFIX), Some(b"doe".to_vec()));
+
+		// the node is taken to be referenced once
+		db.remove(&doe, EMPTY_PREFIX);
+		assert!(!db.contains(&doe, EMPTY_PREFIX));
+		assert_eq!(db.get(&doe, EMPTY_PREFIX), None);
+		db.insert(EMPTY_PREFIX, b"doe");
+		assert_eq!(db.get(&doe, EMPTY_PREFIX), Some(b"doe".to_vec()));
+
+		let dog = db.insert(EMPTY_PREFIX, b"dog");
+		assert!(db.contains(&dog, EMPTY_PREFIX));
+		assert!(!db.backing().contains(&dog, EMPTY_PREFIX));
+		db.commit();
+		assert_eq!(db.backing().get(&dog, EMPTY_PREFIX), Some(b"dog".to_vec()));
+		assert_eq!(db.into_backing().0.raw(&doe), Some((&b"doe".to_vec(), 2)));
+	}
+}
@@ -0,0 +1,155 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reference-counted in-memory `PlainDB`.
+
+use hash_db::{AsPlainDB, PlainDB, PlainDBRef};
+use std::collections::{hash_map::Entry, HashMap};
+use std::hash::Hash;
+
+/// `PlainDB` keeping its values in memory under arbitrary keys, with the same reference
+/// counting as `MemoryDB`.
+///
+/// ```
+/// use hash_db::PlainDB;
+/// use memory_db::PlainMemoryDB;
+///
+/// let mut db = PlainMemoryDB::<Vec<u8>, Vec<u8>>::default();
+/// db.emplace(b"doe".to_vec(), b"reindeer".to_vec());
+/// assert_eq!(db.get(&b"doe".to_vec()), Some(b"reindeer".to_vec()));
+///
+/// db.remove(&b"doe".to_vec());
+/// assert!(!db.contains(&b"doe".to_vec()));
+/// ```
+#[derive(Clone)]
+pub struct PlainMemoryDB<K, V> {
+	data: HashMap<K, (V, i32)>,
+}
+
+impl<K: Hash + Eq, V> Default for PlainMemoryDB<K, V> {
+	fn default() -> Self {
+		PlainMemoryDB { data: HashMap::default() }
+	}
+}
+
+impl<K: Hash + Eq, V> PlainMemoryDB<K, V> {
+	/// Creates an empty database.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Value and reference count of `key`, even if the count isn't positive.
+	pub fn raw(&self, key: &K) -> Option<(&V, i32)> {
+		self.data.get(key).map(|(value, rc)| (value, *rc))
+	}
+
+	/// Number of entries, including those whose reference count isn't positive.
+	pub fn len(&self) -> usize {
+		self.data.len()
+	}
+
+	/// Returns true if there are no entries.
+	pub fn is_empty(&self) -> bool {
+		self.data.is_empty()
+	}
+
+	/// Removes the entries whose reference count is zero.
+	pub fn purge(&mut self) {
+		self.data.retain(|_, (_, rc)| *rc != 0);
+	}
+}
+
+impl<K, V> PlainDB<K, V> for PlainMemoryDB<K, V>
+where
+	K: Hash + Eq + Clone + Send + Sync,
+	V: Default + Clone + Send + Sync,
+{
+	fn get(&self, key: &K) -> Option<V> {
+		match self.data.get(key) {
+			Some((value, rc)) if *rc > 0 => Some(value.clone()),
+			_ => None,
+		}
+	}
+
+	fn contains(&self, key: &K) -> bool {
+		matches!(self.data.get(key), Some((_, rc)) if *rc > 0)
+	}
+
+	fn emplace(&mut self, key: K, value: V) {
+		match self.data.entry(key) {
+			Entry::Occupied(mut entry) => {
+				let (old_value, rc) = entry.get_mut();
+				if *rc <= 0 {
+					*old_value = value;
+				}
+				*rc += 1;
+			}
+			Entry::Vacant(entry) => {
+				entry.insert((value, 1));
+			}
+		}
+	}
+
+	fn remove(&mut self, key: &K) {
+		match self.data.entry(key.clone()) {
+			Entry::Occupied(mut entry) => entry.get_mut().1 -= 1,
+			Entry::Vacant(entry) => {
+				entry.insert((V::default(), -1));
+			}
+		}
+	}
+}
+
+impl<K, V> PlainDBRef<K, V> for PlainMemoryDB<K, V>
+where
+	K: Hash + Eq + Clone + Send + Sync,
+	V: Default + Clone + Send + Sync,
+{
+	fn get(&self, key: &K) -> Option<V> {
+		PlainDB::get(self, key)
+	}
+
+	fn contains(&self, key: &K) -> bool {
+		PlainDB::contains(self, key)
+	}
+}
+
+impl<K, V> AsPlainDB<K, V> for PlainMemoryDB<K, V>
+where
+	K: Hash + Eq + Clone + Send + Sync,
+	V: Default + Clone + Send + Sync,
+{
+	fn as_plain_db(&self) -> &dyn PlainDB<K, V> {
+		self
+	}
+
+	fn as_plain_db_mut<'a>(&'a mut self) -> &'a mut (dyn PlainDB<K, V> + 'a) {
+		self
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::PlainMemoryDB;
+	use hash_db::PlainDB;
+
+	#[test]
+	fn counts_references() {
+		let mut db = PlainMemoryDB::<&str, u32>::new();
+		db.remove(&"doe");
+		db.emplace("doe", 1);
+		assert_eq!(db.raw(&"doe"), Some((&1, 0)));
+		assert!(!db.contains(&"doe"));
+
+		db.emplace("doe", 2);
+		assert_eq!(db.get(&"doe"), Some(2));
+		db.remove(&"doe");
+		db.purge();
+		assert!(db.is_empty());
+	}
+}
@@ -0,0 +1,230 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `MemoryDB` shared between threads.
+
+use crate::{HashDBIter, HashDBRaw, MemoryDB};
+use hash_db::{AsHashDB, HashDB, HashDBRef, Hasher, Prefix};
+use parking_lot::RwLock;
+
+/// Number of shards of `SyncMemoryDB::default`.
+const DEFAULT_SHARDS: usize = 16;
+
+/// `MemoryDB` which can be written to through a shared reference, e.g. by threads building
+/// tries in parallel.
+///
+/// Entries are split into shards by the leading bytes of their key, each shard being a
+/// `MemoryDB` behind its own lock, so threads only contend when they access the same shard.
+/// The inherent methods mirror `HashDB` but take `&self`.
+///
+/// ```
+/// use hash_db::EMPTY_PREFIX;
+/// use keccak_hasher::KeccakHasher;
+/// use memory_db::SyncMemoryDB;
+/// use std::{sync::Arc, thread};
+///
+/// let db = Arc::new(SyncMemoryDB::<KeccakHasher>::default());
+/// let threads = (0..4u8)
+/// 	.map(|i| {
+/// 		let db = db.clone();
+/// 		thread::spawn(move || db.insert(EMPTY_PREFIX, &[i]))
+/// 	})
+/// 	.collect::<Vec<_>>();
+/// for thread in threads {
+/// 	let key = thread.join().unwrap();
+/// 	assert!(db.contains(&key, EMPTY_PREFIX));
+/// }
+/// assert_eq!(db.len(), 4);
+/// ```
+pub struct SyncMemoryDB<H: Hasher> {
+	shards: Vec<RwLock<MemoryDB<H>>>,
+}
+
+impl<H: Hasher> Default for SyncMemoryDB<H> {
+	fn default() -> Self {
+		Self::new(DEFAULT_SHARDS)
+	}
+}
+
+impl<H: Hasher> SyncMemoryDB<H> {
+	/// Creates an empty database of `shards` shards.
+	///
+	/// # Panics
+	///
+	/// If `shards` is zero.
+	pub fn new(shards: usize) -> Self {
+		assert!(shards > 0, "SyncMemoryDB needs at least one shard");
+		SyncMemoryDB { shards: (0..shards).map(|_| RwLock::new(MemoryDB::new())).collect() }
+	}
+
+	/// Shard holding `key`.
+	fn shard(&self, key: &H::Out) -> &RwLock<MemoryDB<H>> {
+		let prefix = key.as_ref().iter().take(8).fold(0u64, |prefix, byte| prefix << 8 | u64::from(*byte));
+		&self.shards[(prefix % self.shards.len() as u64) as usize]
+	}
+
+	/// Value of `key`, if its reference count is positive.
+	pub fn get(&self, key: &H::Out, prefix: Prefix) -> Option<Vec<u8>> {
+		HashDB::get(&*self.shard(key).read(), key, prefix)
+	}
+
+	/// Returns true if the reference count of `key` is positive.
+	pub fn contains(&self, key: &H::Out, prefix: Prefix) -> bool {
+		HashDB::contains(&*self.shard(key).read(), key, prefix)
+	}
+
+	/// Inserts `value` and returns its hash, see `HashDB::insert`.
+	pub fn insert(&self, prefix: Prefix, value: &[u8]) -> H::Out {
+		let key = H::hash(value);
+		self.emplace(key, prefix, value.to_vec());
+		key
+	}
+
+	/// Inserts `value` under `key`, see `HashDB::emplace`.
+	pub fn emplace(&self, key: H::Out, prefix: Prefix, value: Vec<u8>) {
+		self.shard(&key).write().emplace(key, prefix, value)
+	}
+
+	/// Decrements the reference count of `key`, see `HashDB::remove`.
+	pub fn remove(&self, key: &H::Out, prefix: Prefix) {
+		self.shard(key).write().remove(key, prefix)
+	}
+
+	/// Number of entries, including those whose reference count isn't positive.
+	pub fn len(&self) -> usize {
+		self.shards.iter().map(|shard| shard.read().len()).sum()
+	}
+
+	/// Returns true if there are no entries.
+	pub fn is_empty(&self) -> bool {
+		self.shards.iter().all(|shard| shard.read().is_empty())
+	}
+
+	/// Heap memory used by the entries, in bytes.
+	pub fn mem_used(&self) -> usize {
+		self.shards.iter().map(|shard| shard.read().mem_used()).sum()
+	}
+
+	/// Removes the entries whose reference count is zero.
+	pub fn purge(&self) {
+		for shard in &self.shards {
+			shard.write().purge();
+		}
+	}
+
+	/// Merges the shards into a single `MemoryDB`.
+	pub fn into_memory_db(self) -> MemoryDB<H> {
+		let mut db = MemoryDB::new();
+		for shard in self.shards {
+			db.consolidate(shard.into_inner());
+		}
+		db
+	}
+}
+
+impl<H: Hasher> HashDB<H, Vec<u8>> for SyncMemoryDB<H> {
+	fn get(&self, key: &H::Out, prefix: Prefix) -> Option<Vec<u8>> {
+		SyncMemoryDB::get(self, key, prefix)
+	}
+
+	fn contains(&self, key: &H::Out, prefix: Prefix) -> bool {
+		SyncMemoryDB::contains(self, key, prefix)
+	}
+
+	fn insert(&mut self, prefix: Prefix, value: &[u8]) -> H::Out {
+		SyncMemoryDB::insert(self, prefix, value)
+	}
+
+	fn emplace(&mut self, key: H::Out, prefix: Prefix, value: Vec<u8>) {
+		SyncMemoryDB::emplace(self, key, prefix, value)
+	}
+
+	fn remove(&mut self, key: &H::Out, prefix: Prefix) {
+		SyncMemoryDB::remove(self, key, prefix)
+	}
+}
+
+impl<H: Hasher> HashDBIter<H, Vec<u8>> for SyncMemoryDB<H> {
+	fn for_each(&self, f: &mut dyn FnMut(&H::Out, &Vec<u8>, i32)) {
+		for shard in &self.shards {
+			shard.read().for_each(f);
+		}
+	}
+}
+
+impl<H: Hasher> HashDBRaw<H, Vec<u8>> for SyncMemoryDB<H> {
+	fn get_raw(&self, key: &H::Out, prefix: Prefix) -> Option<(Vec<u8>, i32)> {
+		self.shard(key).read().get_raw(key, prefix)
+	}
+}
+
+impl<H: Hasher> HashDBRef<H, Vec<u8>> for SyncMemoryDB<H> {
+	fn get(&self, key: &H::Out, prefix: Prefix) -> Option<Vec<u8>> {
+		SyncMemoryDB::get(self, key, prefix)
+	}
+
+	fn contains(&self, key: &H::Out, prefix: Prefix) -> bool {
+		SyncMemoryDB::contains(self, key, prefix)
+	}
+}
+
+impl<H: Hasher> AsHashDB<H, Vec<u8>> for SyncMemoryDB<H> {
+	fn as_hash_db(&self) -> &dyn HashDB<H, Vec<u8>> {
+		self
+	}
+
+	fn as_hash_db_mut<'a>(&'a mut self) -> &'a mut (dyn HashDB<H, Vec<u8>> + 'a) {
+		self
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::SyncMemoryDB;
+	use hash_db::{HashDB, Hasher, EMPTY_PREFIX};
+	use keccak_hasher::KeccakHasher;
+	use std::{collections::BTreeMap, sync::Arc, thread};
+
+	#[test]
+	fn shares_entries_between_threads() {
+		let db = Arc::new(SyncMemoryDB::<KeccakHasher>::new(3));
+		let threads = (0..4u32)
+			.map(|t| {
+				let db = db.clone();
+				thread::spawn(move || {
+					for i in 0..100u32 {
+						db.insert(EMPTY_PREFIX, &i.to_be_bytes());
+						if t == 0 {
+							db.remove(&KeccakHasher::hash(&(i + 100).to_be_bytes()), EMPTY_PREFIX);
+						}
+					}
+				})
+			})
+			.collect::<Vec<_>>();
+		for thread in threads {
+			thread.join().unwrap();
+		}
+
+		let db = Arc::try_unwrap(db).ok().unwrap();
+		assert_eq!(db.len(), 200);
+		let db = db.into_memory_db();
+		assert_eq!(db.raw(&KeccakHasher::hash(&7u32.to_be_bytes())), Some((&7u32.to_be_bytes().to_vec(), 4)));
+		assert!(!HashDB::contains(&db, &KeccakHasher::hash(&107u32.to_be_bytes()), EMPTY_PREFIX));
+	}
+
+	#[test]
+	fn stores_trie_nodes() {
+		for input in triehash::test_support::test_inputs::<KeccakHasher>() {
+			let mut db = SyncMemoryDB::<KeccakHasher>::default();
+			let root = triehash::trie_root_into_db::<KeccakHasher, _, _, _, _>(input.clone(), &mut db);
+			for (key, value) in input.into_iter().collect::<BTreeMap<_, _>>() {
+				assert!(triehash::verify_proof_in_db::<KeccakHasher, _>(&root, &key, Some(&value), &db));
+			}
+		}
+	}
+}
@@ -12,8 +12,10 @@ use core::hash::Hasher;
 
 use crunchy::unroll;
 
-/// Hasher that just takes 8 bytes of the provided value.
-/// May only be used for keys which are 32 bytes.
+/// Hasher that just folds the provided value into 8 bytes, by XOR-ing every byte into the byte
+/// at its position modulo 8.
+/// Meant for keys which are already hashes, of any length (e.g. 20, 32 or 64 bytes); 32-byte
+/// chunks take a fast path.
 #[derive(Default)]
 pub struct PlainHasher {
 	prefix: u64,
@@ -27,14 +29,21 @@ impl Hasher for PlainHasher {
 
 	#[inline]
 	fn write(&mut self, bytes: &[u8]) {
-		debug_assert!(bytes.len() == 32);
 		let mut prefix_bytes = self.prefix.to_le_bytes();
 
-		unroll! {
-			for i in 0..8 {
-				prefix_bytes[i] ^= (bytes[i] ^ bytes[i + 8]) ^ (bytes[i + 16] ^ bytes[i + 24]);
+		let chunks = bytes.chunks_exact(32);
+		let rest = chunks.remainder();
+		for bytes in chunks {
+			unroll! {
+				for i in 0..8 {
+					prefix_bytes[i] ^= (bytes[i] ^ bytes[i + 8]) ^ (bytes[i + 16] ^ bytes[i + 24]);
+				}
 			}
 		}
+		// chunks are a multiple of 8 bytes long, so the rest starts at position 0 modulo 8
+		for (i, byte) in rest.iter().enumerate() {
+			prefix_bytes[i % 8] ^= byte;
+		}
 
 		self.prefix = u64::from_le_bytes(prefix_bytes);
 	}
@@ -52,4 +61,38 @@ mod tests {
 		hasher.write(&bytes);
 		assert_eq!(hasher.prefix, 47);
 	}
+
+	#[test]
+	fn folds_64_byte_keys() {
+		let mut bytes = [32u8; 64];
+		bytes[0] = 15;
+		bytes[32] = 1;
+		let mut hasher = PlainHasher::default();
+		hasher.write(&bytes);
+		assert_eq!(hasher.prefix, 47 ^ 33);
+	}
+
+	#[test]
+	fn mixes_all_bytes_of_any_length() {
+		for &len in &[8, 16, 20, 32, 64, 65] {
+			let mut all = [0u8; 65];
+			for (i, byte) in all.iter_mut().enumerate() {
+				*byte = i as u8;
+			}
+			let bytes = &all[..len];
+			let mut expected = [0u8; 8];
+			for (i, byte) in bytes.iter().enumerate() {
+				expected[i % 8] ^= byte;
+			}
+			let mut hasher = PlainHasher::default();
+			hasher.write(bytes);
+			assert_eq!(hasher.finish(), u64::from_le_bytes(expected), "{} bytes", len);
+
+			// the last byte changes the hash
+			all[len - 1] ^= 1;
+			let mut other = PlainHasher::default();
+			other.write(&all[..len]);
+			assert_ne!(other.finish(), hasher.finish());
+		}
+	}
 }
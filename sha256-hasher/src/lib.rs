@@ -0,0 +1,102 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Hasher implementation for the SHA-256 hash.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use hash256_std_hasher::Hash256StdHasher;
+use hash_db::Hasher;
+use sha2::{Digest, Sha256};
+
+/// Length of the hashes, in bytes.
+const LENGTH: usize = 32;
+
+/// Concrete `Hasher` impl for the SHA-256 hash.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Sha256Hasher;
+
+impl Hasher for Sha256Hasher {
+	type Out = [u8; LENGTH];
+	type StdHasher = Hash256StdHasher;
+	const LENGTH: usize = LENGTH;
+
+	fn hash(x: &[u8]) -> Self::Out {
+		Sha256::digest(x).into()
+	}
+}
+
+/// State of a SHA-256 computation, enabled by the `triehash` feature.
+#[cfg(feature = "triehash")]
+pub struct Sha256Builder(Sha256);
+
+#[cfg(feature = "triehash")]
+impl triehash::HashBuilder for Sha256Builder {
+	type Out = [u8; LENGTH];
+
+	fn update(&mut self, data: &[u8]) {
+		self.0.update(data);
+	}
+
+	fn finalize(self) -> [u8; LENGTH] {
+		self.0.finalize().into()
+	}
+}
+
+#[cfg(feature = "triehash")]
+impl triehash::IncrementalHasher for Sha256Hasher {
+	type Builder = Sha256Builder;
+
+	fn builder() -> Sha256Builder {
+		Sha256Builder(Sha256::new())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sha256_of_empty_input() {
+		assert_eq!(
+			Sha256Hasher::hash(&[]),
+			[
+				0xe3, 0xb0, 0xc4, 0x42, 0x98, 0xfc, 0x1c, 0x14, 0x9a, 0xfb, 0xf4, 0xc8, 0x99, 0x6f, 0xb9, 0x24, 0x27,
+				0xae, 0x41, 0xe4, 0x64, 0x9b, 0x93, 0x4c, 0xa4, 0x95, 0x99, 0x1b, 0x78, 0x52, 0xb8, 0x55,
+			]
+		);
+	}
+
+	#[test]
+	fn computes_trie_roots() {
+		let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+		let root = triehash::trie_root::<Sha256Hasher, _, _, _>(v.clone());
+		assert_eq!(root, Sha256Hasher::hash(&triehash::unhashed_trie::<Sha256Hasher, _, _, _>(v)));
+	}
+
+	#[cfg(feature = "triehash")]
+	#[test]
+	fn computes_ssz_roots() {
+		use triehash::{ssz_root, IncrementalHasher, SszLimits};
+
+		assert_eq!(
+			Sha256Hasher::hash_parts([&b"doe"[..], b"reindeer"].iter().copied()),
+			Sha256Hasher::hash(b"doereindeer")
+		);
+
+		let limits = SszLimits { key_bytes: 32, value_bytes: 64, items: 4 };
+		let root = ssz_root::<Sha256Hasher, _, _, _>(vec![("dog", "puppy"), ("doe", "reindeer")], limits);
+		assert_eq!(
+			root,
+			[
+				0x8c, 0x73, 0x16, 0x7e, 0x8a, 0xa5, 0xd0, 0x85, 0xc3, 0x6b, 0xe6, 0x48, 0x78, 0x30, 0xb2, 0x2f, 0x70,
+				0x11, 0x82, 0x6d, 0x21, 0x90, 0xd6, 0xc7, 0x85, 0x6e, 0x8b, 0xae, 0x44, 0xaf, 0xc2, 0x87,
+			]
+		);
+	}
+}
@@ -0,0 +1,59 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Hasher implementation for the SHA3-256 hash.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use hash256_std_hasher::Hash256StdHasher;
+use hash_db::Hasher;
+use tiny_keccak::{Hasher as _, Sha3};
+
+/// Length of the hashes, in bytes.
+const LENGTH: usize = 32;
+
+/// Concrete `Hasher` impl for the SHA3-256 hash (FIPS 202, not Keccak-256).
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Sha3Hasher;
+
+impl Hasher for Sha3Hasher {
+	type Out = [u8; LENGTH];
+	type StdHasher = Hash256StdHasher;
+	const LENGTH: usize = LENGTH;
+
+	fn hash(x: &[u8]) -> Self::Out {
+		let mut out = [0; LENGTH];
+		let mut sha3 = Sha3::v256();
+		sha3.update(x);
+		sha3.finalize(&mut out);
+		out
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sha3_256_of_empty_input() {
+		assert_eq!(
+			Sha3Hasher::hash(&[]),
+			[
+				0xa7, 0xff, 0xc6, 0xf8, 0xbf, 0x1e, 0xd7, 0x66, 0x51, 0xc1, 0x47, 0x56, 0xa0, 0x61, 0xd6, 0x62, 0xf5,
+				0x80, 0xff, 0x4d, 0xe4, 0x3b, 0x49, 0xfa, 0x82, 0xd8, 0x0a, 0x4b, 0x80, 0xf8, 0x43, 0x4a,
+			]
+		);
+	}
+
+	#[test]
+	fn computes_trie_roots() {
+		let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+		let root = triehash::trie_root::<Sha3Hasher, _, _, _>(v.clone());
+		assert_eq!(root, Sha3Hasher::hash(&triehash::unhashed_trie::<Sha3Hasher, _, _, _>(v)));
+	}
+}
@@ -0,0 +1,149 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Node encoding of a binary trie, in the style of EIP-3102.
+
+use core::convert::TryFrom;
+
+use crate::stream::{TrieStream, Value};
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use hash_db::Hasher;
+
+/// Length of the hashes referencing children.
+const HASH_LENGTH: usize = 32;
+
+/// Appends the number of bits of a prefix, followed by the bits packed most significant first.
+///
+/// # Panics
+///
+/// If the prefix has more bits than fit into a `u16`, i.e. it is part of a key of over 8191 bytes.
+fn append_prefix(out: &mut Vec<u8>, bits: &[u8]) {
+	let len = u16::try_from(bits.len()).expect("keys of binary tries have at most 8191 bytes");
+	out.extend_from_slice(&len.to_be_bytes());
+	out.extend(bits.chunks(8).map(|byte| byte.iter().enumerate().fold(0, |acc, (i, bit)| acc | bit << (7 - i))));
+}
+
+/// Node encoding of a binary trie, for keys split into bits (`Radix::Binary`).
+///
+/// Every node is encoded as `(prefix, left, right, value)`: the length of its prefix in bits as
+/// a big-endian `u16` followed by the packed bits, the 32 byte hashes of its children, and the
+/// value. Missing children are all-zero hashes, leaves have two of them. There are no extension
+/// nodes, their prefix is stored in the node below, and children are never inlined. The empty
+/// trie is encoded as no bytes at all.
+///
+/// Prefixes longer than a `u16` can count, i.e. keys of over 8191 bytes, are rejected with a panic.
+#[derive(Default)]
+pub struct BinaryTrieStream {
+	buffer: Vec<u8>,
+	/// Prefix of a pending extension, stored in the node appended next.
+	extension: Option<Vec<u8>>,
+}
+
+impl TrieStream for BinaryTrieStream {
+	fn new() -> Self {
+		Self::default()
+	}
+
+	fn clear(&mut self) {
+		self.buffer.clear();
+		self.extension = None;
+	}
+
+	fn append_empty_data(&mut self) {}
+
+	fn append_leaf(&mut self, partial: &[u8], value: Value) {
+		append_prefix(&mut self.buffer, partial);
+		self.buffer.extend_from_slice(&[0; 2 * HASH_LENGTH]);
+		self.buffer.extend_from_slice(value.as_bytes());
+	}
+
+	fn begin_extension(&mut self, partial: &[u8]) {
+		self.extension = Some(partial.to_vec());
+	}
+
+	fn begin_branch(&mut self, _has_value: bool, _children: impl Iterator<Item = bool>) {
+		append_prefix(&mut self.buffer, &[]);
+	}
+
+	fn begin_branch_with_arity(&mut self, arity: usize, has_value: bool, children: impl Iterator<Item = bool>) {
+		debug_assert_eq!(arity, 2, "binary tries have two children per branch");
+		self.begin_branch(has_value, children);
+	}
+
+	fn append_empty_child(&mut self) {
+		self.buffer.extend_from_slice(&[0; HASH_LENGTH]);
+	}
+
+	fn end_branch(&mut self, value: Option<Value>) {
+		if let Some(value) = value {
+			self.buffer.extend_from_slice(value.as_bytes());
+		}
+	}
+
+	fn has_extension_nodes(&self) -> bool {
+		false
+	}
+
+	fn append_substream<H: Hasher>(&mut self, child: &Self) -> Option<H::Out> {
+		let encoded = &child.buffer;
+		if let Some(partial) = self.extension.take() {
+			// the child is a branch, whose prefix is empty
+			append_prefix(&mut self.buffer, &partial);
+			self.buffer.extend_from_slice(&encoded[2..]);
+			return None;
+		}
+		assert_eq!(H::LENGTH, HASH_LENGTH, "binary trie nodes are referenced by 32 byte hashes");
+		let hash = H::hash(encoded);
+		self.append_hashed_subtree(hash.as_ref());
+		Some(hash)
+	}
+
+	fn append_hashed_subtree(&mut self, hash: &[u8]) {
+		assert!(self.extension.is_none(), "the child of an extension is stored in the extension itself");
+		self.buffer.extend_from_slice(hash);
+	}
+
+	fn as_raw(&self) -> &[u8] {
+		&self.buffer
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::append_prefix;
+	use crate::binary_trie_root;
+	use hash_db::Hasher;
+	use keccak_hasher::KeccakHasher;
+
+	#[test]
+	fn packs_prefixes() {
+		let mut out = Vec::new();
+		append_prefix(&mut out, &[1, 0, 1, 0, 0, 0, 0, 1, 1]);
+		assert_eq!(out, vec![0, 9, 0xa1, 0x80]);
+	}
+
+	#[test]
+	#[should_panic(expected = "keys of binary tries have at most 8191 bytes")]
+	fn rejects_long_keys() {
+		binary_trie_root::<KeccakHasher, _, _, _>(vec![(vec![0; 8192], b"a")]);
+	}
+
+	#[test]
+	fn encodes_nodes() {
+		assert_eq!(binary_trie_root::<KeccakHasher, _, _, _>(Vec::<(&[u8], &[u8])>::new()), KeccakHasher::hash(&[]));
+
+		// keys 0b1010_0000 and 0b1011_0000 share the prefix 101
+		let left = [&[0, 4, 0][..], &[0; 64], b"a"].concat();
+		let right = [&[0, 4, 0][..], &[0; 64], b"b"].concat();
+		let root = [&[0, 3, 0xa0][..], &KeccakHasher::hash(&left), &KeccakHasher::hash(&right)].concat();
+		let input = vec![(vec![0xa0], b"a"), (vec![0xb0], b"b")];
+		assert_eq!(binary_trie_root::<KeccakHasher, _, _, _>(input), KeccakHasher::hash(&root));
+	}
+}
@@ -0,0 +1,253 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Encoding nodes into a buffer provided by the caller.
+
+use core::cell::RefCell;
+use core::{cmp, mem};
+
+use crate::fallible::{try_hash256rlp_with, TryTrieStream};
+use crate::hex_prefix::hex_prefix_encode;
+use crate::sorted_nibbles;
+use crate::stream::Value;
+use crate::value::AsValue;
+
+use hash_db::Hasher;
+
+/// Space reserved for the header of a list, enough for lists of up to 64 KiB.
+const LIST_HEADER_LEN: usize = 3;
+
+/// A node doesn't fit into its part of the buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferOverflow;
+
+/// RLP encoding of the Ethereum trie into a slice, without allocating.
+///
+/// Encoding fails with `BufferOverflow` once the node doesn't fit into the slice. Streams are
+/// created by `trie_root_in_buffer`, `TryTrieStream::new` can't give them a slice.
+pub(crate) struct SliceTrieStream<'a> {
+	buf: &'a mut [u8],
+	len: usize,
+	/// Whether the node is an extension whose child is still to be appended.
+	in_extension: bool,
+}
+
+impl<'a> SliceTrieStream<'a> {
+	/// Creates an empty stream encoding into `buf`.
+	fn new_in(buf: &'a mut [u8]) -> Self {
+		SliceTrieStream { buf, len: 0, in_extension: false }
+	}
+
+	/// Appends `bytes` as they are.
+	fn put(&mut self, bytes: &[u8]) -> Result<(), BufferOverflow> {
+		let end = self.len + bytes.len();
+		self.buf.get_mut(self.len..end).ok_or(BufferOverflow)?.copy_from_slice(bytes);
+		self.len = end;
+		Ok(())
+	}
+
+	/// Appends the header of a string or list of `len` bytes, with `offset` 0x80 or 0xc0.
+	fn put_header(&mut self, len: usize, offset: u8) -> Result<(), BufferOverflow> {
+		if len < 56 {
+			return self.put(&[offset + len as u8]);
+		}
+		let len_bytes = (len as u64).to_be_bytes();
+		let skip = len_bytes.iter().take_while(|byte| **byte == 0).count();
+		self.put(&[offset + 55 + (8 - skip) as u8])?;
+		self.put(&len_bytes[skip..])
+	}
+
+	/// Appends an RLP string.
+	fn put_string(&mut self, bytes: &[u8]) -> Result<(), BufferOverflow> {
+		match bytes {
+			[byte] if *byte < 0x80 => self.put(bytes),
+			_ => {
+				self.put_header(bytes.len(), 0x80)?;
+				self.put(bytes)
+			}
+		}
+	}
+
+	/// Appends the hex-prefix encoding of a partial key as an RLP string.
+	fn put_partial(&mut self, partial: &[u8], leaf: bool) -> Result<(), BufferOverflow> {
+		let len = partial.len() / 2 + 1;
+		let mut encoded = hex_prefix_encode(partial, leaf);
+		if len == 1 {
+			return self.put_string(&[encoded.next().expect("hex-prefix encoding has a byte; qed")]);
+		}
+		self.put_header(len, 0x80)?;
+		let end = self.len + len;
+		for (byte, encoded) in self.buf.get_mut(self.len..end).ok_or(BufferOverflow)?.iter_mut().zip(encoded) {
+			*byte = encoded;
+		}
+		self.len = end;
+		Ok(())
+	}
+
+	/// Starts a list, leaving room for its header.
+	fn begin_list(&mut self) -> Result<(), BufferOverflow> {
+		self.put(&[0; LIST_HEADER_LEN])
+	}
+
+	/// Completes the node if it is an extension, whose child was just appended.
+	fn end_extension(&mut self) -> Result<(), BufferOverflow> {
+		match mem::replace(&mut self.in_extension, false) {
+			true => self.end_list(),
+			false => Ok(()),
+		}
+	}
+
+	/// Completes the list started at the beginning of the buffer, moving its items next to its header.
+	fn end_list(&mut self) -> Result<(), BufferOverflow> {
+		let payload = self.len - LIST_HEADER_LEN;
+		self.len = 0;
+		self.put_header(payload, 0xc0)?;
+		if self.len > LIST_HEADER_LEN {
+			return Err(BufferOverflow);
+		}
+		self.buf.copy_within(LIST_HEADER_LEN..LIST_HEADER_LEN + payload, self.len);
+		self.len += payload;
+		Ok(())
+	}
+}
+
+impl<'a> TryTrieStream for SliceTrieStream<'a> {
+	type Error = BufferOverflow;
+
+	fn new() -> Self {
+		unreachable!("slice streams are created by `trie_root_in_buffer`")
+	}
+
+	fn clear(&mut self) {
+		self.len = 0;
+		self.in_extension = false;
+	}
+
+	fn append_empty_data(&mut self) -> Result<(), BufferOverflow> {
+		self.put(&[0x80])
+	}
+
+	fn append_leaf(&mut self, partial: &[u8], value: Value) -> Result<(), BufferOverflow> {
+		self.begin_list()?;
+		self.put_partial(partial, true)?;
+		self.put_string(value.as_bytes())?;
+		self.end_list()
+	}
+
+	fn begin_extension(&mut self, partial: &[u8]) -> Result<(), BufferOverflow> {
+		self.begin_list()?;
+		self.in_extension = true;
+		self.put_partial(partial, false)
+	}
+
+	fn begin_branch(&mut self, _has_value: bool, _children: impl Iterator<Item = bool>) -> Result<(), BufferOverflow> {
+		self.begin_list()
+	}
+
+	fn append_empty_child(&mut self) -> Result<(), BufferOverflow> {
+		self.put(&[0x80])
+	}
+
+	fn end_branch(&mut self, value: Option<Value>) -> Result<(), BufferOverflow> {
+		match value {
+			Some(value) => self.put_string(value.as_bytes())?,
+			None => self.put(&[0x80])?,
+		}
+		self.end_list()
+	}
+
+	fn append_substream<H: Hasher>(&mut self, child: &Self) -> Result<Option<H::Out>, BufferOverflow> {
+		let encoded = child.as_raw();
+		if encoded.len() >= H::LENGTH {
+			let hash = H::hash(encoded);
+			self.append_hashed_subtree(hash.as_ref())?;
+			return Ok(Some(hash));
+		}
+		self.put(encoded)?;
+		self.end_extension()?;
+		Ok(None)
+	}
+
+	fn append_hashed_subtree(&mut self, hash: &[u8]) -> Result<(), BufferOverflow> {
+		self.put_string(hash)?;
+		self.end_extension()
+	}
+
+	fn as_raw(&self) -> &[u8] {
+		&self.buf[..self.len]
+	}
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, encoding the nodes into
+/// `buffer` rather than allocating for them.
+///
+/// Every node being encoded takes `node_len` bytes of the buffer, and as many nodes are encoded
+/// at a time as the trie is deep, so the buffer should hold `node_len` bytes for every nibble of
+/// the longest key. Sorting the input and traversing the trie still allocate a little, in
+/// proportion to the input rather than to the nodes. Returns `BufferOverflow` if a node doesn't
+/// fit into `node_len` bytes or the buffer is exhausted.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_in_buffer, BufferOverflow};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+///
+/// let mut buffer = [0; 4096];
+/// let root = trie_root_in_buffer::<KeccakHasher, _, _, _>(v.clone(), &mut buffer, 600);
+/// assert_eq!(root, Ok(trie_root::<KeccakHasher, _, _, _>(v.clone())));
+///
+/// let root = trie_root_in_buffer::<KeccakHasher, _, _, _>(v, &mut buffer, 16);
+/// assert_eq!(root, Err(BufferOverflow));
+/// ```
+pub fn trie_root_in_buffer<H, I, A, B>(input: I, buffer: &mut [u8], node_len: usize) -> Result<H::Out, BufferOverflow>
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+{
+	let input = sorted_nibbles(input.into_iter().collect());
+	let rest = RefCell::new(buffer);
+	let new_stream = || {
+		let mut rest = rest.borrow_mut();
+		let len = cmp::min(node_len, rest.len());
+		let (buf, tail) = mem::take(&mut *rest).split_at_mut(len);
+		*rest = tail;
+		SliceTrieStream::new_in(buf)
+	};
+	let root = try_hash256rlp_with::<H, _, _, _, _>(&input, new_stream)?;
+	Ok(H::hash(root.as_raw()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{trie_root_in_buffer, BufferOverflow};
+	use crate::test_support::{indexed_input, test_inputs};
+	use crate::trie_root;
+	use keccak_hasher::KeccakHasher;
+
+	#[test]
+	fn matches_trie_root() {
+		let mut buffer = vec![0; 64 * 1024];
+		for input in test_inputs::<KeccakHasher>() {
+			let root = trie_root_in_buffer::<KeccakHasher, _, _, _>(input.clone(), &mut buffer, 1024);
+			assert_eq!(root, Ok(trie_root::<KeccakHasher, _, _, _>(input)));
+		}
+	}
+
+	#[test]
+	fn fails_once_buffer_is_exhausted() {
+		let input = indexed_input(100);
+		let mut buffer = vec![0; 600 * 8];
+		let root = trie_root_in_buffer::<KeccakHasher, _, _, _>(input.clone(), &mut buffer, 600);
+		assert_eq!(root, Ok(trie_root::<KeccakHasher, _, _, _>(input.clone())));
+		let root = trie_root_in_buffer::<KeccakHasher, _, _, _>(input, &mut buffer[..600 * 2], 600);
+		assert_eq!(root, Err(BufferOverflow));
+	}
+}
@@ -0,0 +1,284 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Incremental trie root calculation.
+
+use crate::iter_build::key_nibbles;
+use crate::rstd::*;
+use crate::{hash_sorted, Layout, NodeVisitor, RlpTrieStream};
+
+use hash_db::Hasher;
+
+/// Keeps a set of key-value pairs together with the encoded nodes of their trie.
+///
+/// Inserting or removing a key only discards the cached nodes on the path to that key,
+/// so the next call to `root` rehashes just the affected part of the trie. Nodes which are
+/// no longer part of the trie are evicted by `root`.
+///
+/// ```
+/// use triehash::{trie_root, TrieRootBuilder};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let mut builder = TrieRootBuilder::<KeccakHasher>::new();
+/// builder.insert(b"doe", b"reindeer".to_vec());
+/// builder.insert(b"dog", b"puppy".to_vec());
+/// let root = builder.root();
+///
+/// builder.insert(b"dogglesworth", b"cat".to_vec());
+/// builder.remove(b"doe");
+/// assert_eq!(builder.root(), trie_root::<KeccakHasher, _, _, _>(vec![("dog", "puppy"), ("dogglesworth", "cat")]));
+///
+/// builder.insert(b"doe", b"reindeer".to_vec());
+/// builder.remove(b"dogglesworth");
+/// assert_eq!(builder.root(), root);
+/// ```
+pub struct TrieRootBuilder<H: Hasher> {
+	/// Values keyed by the nibbles of their keys.
+	entries: BTreeMap<Vec<u8>, Vec<u8>>,
+	/// Hashes of the nodes below the root which are referenced by hash, keyed by their path.
+	nodes: BTreeMap<Vec<u8>, H::Out>,
+	root: Option<H::Out>,
+}
+
+impl<H: Hasher> Default for TrieRootBuilder<H> {
+	fn default() -> Self {
+		TrieRootBuilder { entries: BTreeMap::new(), nodes: BTreeMap::new(), root: None }
+	}
+}
+
+impl<H: Hasher> TrieRootBuilder<H> {
+	/// Creates a builder for an empty trie.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Number of keys in the trie.
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	/// Returns true if the trie has no keys.
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	/// Inserts a value, returning the previous value of the key.
+	pub fn insert(&mut self, key: &[u8], value: Vec<u8>) -> Option<Vec<u8>> {
+		let key = key_nibbles(key);
+		if self.entries.get(&key) == Some(&value) {
+			return Some(value);
+		}
+		self.invalidate(&key);
+		self.entries.insert(key, value)
+	}
+
+	/// Removes a key, returning its value.
+	pub fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+		let key = key_nibbles(key);
+		let value = self.entries.remove(&key);
+		if value.is_some() {
+			self.invalidate(&key);
+		}
+		value
+	}
+
+	/// Returns the root hash of the trie, encoding only nodes changed since the last call.
+	pub fn root(&mut self) -> H::Out {
+		if let Some(root) = self.root {
+			return root;
+		}
+		let input = self.entries.iter().collect::<Vec<_>>();
+		let mut visitor = CachedNodes::<H> { old: &self.nodes, new: BTreeMap::new(), reused: Vec::new() };
+		let root = hash_sorted::<H, RlpTrieStream, _, _, _>(&input, Layout::HEX, &mut visitor);
+
+		// the nodes below reused ones are still part of the trie, the others were encoded again
+		let CachedNodes { mut new, reused, .. } = visitor;
+		for path in reused {
+			let below = self.nodes.range(path.clone()..).take_while(|(below, _)| below.starts_with(&path));
+			new.extend(below.map(|(below, hash)| (below.clone(), *hash)));
+		}
+		self.nodes = new;
+		self.root = Some(root);
+		root
+	}
+
+	/// Drops the cached nodes on the path to `key`.
+	fn invalidate(&mut self, key: &[u8]) {
+		for len in 0..=key.len() {
+			self.nodes.remove(&key[..len]);
+		}
+		self.root = None;
+	}
+}
+
+/// Generates the trie root hashes of two sets of key-value tuples, `(old root, new root)`.
+///
+/// The second root is generated from the nodes of the first one, re-encoding only the nodes
+/// on the paths to keys whose values differ. This is much faster than two calls to `trie_root`
+/// when the sets are similar, e.g. the state before and after a block.
+///
+/// ```
+/// use triehash::{trie_root, trie_roots_diff};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let old = vec![("doe", "reindeer"), ("dog", "puppy")];
+/// let new = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+///
+/// let (old_root, new_root) = trie_roots_diff::<KeccakHasher, _, _, _, _>(old.clone(), new.clone());
+/// assert_eq!(old_root, trie_root::<KeccakHasher, _, _, _>(old));
+/// assert_eq!(new_root, trie_root::<KeccakHasher, _, _, _>(new));
+/// ```
+pub fn trie_roots_diff<H, I, J, A, B>(old: I, new: J) -> (H::Out, H::Out)
+where
+	I: IntoIterator<Item = (A, B)>,
+	J: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	H: Hasher,
+{
+	let old = old.into_iter().collect::<BTreeMap<_, _>>();
+	let new = new.into_iter().collect::<BTreeMap<_, _>>();
+
+	let mut builder = TrieRootBuilder::<H>::new();
+	for (key, value) in &old {
+		builder.insert(key.as_ref(), value.as_ref().to_vec());
+	}
+	let old_root = builder.root();
+
+	for key in old.keys().filter(|key| !new.contains_key(*key)) {
+		builder.remove(key.as_ref());
+	}
+	// inserting an unchanged value keeps the cached nodes
+	for (key, value) in &new {
+		builder.insert(key.as_ref(), value.as_ref().to_vec());
+	}
+	(old_root, builder.root())
+}
+
+/// Skips the nodes cached by a `TrieRootBuilder`, collecting the nodes of the new trie.
+struct CachedNodes<'a, H: Hasher> {
+	/// Nodes cached by the previous call to `root`.
+	old: &'a BTreeMap<Vec<u8>, H::Out>,
+	/// Nodes encoded by this call.
+	new: BTreeMap<Vec<u8>, H::Out>,
+	/// Paths of the cached nodes which were reused.
+	reused: Vec<Vec<u8>>,
+}
+
+impl<'a, H: Hasher> NodeVisitor<H> for CachedNodes<'a, H> {
+	fn visit(&mut self, path: &[u8], _encoded: &[u8], hash: Option<&H::Out>) {
+		// the root is cached by the builder itself
+		if let (false, Some(hash)) = (path.is_empty(), hash) {
+			self.new.insert(path.to_vec(), *hash);
+		}
+	}
+
+	fn known_hash(&mut self, path: &[u8], _begin: usize, _end: usize) -> Option<H::Out> {
+		let hash = self.old.get(path).copied();
+		if hash.is_some() {
+			self.reused.push(path.to_vec());
+		}
+		hash
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{trie_roots_diff, TrieRootBuilder};
+	use crate::trie_root;
+	use hash_db::Hasher;
+	use keccak_hasher::KeccakHasher;
+	use std::collections::BTreeMap;
+
+	#[test]
+	fn matches_trie_root_after_each_change() {
+		let mut builder = TrieRootBuilder::<KeccakHasher>::new();
+		let mut expected = BTreeMap::new();
+		assert_eq!(builder.root(), trie_root::<KeccakHasher, _, _, _>(expected.clone()));
+
+		for i in 0..500u32 {
+			let seed = KeccakHasher::hash(&i.to_le_bytes());
+			let key = seed[..1 + seed[0] as usize % 3].to_vec();
+			if seed[1] % 4 == 0 {
+				assert_eq!(builder.remove(&key), expected.remove(&key));
+			} else {
+				let value = seed[2..2 + seed[2] as usize % 30].to_vec();
+				assert_eq!(builder.insert(&key, value.clone()), expected.insert(key, value));
+			}
+			assert_eq!(builder.len(), expected.len());
+			assert_eq!(builder.root(), trie_root::<KeccakHasher, _, _, _>(expected.clone()));
+		}
+	}
+
+	#[test]
+	fn evicts_nodes_no_longer_in_the_trie() {
+		let mut builder = TrieRootBuilder::<KeccakHasher>::new();
+		for i in 0..=255u8 {
+			builder.insert(&[i, 0], vec![i; 40]);
+		}
+		builder.root();
+		assert_eq!(builder.nodes.len(), 16 + 256);
+
+		// changing a key keeps the nodes of the others
+		builder.insert(&[0x12, 0], vec![0; 40]);
+		builder.root();
+		assert_eq!(builder.nodes.len(), 16 + 256);
+
+		// the remaining key is a leaf at the root
+		for i in 1..=255u8 {
+			builder.remove(&[i, 0]);
+		}
+		assert_eq!(builder.root(), trie_root::<KeccakHasher, _, _, _>(vec![([0u8, 0], [0u8; 40])]));
+		assert!(builder.nodes.is_empty());
+	}
+
+	#[test]
+	fn deep_trie_on_small_stack() {
+		// every key is a prefix of the next one, so each key adds a level to the trie
+		let input = (1..300).map(|len| (vec![0u8; len], vec![len as u8])).collect::<Vec<_>>();
+		let expected = trie_root::<KeccakHasher, _, _, _>(input.clone());
+		let root = std::thread::Builder::new()
+			.stack_size(64 * 1024)
+			.spawn(move || {
+				let mut builder = TrieRootBuilder::<KeccakHasher>::new();
+				for (key, value) in input {
+					builder.insert(&key, value);
+				}
+				builder.root()
+			})
+			.unwrap()
+			.join()
+			.unwrap();
+		assert_eq!(root, expected);
+	}
+
+	#[test]
+	fn roots_diff_matches_trie_root() {
+		let old = (0..500u32).map(|i| (KeccakHasher::hash(&i.to_le_bytes())[..3].to_vec(), vec![i as u8; 40]));
+		let old = old.collect::<BTreeMap<_, _>>();
+		let mut new = old.clone();
+		for (i, (key, value)) in old.iter().enumerate() {
+			match i % 10 {
+				0 => {
+					new.remove(key);
+				}
+				1 => {
+					new.insert(key.clone(), value[..5].to_vec());
+				}
+				2 => {
+					new.insert(key[..2].to_vec(), value.clone());
+				}
+				_ => {}
+			}
+		}
+
+		let (old_root, new_root) = trie_roots_diff::<KeccakHasher, _, _, _, _>(old.clone(), new.clone());
+		assert_eq!(old_root, trie_root::<KeccakHasher, _, _, _>(old));
+		assert_eq!(new_root, trie_root::<KeccakHasher, _, _, _>(new));
+	}
+}
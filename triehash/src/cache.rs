@@ -0,0 +1,253 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reusing encoded subtries across trie root computations.
+
+use core::cmp;
+
+use crate::iter_build::key_nibbles;
+use crate::rstd::*;
+use crate::{hash_sorted, Layout, NodeVisitor, RlpTrieStream};
+
+use hash_db::Hasher;
+
+/// Encoded subtries of a previous trie root computation, keyed by a digest of their items.
+///
+/// Passing the same cache to subsequent calls of `trie_root_with_cache` reuses the subtries
+/// whose items didn't change instead of encoding them again, e.g. when recomputing the receipts
+/// root of the pending block after every new transaction. Only the subtries of the last
+/// computation are kept.
+pub struct TrieRootCache<H: Hasher> {
+	/// Subtries keyed by the digest of their items.
+	nodes: BTreeMap<H::Out, CachedNode<H>>,
+}
+
+/// Subtrie referenced by hash.
+struct CachedNode<H: Hasher> {
+	/// Hash of the subtrie.
+	hash: H::Out,
+	/// Digests of the cached subtries below it.
+	children: Vec<H::Out>,
+}
+
+impl<H: Hasher> Default for TrieRootCache<H> {
+	fn default() -> Self {
+		TrieRootCache { nodes: BTreeMap::new() }
+	}
+}
+
+impl<H: Hasher> TrieRootCache<H> {
+	/// Creates an empty cache.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Number of cached subtries.
+	pub fn len(&self) -> usize {
+		self.nodes.len()
+	}
+
+	/// Returns true if no subtries are cached.
+	pub fn is_empty(&self) -> bool {
+		self.nodes.is_empty()
+	}
+
+	/// Drops all cached subtries.
+	pub fn clear(&mut self) {
+		self.nodes.clear();
+	}
+}
+
+/// State of a single computation, skipping the subtries of the previous one.
+struct CacheVisitor<'a, H: Hasher> {
+	/// Subtries of the previous computation.
+	old: &'a mut BTreeMap<H::Out, CachedNode<H>>,
+	/// Subtries of this computation.
+	new: BTreeMap<H::Out, CachedNode<H>>,
+	/// Digests of the input items.
+	digests: &'a [H::Out],
+	/// Scratch space for digests.
+	buffer: Vec<u8>,
+	/// Nodes being encoded, innermost last.
+	pending: Vec<PendingNode<H>>,
+}
+
+/// Node being encoded by a `CacheVisitor`.
+struct PendingNode<H: Hasher> {
+	/// Digest the node is cached with, leaves aren't cached.
+	digest: Option<H::Out>,
+	/// Digests of the cached subtries below it.
+	children: Vec<H::Out>,
+}
+
+impl<'a, H: Hasher> CacheVisitor<'a, H>
+where
+	H::Out: cmp::Ord,
+{
+	/// Moves the cached subtries below a reused node into this computation.
+	fn keep(&mut self, node: &CachedNode<H>) {
+		for digest in &node.children {
+			if let Some(child) = self.old.remove(digest) {
+				self.keep(&child);
+				self.new.insert(*digest, child);
+			}
+		}
+	}
+
+	/// Digest of the items `begin..end` below a node at depth `pre_len`.
+	fn digest(&mut self, begin: usize, end: usize, pre_len: usize) -> H::Out {
+		self.buffer.clear();
+		self.buffer.extend_from_slice(&(pre_len as u32).to_be_bytes());
+		for digest in &self.digests[begin..end] {
+			self.buffer.extend_from_slice(digest.as_ref());
+		}
+		H::hash(&self.buffer)
+	}
+
+	/// Records a cached subtrie below the node being encoded.
+	fn add_child(&mut self, digest: H::Out) {
+		if let Some(node) = self.pending.last_mut() {
+			node.children.push(digest);
+		}
+	}
+}
+
+impl<'a, H: Hasher> NodeVisitor<H> for CacheVisitor<'a, H>
+where
+	H::Out: cmp::Ord,
+{
+	fn visit(&mut self, _path: &[u8], _encoded: &[u8], hash: Option<&H::Out>) {
+		// the root isn't looked up, so nothing is pending for it
+		let PendingNode { digest, children } = match self.pending.pop() {
+			Some(node) => node,
+			None => return,
+		};
+		match (digest, hash) {
+			(Some(digest), Some(hash)) => {
+				self.new.insert(digest, CachedNode { hash: *hash, children });
+				self.add_child(digest);
+			}
+			// inlined nodes aren't cached, their cached children belong to the parent
+			_ => {
+				for child in children {
+					self.add_child(child);
+				}
+			}
+		}
+	}
+
+	fn known_hash(&mut self, path: &[u8], begin: usize, end: usize) -> Option<H::Out> {
+		// leaves are cheaper to encode than to look up
+		if end - begin == 1 {
+			self.pending.push(PendingNode { digest: None, children: Vec::new() });
+			return None;
+		}
+		let digest = self.digest(begin, end, path.len());
+		match self.old.remove(&digest) {
+			Some(node) => {
+				let hash = node.hash;
+				self.keep(&node);
+				self.new.insert(digest, node);
+				self.add_child(digest);
+				Some(hash)
+			}
+			None => {
+				self.pending.push(PendingNode { digest: Some(digest), children: Vec::new() });
+				None
+			}
+		}
+	}
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, reusing the subtries cached
+/// by the previous call with the same `cache`.
+///
+/// ```
+/// use triehash::{ordered_trie_root, trie_root_with_cache, TrieRootCache};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let mut cache = TrieRootCache::<KeccakHasher>::new();
+/// let mut receipts = Vec::new();
+/// for i in 0..100u32 {
+/// 	receipts.push(i.to_be_bytes());
+/// 	let input = receipts.iter().enumerate().map(|(i, receipt)| (rlp::encode(&i), receipt));
+/// 	let root = trie_root_with_cache::<KeccakHasher, _, _, _>(input, &mut cache);
+/// 	assert_eq!(root, ordered_trie_root::<KeccakHasher, _>(&receipts));
+/// }
+/// ```
+pub fn trie_root_with_cache<H, I, A, B>(input: I, cache: &mut TrieRootCache<H>) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	H: Hasher,
+	H::Out: cmp::Ord,
+{
+	let input = input.into_iter().collect::<BTreeMap<_, _>>();
+	let input = input.into_iter().map(|(k, v)| (key_nibbles(k.as_ref()), v)).collect::<Vec<_>>();
+
+	let mut buffer = Vec::new();
+	let digests = input
+		.iter()
+		.map(|(key, value)| {
+			buffer.clear();
+			buffer.extend_from_slice(&(key.len() as u32).to_be_bytes());
+			buffer.extend_from_slice(key);
+			buffer.extend_from_slice(value.as_ref());
+			H::hash(&buffer)
+		})
+		.collect::<Vec<_>>();
+
+	let mut visitor = CacheVisitor::<H> {
+		old: &mut cache.nodes,
+		new: BTreeMap::new(),
+		digests: &digests,
+		buffer,
+		pending: Vec::new(),
+	};
+	let root = hash_sorted::<H, RlpTrieStream, _, _, _>(&input, Layout::HEX, &mut visitor);
+	cache.nodes = visitor.new;
+	root
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{trie_root_with_cache, TrieRootCache};
+	use crate::trie_root;
+	use keccak_hasher::KeccakHasher;
+	use std::collections::BTreeMap;
+
+	#[test]
+	fn matches_trie_root_after_changes() {
+		let mut cache = TrieRootCache::<KeccakHasher>::new();
+		let mut input = BTreeMap::new();
+		for i in 0..300u32 {
+			input.insert((i * 7 % 256).to_be_bytes().to_vec(), vec![i as u8; 1 + i as usize % 40]);
+			if i % 5 == 0 {
+				input.remove(&(i % 64).to_be_bytes()[..]);
+			}
+			let root = trie_root_with_cache::<KeccakHasher, _, _, _>(input.clone(), &mut cache);
+			assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(input.clone()));
+		}
+	}
+
+	#[test]
+	fn keeps_only_last_subtries() {
+		let mut cache = TrieRootCache::<KeccakHasher>::new();
+		let input = (0..100u32).map(|i| (i.to_be_bytes(), i.to_le_bytes())).collect::<Vec<_>>();
+		trie_root_with_cache::<KeccakHasher, _, _, _>(input.clone(), &mut cache);
+		let len = cache.len();
+		assert!(len > 0);
+
+		trie_root_with_cache::<KeccakHasher, _, _, _>(input, &mut cache);
+		assert_eq!(cache.len(), len);
+
+		trie_root_with_cache::<KeccakHasher, _, _, _>(vec![(b"doe", b"reindeer")], &mut cache);
+		assert!(cache.is_empty());
+	}
+}
@@ -0,0 +1,113 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Cancelling the generation of a trie root.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{trie_root_with_visitor, AsValue, NodeVisitor};
+
+use hash_db::Hasher;
+
+/// Stops building the trie once the flag is set.
+struct CancelFlag<'a> {
+	flag: &'a AtomicBool,
+	cancelled: bool,
+}
+
+impl<'a, H: Hasher> NodeVisitor<H> for CancelFlag<'a> {
+	fn visit(&mut self, _path: &[u8], _encoded: &[u8], _hash: Option<&H::Out>) {}
+
+	fn is_cancelled(&mut self) -> bool {
+		self.cancelled = self.cancelled || self.flag.load(Ordering::Relaxed);
+		self.cancelled
+	}
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, unless `cancel` is set meanwhile.
+///
+/// The flag is checked before encoding every node, so setting it from another thread (e.g. when
+/// shutting down) aborts the computation promptly. Returns `None` if it was cancelled.
+///
+/// ```
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use triehash::{trie_root, trie_root_with_cancel};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let cancel = AtomicBool::new(false);
+/// let root = trie_root_with_cancel::<KeccakHasher, _, _, _>(v.clone(), &cancel);
+/// assert_eq!(root, Some(trie_root::<KeccakHasher, _, _, _>(v.clone())));
+///
+/// cancel.store(true, Ordering::Relaxed);
+/// assert_eq!(trie_root_with_cancel::<KeccakHasher, _, _, _>(v, &cancel), None);
+/// ```
+pub fn trie_root_with_cancel<H, I, A, B>(input: I, cancel: &AtomicBool) -> Option<H::Out>
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+{
+	let mut flag = CancelFlag { flag: cancel, cancelled: false };
+	let root = trie_root_with_visitor::<H, _, _, _, _>(input, &mut flag);
+	if flag.cancelled {
+		None
+	} else {
+		Some(root)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{trie_root_with_cancel, CancelFlag};
+	use crate::test_support::indexed_input;
+	use crate::{trie_root_with_visitor, NodeVisitor};
+	use core::sync::atomic::{AtomicBool, Ordering};
+	use hash_db::Hasher;
+	use keccak_hasher::KeccakHasher;
+
+	/// Sets the flag after visiting a number of nodes.
+	struct CancelAfter<'a> {
+		inner: CancelFlag<'a>,
+		cancel_after: usize,
+		visited: usize,
+	}
+
+	impl<'a, H: Hasher> NodeVisitor<H> for CancelAfter<'a> {
+		fn visit(&mut self, _path: &[u8], _encoded: &[u8], _hash: Option<&H::Out>) {
+			self.visited += 1;
+			if self.visited == self.cancel_after {
+				self.inner.flag.store(true, Ordering::Relaxed);
+			}
+		}
+
+		fn is_cancelled(&mut self) -> bool {
+			NodeVisitor::<H>::is_cancelled(&mut self.inner)
+		}
+	}
+
+	#[test]
+	fn stops_soon_after_cancelling() {
+		let input = indexed_input(1000);
+		let cancel = AtomicBool::new(false);
+		let mut visitor =
+			CancelAfter { inner: CancelFlag { flag: &cancel, cancelled: false }, cancel_after: 10, visited: 0 };
+		trie_root_with_visitor::<KeccakHasher, _, _, _, _>(input.clone(), &mut visitor);
+
+		assert!(visitor.inner.cancelled);
+		// the node completed when cancelling and the root are still visited
+		assert_eq!(visitor.visited, 11);
+		assert_eq!(trie_root_with_cancel::<KeccakHasher, _, _, _>(input, &cancel), None);
+	}
+}
@@ -0,0 +1,190 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Encoding of trie nodes as CBOR.
+
+use crate::hex_prefix::hex_prefix_encode;
+use crate::stream::{TrieStream, Value};
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use hash_db::Hasher;
+
+/// Major type of byte strings.
+const BYTES: u8 = 2;
+/// Major type of arrays.
+const ARRAY: u8 = 4;
+/// The `null` simple value.
+const NULL: u8 = 0xf6;
+
+/// Appends the head of a data item in its shortest form, as required by canonical CBOR.
+fn append_head(out: &mut Vec<u8>, major: u8, len: usize) {
+	let major = major << 5;
+	match len {
+		0..=23 => out.push(major | len as u8),
+		24..=0xff => out.extend_from_slice(&[major | 24, len as u8]),
+		0x100..=0xffff => {
+			out.push(major | 25);
+			out.extend_from_slice(&(len as u16).to_be_bytes());
+		}
+		_ if len as u64 <= 0xffff_ffff => {
+			out.push(major | 26);
+			out.extend_from_slice(&(len as u32).to_be_bytes());
+		}
+		_ => {
+			out.push(major | 27);
+			out.extend_from_slice(&(len as u64).to_be_bytes());
+		}
+	}
+}
+
+/// Appends a byte string.
+fn append_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+	append_head(out, BYTES, bytes.len());
+	out.extend_from_slice(bytes);
+}
+
+/// Appends a hex-prefix encoded partial key as a byte string.
+fn append_partial(out: &mut Vec<u8>, partial: &[u8], leaf: bool) {
+	append_head(out, BYTES, partial.len() / 2 + 1);
+	out.extend(hex_prefix_encode(partial, leaf));
+}
+
+/// Canonical CBOR encoding of the nodes of the Ethereum trie.
+///
+/// Nodes have the structure of their RLP encoding: leaves and extensions are arrays of the
+/// hex-prefix encoded partial key and the value or child, branches are arrays of their
+/// children followed by their value. Empty slots and the empty trie are `null`. Children
+/// shorter than a hash (`Hasher::LENGTH`, 32 bytes for Keccak-256) are embedded into their
+/// parents, longer ones are referenced by the byte string of their hash.
+#[derive(Default)]
+pub struct CborTrieStream {
+	buffer: Vec<u8>,
+}
+
+impl TrieStream for CborTrieStream {
+	fn new() -> Self {
+		Self::default()
+	}
+
+	fn clear(&mut self) {
+		self.buffer.clear();
+	}
+
+	fn append_empty_data(&mut self) {
+		self.buffer.push(NULL);
+	}
+
+	fn append_leaf(&mut self, partial: &[u8], value: Value) {
+		append_head(&mut self.buffer, ARRAY, 2);
+		append_partial(&mut self.buffer, partial, true);
+		append_bytes(&mut self.buffer, value.as_bytes());
+	}
+
+	fn begin_extension(&mut self, partial: &[u8]) {
+		append_head(&mut self.buffer, ARRAY, 2);
+		append_partial(&mut self.buffer, partial, false);
+	}
+
+	fn begin_branch(&mut self, _has_value: bool, children: impl Iterator<Item = bool>) {
+		append_head(&mut self.buffer, ARRAY, children.count() + 1);
+	}
+
+	fn begin_branch_with_arity(&mut self, arity: usize, _has_value: bool, _children: impl Iterator<Item = bool>) {
+		append_head(&mut self.buffer, ARRAY, arity + 1);
+	}
+
+	fn append_empty_child(&mut self) {
+		self.buffer.push(NULL);
+	}
+
+	fn end_branch(&mut self, value: Option<Value>) {
+		match value {
+			Some(value) => append_bytes(&mut self.buffer, value.as_bytes()),
+			None => self.buffer.push(NULL),
+		}
+	}
+
+	fn append_substream<H: Hasher>(&mut self, child: &Self) -> Option<H::Out> {
+		let encoded = &child.buffer;
+		if encoded.len() < H::LENGTH {
+			self.buffer.extend_from_slice(encoded);
+			return None;
+		}
+		let hash = H::hash(encoded);
+		self.append_hashed_subtree(hash.as_ref());
+		Some(hash)
+	}
+
+	fn append_hashed_subtree(&mut self, hash: &[u8]) {
+		append_bytes(&mut self.buffer, hash);
+	}
+
+	fn as_raw(&self) -> &[u8] {
+		&self.buffer
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{append_head, CborTrieStream, ARRAY, BYTES, NULL};
+	use crate::trie_root_with_stream;
+	use hash_db::Hasher;
+	use keccak_hasher::KeccakHasher;
+
+	#[test]
+	fn heads_are_canonical() {
+		let mut out = Vec::new();
+		append_head(&mut out, BYTES, 23);
+		append_head(&mut out, BYTES, 24);
+		append_head(&mut out, ARRAY, 256);
+		append_head(&mut out, ARRAY, 1 << 16);
+		assert_eq!(out, vec![0x57, 0x58, 24, 0x99, 0x01, 0x00, 0x9a, 0x00, 0x01, 0x00, 0x00]);
+	}
+
+	#[test]
+	fn encodes_nodes() {
+		let empty = Vec::<(Vec<u8>, Vec<u8>)>::new();
+		assert_eq!(trie_root_with_stream::<KeccakHasher, CborTrieStream, _, _, _>(empty), KeccakHasher::hash(&[NULL]));
+
+		let leaf = [0x82, 0x43, 0x20, 0x01, 0x23, 0x43, b'a', b'b', b'c'];
+		let root = trie_root_with_stream::<KeccakHasher, CborTrieStream, _, _, _>(vec![(vec![0x01, 0x23], b"abc")]);
+		assert_eq!(root, KeccakHasher::hash(&leaf));
+
+		// extension with partial key 123 to an inline branch with leaves at 4 and 5
+		let mut branch = vec![0x91, NULL, NULL, NULL, NULL];
+		branch.extend_from_slice(&[0x82, 0x41, 0x20, 0x41, b'a', 0x82, 0x41, 0x20, 0x41, b'b']);
+		branch.extend_from_slice(&[NULL; 11]);
+		let mut extension = vec![0x82, 0x42, 0x11, 0x23];
+		extension.extend_from_slice(&branch);
+		let input = vec![(vec![0x12, 0x34], b"a"), (vec![0x12, 0x35], b"b")];
+		let root = trie_root_with_stream::<KeccakHasher, CborTrieStream, _, _, _>(input.clone());
+		assert_eq!(root, KeccakHasher::hash(&extension));
+
+		// with 20 byte hashes, the 26 byte branch is referenced by hash
+		let mut extension = vec![0x82, 0x42, 0x11, 0x23, 0x54];
+		extension.extend_from_slice(&Keccak160::hash(&branch));
+		let root = trie_root_with_stream::<Keccak160, CborTrieStream, _, _, _>(input);
+		assert_eq!(root, Keccak160::hash(&extension));
+	}
+
+	/// Keccak-256 truncated to 20 bytes.
+	struct Keccak160;
+
+	impl Hasher for Keccak160 {
+		type Out = [u8; 20];
+		type StdHasher = <KeccakHasher as Hasher>::StdHasher;
+		const LENGTH: usize = 20;
+
+		fn hash(x: &[u8]) -> [u8; 20] {
+			let mut out = [0; 20];
+			out.copy_from_slice(&KeccakHasher::hash(x)[..20]);
+			out
+		}
+	}
+}
@@ -0,0 +1,425 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Node encoding of Substrate's trie.
+
+use crate::stream::{TrieStream, Value};
+use crate::value::AsValue;
+use crate::{hash_sorted, sorted_nibbles, Layout};
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use hash_db::Hasher;
+
+/// Header of the empty node.
+const EMPTY_TRIE: u8 = 0;
+/// Header prefix of a leaf with its value inline.
+const LEAF: u8 = 0b01 << 6;
+/// Header prefix of a branch without a value.
+const BRANCH_WITHOUT_VALUE: u8 = 0b10 << 6;
+/// Header prefix of a branch with its value inline.
+const BRANCH_WITH_VALUE: u8 = 0b11 << 6;
+/// Header prefix of a leaf storing the hash of its value, since state version 1.
+const HASHED_VALUE_LEAF: u8 = 0b001 << 5;
+/// Header prefix of a branch storing the hash of its value, since state version 1.
+const HASHED_VALUE_BRANCH: u8 = 0b0001 << 4;
+
+/// Header of a node of Substrate's trie, telling its kind and the number of nibbles of its
+/// partial key.
+///
+/// The high bits of the first byte tell the kind, the low bits hold the number of nibbles. If
+/// they are all set, the number continues in the following bytes: bytes of 255 ended by a
+/// smaller byte, which are added to the count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeHeader {
+	/// The empty node, i.e. the root of the empty trie.
+	Null,
+	/// Leaf with its value inline.
+	Leaf(usize),
+	/// Branch, with or without an inline value.
+	Branch(bool, usize),
+	/// Leaf storing the hash of its value.
+	HashedValueLeaf(usize),
+	/// Branch storing the hash of its value.
+	HashedValueBranch(usize),
+}
+
+impl NodeHeader {
+	/// The prefix of the first byte and its number of bits, with the number of nibbles.
+	fn parts(&self) -> (u8, u32, usize) {
+		match *self {
+			NodeHeader::Null => (EMPTY_TRIE, 8, 0),
+			NodeHeader::Leaf(nibbles) => (LEAF, 2, nibbles),
+			NodeHeader::Branch(false, nibbles) => (BRANCH_WITHOUT_VALUE, 2, nibbles),
+			NodeHeader::Branch(true, nibbles) => (BRANCH_WITH_VALUE, 2, nibbles),
+			NodeHeader::HashedValueLeaf(nibbles) => (HASHED_VALUE_LEAF, 3, nibbles),
+			NodeHeader::HashedValueBranch(nibbles) => (HASHED_VALUE_BRANCH, 4, nibbles),
+		}
+	}
+
+	/// Number of nibbles of the partial key.
+	pub fn nibbles(&self) -> usize {
+		self.parts().2
+	}
+
+	/// The same header, for a partial key of `nibbles` nibbles.
+	pub fn with_nibbles(&self, nibbles: usize) -> Self {
+		match *self {
+			NodeHeader::Null => NodeHeader::Null,
+			NodeHeader::Leaf(_) => NodeHeader::Leaf(nibbles),
+			NodeHeader::Branch(has_value, _) => NodeHeader::Branch(has_value, nibbles),
+			NodeHeader::HashedValueLeaf(_) => NodeHeader::HashedValueLeaf(nibbles),
+			NodeHeader::HashedValueBranch(_) => NodeHeader::HashedValueBranch(nibbles),
+		}
+	}
+
+	/// Appends the encoded header to `out`.
+	pub fn encode_to(&self, out: &mut Vec<u8>) {
+		let (prefix, prefix_bits, nibbles) = self.parts();
+		if prefix_bits == 8 {
+			out.push(prefix);
+			return;
+		}
+		let max_value = 255 >> prefix_bits;
+		if nibbles < max_value as usize {
+			out.push(prefix | nibbles as u8);
+			return;
+		}
+		out.push(prefix | max_value);
+		let mut rest = nibbles - (max_value as usize - 1);
+		while rest >= 256 {
+			out.push(255);
+			rest -= 255;
+		}
+		out.push((rest - 1) as u8);
+	}
+
+	/// Decodes a header from the start of `input`, returning it with its encoded length.
+	///
+	/// Returns `None` if the input is truncated or the header is invalid.
+	pub fn decode(input: &[u8]) -> Option<(Self, usize)> {
+		let first = *input.first()?;
+		let (header, prefix_bits) = match first {
+			EMPTY_TRIE => return Some((NodeHeader::Null, 1)),
+			_ if first & 0b11 << 6 == LEAF => (NodeHeader::Leaf(0), 2),
+			_ if first & 0b11 << 6 == BRANCH_WITHOUT_VALUE => (NodeHeader::Branch(false, 0), 2),
+			_ if first & 0b11 << 6 == BRANCH_WITH_VALUE => (NodeHeader::Branch(true, 0), 2),
+			_ if first & 0b111 << 5 == HASHED_VALUE_LEAF => (NodeHeader::HashedValueLeaf(0), 3),
+			_ if first & 0b1111 << 4 == HASHED_VALUE_BRANCH => (NodeHeader::HashedValueBranch(0), 4),
+			_ => return None,
+		};
+		let max_value = 255u8 >> prefix_bits;
+		let mut nibbles = (first & max_value) as usize;
+		let mut len = 1;
+		if nibbles == max_value as usize {
+			nibbles -= 1;
+			loop {
+				let byte = *input.get(len)?;
+				len += 1;
+				if byte < 255 {
+					nibbles += byte as usize + 1;
+					break;
+				}
+				nibbles += 255;
+			}
+		}
+		Some((header.with_nibbles(nibbles), len))
+	}
+}
+
+/// Appends nibbles packed two per byte, with the first nibble alone if their number is odd.
+fn append_nibbles(out: &mut Vec<u8>, nibbles: &[u8]) {
+	let (first, pairs) = nibbles.split_at(nibbles.len() % 2);
+	out.extend_from_slice(first);
+	out.extend(pairs.chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+}
+
+/// Appends a length in SCALE compact encoding.
+fn append_compact_len(out: &mut Vec<u8>, len: usize) {
+	match len {
+		0..=0x3f => out.push((len as u8) << 2),
+		0x40..=0x3fff => out.extend_from_slice(&((len as u16) << 2 | 0b01).to_le_bytes()),
+		0x4000..=0x3fff_ffff => out.extend_from_slice(&((len as u32) << 2 | 0b10).to_le_bytes()),
+		_ => {
+			out.push(0b11);
+			out.extend_from_slice(&(len as u32).to_le_bytes());
+		}
+	}
+}
+
+/// Appends a value, prefixed by its length if it is stored inline.
+fn append_value(out: &mut Vec<u8>, value: Value) {
+	match value {
+		Value::Inline(value) => {
+			append_compact_len(out, value.len());
+			out.extend_from_slice(value);
+		}
+		Value::Hashed(hash) => out.extend_from_slice(hash),
+	}
+}
+
+/// Node encoding of Substrate's trie, in the state version `VERSION`.
+///
+/// The trie has no extension nodes, the shared prefix of their children is stored in the
+/// branch below instead. Children references are length prefixed and nodes shorter than the
+/// hash are inlined into their parents. Since state version 1 values may be stored by hash;
+/// use `trie_root_v1` for the roots of the Substrate state, which also replaces the values of
+/// at least 33 bytes by their hash.
+#[derive(Default)]
+pub struct CodecTrieStream<const VERSION: u8> {
+	buffer: Vec<u8>,
+	/// Partial key of a pending extension, stored in the branch appended next.
+	extension: Option<Vec<u8>>,
+	/// Present children of the branch being encoded, bit `i` for child `i`.
+	bitmap: u16,
+	/// References of the children of the branch being encoded.
+	children: Vec<u8>,
+}
+
+/// Node encoding of Substrate's trie in state version 0, where values are always inline.
+pub type CodecTrieStreamV0 = CodecTrieStream<0>;
+
+/// Node encoding of Substrate's trie in state version 1, where values may be stored by hash.
+pub type CodecTrieStreamV1 = CodecTrieStream<1>;
+
+impl<const VERSION: u8> CodecTrieStream<VERSION> {
+	/// Header of a node storing `value`.
+	fn header(value: Option<Value>, leaf: bool, nibbles: usize) -> NodeHeader {
+		match (value, leaf) {
+			(Some(Value::Hashed(_)), _) if VERSION == 0 => panic!("state version 0 doesn't store values by hash"),
+			(Some(Value::Hashed(_)), true) => NodeHeader::HashedValueLeaf(nibbles),
+			(Some(Value::Hashed(_)), false) => NodeHeader::HashedValueBranch(nibbles),
+			(_, true) => NodeHeader::Leaf(nibbles),
+			(value, false) => NodeHeader::Branch(value.is_some(), nibbles),
+		}
+	}
+}
+
+impl<const VERSION: u8> TrieStream for CodecTrieStream<VERSION> {
+	fn new() -> Self {
+		Self::default()
+	}
+
+	fn clear(&mut self) {
+		self.buffer.clear();
+		self.extension = None;
+		self.children.clear();
+	}
+
+	fn append_empty_data(&mut self) {
+		NodeHeader::Null.encode_to(&mut self.buffer);
+	}
+
+	fn append_leaf(&mut self, partial: &[u8], value: Value) {
+		Self::header(Some(value), true, partial.len()).encode_to(&mut self.buffer);
+		append_nibbles(&mut self.buffer, partial);
+		append_value(&mut self.buffer, value);
+	}
+
+	fn begin_extension(&mut self, partial: &[u8]) {
+		self.extension = Some(partial.to_vec());
+	}
+
+	fn begin_branch(&mut self, _has_value: bool, children: impl Iterator<Item = bool>) {
+		self.bitmap = children.enumerate().filter(|(_, present)| *present).fold(0, |bitmap, (i, _)| bitmap | 1 << i);
+	}
+
+	fn append_empty_child(&mut self) {}
+
+	fn end_branch(&mut self, value: Option<Value>) {
+		// branches are only given a partial key when they are the child of an extension
+		Self::header(value, false, 0).encode_to(&mut self.buffer);
+		self.buffer.extend_from_slice(&self.bitmap.to_le_bytes());
+		if let Some(value) = value {
+			append_value(&mut self.buffer, value);
+		}
+		self.buffer.extend_from_slice(&self.children);
+	}
+
+	fn has_extension_nodes(&self) -> bool {
+		false
+	}
+
+	fn append_substream<H: Hasher>(&mut self, child: &Self) -> Option<H::Out> {
+		let encoded = &child.buffer;
+		if let Some(partial) = self.extension.take() {
+			// the child is a branch without a partial key
+			let (header, len) = NodeHeader::decode(encoded).expect("the child was encoded by a codec stream; qed");
+			header.with_nibbles(partial.len()).encode_to(&mut self.buffer);
+			append_nibbles(&mut self.buffer, &partial);
+			self.buffer.extend_from_slice(&encoded[len..]);
+			return None;
+		}
+
+		if encoded.len() < H::LENGTH {
+			append_compact_len(&mut self.children, encoded.len());
+			self.children.extend_from_slice(encoded);
+			return None;
+		}
+		let hash = H::hash(encoded);
+		self.append_hashed_subtree(hash.as_ref());
+		Some(hash)
+	}
+
+	fn append_hashed_subtree(&mut self, hash: &[u8]) {
+		assert!(self.extension.is_none(), "the child of an extension is stored in the extension itself");
+		append_compact_len(&mut self.children, hash.len());
+		self.children.extend_from_slice(hash);
+	}
+
+	fn as_raw(&self) -> &[u8] {
+		&self.buffer
+	}
+}
+
+/// Prefix of the keys under which the roots of Substrate's default child tries are stored in the
+/// parent trie.
+pub const CHILD_STORAGE_KEY_PREFIX: &[u8] = b":child_storage:default:";
+
+/// Key under which the root of the child trie `storage_key` is stored in the parent trie.
+pub fn child_storage_key(storage_key: &[u8]) -> Vec<u8> {
+	let mut key = Vec::with_capacity(CHILD_STORAGE_KEY_PREFIX.len() + storage_key.len());
+	key.extend_from_slice(CHILD_STORAGE_KEY_PREFIX);
+	key.extend_from_slice(storage_key);
+	key
+}
+
+/// Value of the parent trie: one of its own items or the root of a child trie.
+enum ParentValue<B, O> {
+	Item(B),
+	ChildRoot(O),
+}
+
+impl<B: AsValue, O: AsRef<[u8]>> AsValue for ParentValue<B, O> {
+	fn with_value<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+		match self {
+			ParentValue::Item(value) => value.with_value(f),
+			ParentValue::ChildRoot(root) => f(root.as_ref()),
+		}
+	}
+}
+
+/// Generates the root of Substrate's trie (state version 1) for a vector of key-value tuples
+/// and the roots of its child tries, given by their unprefixed storage keys.
+///
+/// Every child root is stored under `child_storage_key` of its storage key, replacing an item of
+/// the same key. Empty child tries are left out, as Substrate removes them from the parent trie.
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use triehash::{child_storage_key, trie_root_v1, trie_root_v1_with_child_roots};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let child = vec![("doe", "reindeer"), ("dog", "puppy")];
+/// let mut child_roots = BTreeMap::new();
+/// child_roots.insert(b"animals".to_vec(), trie_root_v1::<KeccakHasher, _, _, _>(child));
+///
+/// let v = vec![(b"balance".to_vec(), vec![1u8; 16])];
+/// let root = trie_root_v1_with_child_roots::<KeccakHasher, _, _, _, _, _>(v.clone(), child_roots.clone());
+///
+/// let mut prefixed = v;
+/// prefixed.push((child_storage_key(b"animals"), child_roots[&b"animals"[..]].to_vec()));
+/// assert_eq!(root, trie_root_v1::<KeccakHasher, _, _, _>(prefixed));
+/// ```
+pub fn trie_root_v1_with_child_roots<H, I, A, B, C, K>(input: I, child_roots: C) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]>,
+	B: AsValue,
+	H: Hasher,
+	C: IntoIterator<Item = (K, H::Out)>,
+	K: AsRef<[u8]>,
+{
+	let empty = H::hash(&[EMPTY_TRIE]);
+	let items = input.into_iter().map(|(k, v)| (k.as_ref().to_vec(), ParentValue::Item(v)));
+	let children = child_roots
+		.into_iter()
+		.filter(|(_, root)| *root != empty)
+		.map(|(k, root)| (child_storage_key(k.as_ref()), ParentValue::ChildRoot(root)));
+	let input = sorted_nibbles(items.chain(children).collect());
+
+	hash_sorted::<H, CodecTrieStreamV1, _, _, _>(&input, Layout { value_threshold: Some(33), ..Layout::HEX }, &mut ())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{append_compact_len, child_storage_key, trie_root_v1_with_child_roots, CodecTrieStreamV1, NodeHeader};
+	use crate::{trie_root_v1, trie_root_with_stream};
+	use hash_db::Hasher;
+	use keccak_hasher::KeccakHasher;
+
+	#[test]
+	fn long_headers_and_lengths() {
+		let mut out = Vec::new();
+		NodeHeader::Leaf(62).encode_to(&mut out);
+		NodeHeader::Leaf(70).encode_to(&mut out);
+		NodeHeader::Leaf(400).encode_to(&mut out);
+		assert_eq!(out, vec![0x7e, 0x7f, 0x07, 0x7f, 0xff, 0x52]);
+
+		let mut out = Vec::new();
+		append_compact_len(&mut out, 63);
+		append_compact_len(&mut out, 64);
+		append_compact_len(&mut out, 1 << 14);
+		assert_eq!(out, vec![0xfc, 0x01, 0x01, 0x02, 0x00, 0x01, 0x00]);
+	}
+
+	#[test]
+	fn headers_round_trip() {
+		for &nibbles in &[0, 1, 14, 15, 30, 31, 62, 63, 64, 316, 317, 318, 1000] {
+			let headers = vec![
+				NodeHeader::Leaf(nibbles),
+				NodeHeader::Branch(false, nibbles),
+				NodeHeader::Branch(true, nibbles),
+				NodeHeader::HashedValueLeaf(nibbles),
+				NodeHeader::HashedValueBranch(nibbles),
+			];
+			for header in headers {
+				let mut out = Vec::new();
+				header.encode_to(&mut out);
+				assert_eq!(NodeHeader::decode(&out), Some((header, out.len())));
+				assert_eq!(NodeHeader::decode(&out[..out.len() - 1]), None);
+			}
+		}
+		assert_eq!(NodeHeader::decode(&[0]), Some((NodeHeader::Null, 1)));
+		assert_eq!(NodeHeader::decode(&[0x01]), None);
+	}
+
+	#[test]
+	fn extensions_are_merged_into_branches() {
+		let input = vec![(vec![0x12, 0x34], b"a"), (vec![0x12, 0x35], b"b")];
+		// partial key 123, children 4 and 5 inlined
+		let root = [0x83, 0x01, 0x23, 0x30, 0x00, 0x0c, 0x40, 0x04, b'a', 0x0c, 0x40, 0x04, b'b'];
+		assert_eq!(trie_root_with_stream::<KeccakHasher, CodecTrieStreamV1, _, _, _>(input), KeccakHasher::hash(&root));
+	}
+
+	#[test]
+	fn long_values_are_hashed() {
+		let short = vec![7; 32];
+		let mut leaf = vec![0x42, 0xaa, 0x80];
+		leaf.extend_from_slice(&short);
+		assert_eq!(trie_root_v1::<KeccakHasher, _, _, _>(vec![(vec![0xaa], short)]), KeccakHasher::hash(&leaf));
+
+		let long = vec![7; 33];
+		let mut leaf = vec![0x22, 0xaa];
+		leaf.extend_from_slice(&KeccakHasher::hash(&long));
+		assert_eq!(trie_root_v1::<KeccakHasher, _, _, _>(vec![(vec![0xaa], long)]), KeccakHasher::hash(&leaf));
+	}
+
+	#[test]
+	fn empty_child_tries_are_left_out() {
+		let v = vec![(b"doe".to_vec(), b"reindeer".to_vec()), (child_storage_key(b"pets"), b"overwritten".to_vec())];
+		let empty = trie_root_v1::<KeccakHasher, Vec<(Vec<u8>, Vec<u8>)>, _, _>(Vec::new());
+		let pets = KeccakHasher::hash(b"pets");
+		let children = vec![(&b"pets"[..], pets), (b"none", empty)];
+		let expected = vec![(b"doe".to_vec(), b"reindeer".to_vec()), (child_storage_key(b"pets"), pets.to_vec())];
+		assert_eq!(
+			trie_root_v1_with_child_roots::<KeccakHasher, _, _, _, _, _>(v, children),
+			trie_root_v1::<KeccakHasher, _, _, _>(expected),
+		);
+	}
+}
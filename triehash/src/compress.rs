@@ -0,0 +1,343 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Compressing the encoding of nodes.
+
+use core::marker::PhantomData;
+
+use crate::stream::{TrieStream, Value};
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use hash_db::Hasher;
+
+/// Flag of nodes stored as they are.
+const STORED: u8 = 0;
+/// Flag of compressed nodes.
+const COMPRESSED: u8 = 1;
+
+/// Compression algorithm of node payloads.
+pub trait Compress {
+	/// Error decompressing a payload.
+	type Error;
+
+	/// Compresses `input`.
+	fn compress(input: &[u8]) -> Vec<u8>;
+
+	/// Decompresses the output of `compress`.
+	fn decompress(input: &[u8]) -> Result<Vec<u8>, Self::Error>;
+}
+
+/// Snappy compression, enabled by the `snap` feature.
+#[cfg(feature = "snap")]
+pub struct Snappy;
+
+#[cfg(feature = "snap")]
+impl Compress for Snappy {
+	type Error = snap::Error;
+
+	fn compress(input: &[u8]) -> Vec<u8> {
+		snap::raw::Encoder::new().compress_vec(input).expect("nodes are far shorter than the snappy limit; qed")
+	}
+
+	fn decompress(input: &[u8]) -> Result<Vec<u8>, Self::Error> {
+		snap::raw::Decoder::new().decompress_vec(input)
+	}
+}
+
+/// Largest ratio of the decompressed to the compressed length of an LZ4 block.
+#[cfg(feature = "lz4_flex")]
+const LZ4_MAX_RATIO: usize = 255;
+
+/// LZ4 block compression, enabled by the `lz4_flex` feature.
+#[cfg(feature = "lz4_flex")]
+pub struct Lz4;
+
+#[cfg(feature = "lz4_flex")]
+impl Compress for Lz4 {
+	type Error = lz4_flex::block::DecompressError;
+
+	fn compress(input: &[u8]) -> Vec<u8> {
+		lz4_flex::compress_prepend_size(input)
+	}
+
+	fn decompress(input: &[u8]) -> Result<Vec<u8>, Self::Error> {
+		use lz4_flex::block::{self, DecompressError};
+
+		// the decompressed size is allocated up front, a block expands at most 255 times so a
+		// corrupt size doesn't allocate up to 4 GiB
+		let (len, compressed) = block::uncompressed_size(input)?;
+		let max_len = compressed.len().saturating_mul(LZ4_MAX_RATIO);
+		if len > max_len {
+			return Err(DecompressError::OutputTooSmall { expected: len, actual: max_len });
+		}
+		block::decompress(compressed, len)
+	}
+}
+
+/// Error decoding a node encoded by `CompressedStream`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompressedNodeError<E> {
+	/// The node doesn't start with a valid flag.
+	InvalidFlag,
+	/// The compressed payload is invalid.
+	Decompress(E),
+}
+
+/// Wraps the stream `S`, compressing the encoding of nodes of at least `THRESHOLD` bytes with `C`
+/// unless that doesn't make them shorter, e.g. branches holding little more than child hashes.
+///
+/// Every node is prefixed by a flag telling whether the rest is the encoding of `S` as it is
+/// (`0`) or compressed (`1`), see `decompress_node`. The trie has the shape of the trie of `S`,
+/// but nodes are hashed as they are emitted, so every child is referenced by the hash of its
+/// compressed node rather than inlined, and the root differs from the root of `S`. Streams
+/// without extension nodes store the child of an extension in the extension itself, so that
+/// child isn't a node of its own.
+///
+/// ```
+/// use triehash::{decompress_node, trie_nodes, CompressedStream, Compress, RlpTrieStream};
+/// use keccak_hasher::KeccakHasher;
+///
+/// /// Run-length encoding of repeated bytes.
+/// struct Rle;
+///
+/// impl Compress for Rle {
+/// 	type Error = ();
+///
+/// 	fn compress(input: &[u8]) -> Vec<u8> {
+/// 		let mut out = Vec::new();
+/// 		for byte in input {
+/// 			match out.len() {
+/// 				len if len >= 2 && out[len - 1] == *byte && out[len - 2] < 255 => out[len - 2] += 1,
+/// 				_ => out.extend_from_slice(&[1, *byte]),
+/// 			}
+/// 		}
+/// 		out
+/// 	}
+///
+/// 	fn decompress(input: &[u8]) -> Result<Vec<u8>, ()> {
+/// 		Ok(input.chunks(2).flat_map(|run| vec![run[1]; run[0] as usize]).collect())
+/// 	}
+/// }
+///
+/// let v = vec![(vec![1u8], vec![0u8; 100]), (vec![2], vec![7; 100])];
+/// for (_, node) in trie_nodes::<KeccakHasher, CompressedStream<RlpTrieStream, Rle>, _, _, _>(v) {
+/// 	let encoded = decompress_node::<Rle>(&node).unwrap();
+/// 	assert!(node.len() <= encoded.len() + 1);
+/// }
+/// ```
+pub struct CompressedStream<S, C, const THRESHOLD: usize = 64> {
+	stream: S,
+	/// Flagged encoding of the node, once it is complete.
+	node: Vec<u8>,
+	/// Whether the node is an extension whose child is still to be appended.
+	in_extension: bool,
+	_compress: PhantomData<C>,
+}
+
+impl<S: TrieStream, C: Compress, const THRESHOLD: usize> CompressedStream<S, C, THRESHOLD> {
+	/// Flags and compresses the complete node.
+	fn complete(&mut self) {
+		let encoded = self.stream.as_raw();
+		self.node.clear();
+		if encoded.len() >= THRESHOLD {
+			let compressed = C::compress(encoded);
+			if compressed.len() < encoded.len() {
+				self.node.push(COMPRESSED);
+				self.node.extend_from_slice(&compressed);
+				return;
+			}
+		}
+		self.node.push(STORED);
+		self.node.extend_from_slice(encoded);
+	}
+}
+
+impl<S: TrieStream, C: Compress, const THRESHOLD: usize> TrieStream for CompressedStream<S, C, THRESHOLD> {
+	fn new() -> Self {
+		CompressedStream { stream: S::new(), node: Vec::new(), in_extension: false, _compress: PhantomData }
+	}
+
+	fn with_capacity(items: usize, key_len: usize) -> Self {
+		let stream = S::with_capacity(items, key_len);
+		CompressedStream { stream, node: Vec::new(), in_extension: false, _compress: PhantomData }
+	}
+
+	fn clear(&mut self) {
+		self.stream.clear();
+		self.node.clear();
+		self.in_extension = false;
+	}
+
+	fn append_empty_data(&mut self) {
+		self.stream.append_empty_data();
+		self.complete();
+	}
+
+	fn append_leaf(&mut self, partial: &[u8], value: Value) {
+		self.stream.append_leaf(partial, value);
+		self.complete();
+	}
+
+	fn begin_extension(&mut self, partial: &[u8]) {
+		self.stream.begin_extension(partial);
+		self.in_extension = true;
+	}
+
+	fn begin_branch(&mut self, has_value: bool, children: impl Iterator<Item = bool>) {
+		self.stream.begin_branch(has_value, children);
+	}
+
+	fn append_empty_child(&mut self) {
+		self.stream.append_empty_child();
+	}
+
+	fn end_branch(&mut self, value: Option<Value>) {
+		self.stream.end_branch(value);
+		self.complete();
+	}
+
+	fn has_value_slot(&self) -> bool {
+		self.stream.has_value_slot()
+	}
+
+	fn has_extension_nodes(&self) -> bool {
+		self.stream.has_extension_nodes()
+	}
+
+	fn append_substream<H: Hasher>(&mut self, child: &Self) -> Option<H::Out> {
+		if self.in_extension && !self.stream.has_extension_nodes() {
+			// the child of the extension is stored in the extension itself, which completes it
+			self.in_extension = false;
+			self.stream.append_substream::<H>(&child.stream);
+			self.complete();
+			return None;
+		}
+		let hash = H::hash(child.as_raw());
+		self.append_hashed_subtree(hash.as_ref());
+		Some(hash)
+	}
+
+	fn append_hashed_subtree(&mut self, hash: &[u8]) {
+		self.stream.append_hashed_subtree(hash);
+		if self.in_extension {
+			self.in_extension = false;
+			self.complete();
+		}
+	}
+
+	fn as_raw(&self) -> &[u8] {
+		&self.node
+	}
+}
+
+/// Recovers the encoding of a node emitted by `CompressedStream` with the compression `C`.
+///
+/// Children of the node are referenced by hash.
+pub fn decompress_node<C: Compress>(node: &[u8]) -> Result<Vec<u8>, CompressedNodeError<C::Error>> {
+	match node.split_first() {
+		Some((&STORED, encoded)) => Ok(encoded.to_vec()),
+		Some((&COMPRESSED, compressed)) => C::decompress(compressed).map_err(CompressedNodeError::Decompress),
+		_ => Err(CompressedNodeError::InvalidFlag),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{decompress_node, Compress, CompressedNodeError, CompressedStream};
+	use crate::{trie_nodes, CodecTrieStreamV1, RlpTrieStream};
+	use keccak_hasher::KeccakHasher;
+
+	/// Node encodings of a trie with long, repetitive values.
+	#[cfg(any(feature = "snap", feature = "lz4_flex"))]
+	fn nodes<C: Compress>() -> Vec<(Vec<u8>, Vec<u8>)>
+	where
+		C::Error: core::fmt::Debug,
+	{
+		let input = (0..100u32).map(|i| (i.to_be_bytes(), vec![i as u8; 200])).collect::<Vec<_>>();
+		let nodes = trie_nodes::<KeccakHasher, CompressedStream<RlpTrieStream, C>, _, _, _>(input.clone());
+		let root = crate::trie_root_with_stream::<KeccakHasher, CompressedStream<RlpTrieStream, C>, _, _, _>(input);
+		assert_eq!(nodes.last().map(|(hash, _)| *hash), Some(root));
+		nodes.into_iter().map(|(_, node)| (decompress_node::<C>(&node).unwrap(), node)).collect()
+	}
+
+	#[cfg(feature = "snap")]
+	#[test]
+	fn snappy_round_trips() {
+		let nodes = nodes::<super::Snappy>();
+		assert!(nodes.iter().all(|(encoded, node)| node.len() <= encoded.len() + 1));
+		assert!(nodes.iter().any(|(encoded, node)| node.len() < encoded.len()));
+	}
+
+	#[cfg(feature = "lz4_flex")]
+	#[test]
+	fn lz4_round_trips() {
+		let nodes = nodes::<super::Lz4>();
+		assert!(nodes.iter().all(|(encoded, node)| node.len() <= encoded.len() + 1));
+		assert!(nodes.iter().any(|(encoded, node)| node.len() < encoded.len()));
+	}
+
+	#[cfg(feature = "lz4_flex")]
+	#[test]
+	fn lz4_rejects_corrupt_sizes() {
+		use super::Lz4;
+
+		let mut node = vec![1];
+		node.extend_from_slice(&Lz4::compress(&[7; 1000]));
+		assert_eq!(decompress_node::<Lz4>(&node).unwrap(), vec![7; 1000]);
+
+		// a size of 4 GiB isn't allocated
+		node[1..5].copy_from_slice(&u32::MAX.to_le_bytes());
+		assert!(matches!(decompress_node::<Lz4>(&node), Err(CompressedNodeError::Decompress(_))));
+	}
+
+	/// Compression never making payloads shorter, and failing to decompress anything.
+	struct Broken;
+
+	impl Compress for Broken {
+		type Error = ();
+
+		fn compress(input: &[u8]) -> Vec<u8> {
+			input.to_vec()
+		}
+
+		fn decompress(_input: &[u8]) -> Result<Vec<u8>, ()> {
+			Err(())
+		}
+	}
+
+	#[test]
+	fn codec_extensions_are_merged_into_branches() {
+		let input = vec![(vec![0x12, 0x34], vec![1u8; 40]), (vec![0x12, 0x35], vec![2; 40])];
+		let nodes = trie_nodes::<KeccakHasher, CompressedStream<CodecTrieStreamV1, Broken>, _, _, _>(input.clone());
+		let root = crate::trie_root_with_stream::<KeccakHasher, CompressedStream<CodecTrieStreamV1, Broken>, _, _, _>(
+			input.clone(),
+		);
+		assert_eq!(nodes.last().map(|(hash, _)| *hash), Some(root));
+
+		// the root is the branch with the shared prefix, followed by the two leaves
+		let plain = trie_nodes::<KeccakHasher, CodecTrieStreamV1, _, _, _>(input);
+		assert_eq!(nodes.len(), plain.len());
+		let (_, node) = nodes.last().unwrap();
+		let (_, encoded) = plain.last().unwrap();
+		assert_eq!(node[0], 0);
+		assert_eq!(node[1..].len(), encoded.len());
+	}
+
+	#[test]
+	fn incompressible_nodes_are_stored() {
+		let input = (0..100u32).map(|i| (i.to_be_bytes(), vec![i as u8; 200])).collect::<Vec<_>>();
+		for (_, node) in trie_nodes::<KeccakHasher, CompressedStream<RlpTrieStream, Broken>, _, _, _>(input) {
+			assert_eq!(node[0], 0);
+			assert_eq!(decompress_node::<Broken>(&node), Ok(node[1..].to_vec()));
+		}
+		assert_eq!(decompress_node::<Broken>(&[1, 2, 3]), Err(CompressedNodeError::Decompress(())));
+		assert_eq!(decompress_node::<Broken>(&[2]), Err(CompressedNodeError::InvalidFlag));
+		assert_eq!(decompress_node::<Broken>(&[]), Err(CompressedNodeError::InvalidFlag));
+	}
+}
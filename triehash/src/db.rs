@@ -0,0 +1,327 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Storing the generated trie in a `HashDB`.
+
+use crate::{trie_root_with_visitor, NodeVisitor};
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use hash_db::{HashDB, Hasher, Prefix};
+
+/// `HashDB` which can insert and remove many values at once, e.g. in a single write batch.
+///
+/// The default implementations insert and remove the values one by one.
+pub trait BatchHashDB<H: Hasher, T>: HashDB<H, T> {
+	/// Inserts every value with its prefix, returning their keys in order.
+	fn insert_batch(&mut self, values: &[(Prefix, &[u8])]) -> Vec<H::Out> {
+		values.iter().map(|(prefix, value)| self.insert(*prefix, value)).collect()
+	}
+
+	/// Inserts every value with its key and prefix.
+	fn emplace_batch(&mut self, values: Vec<(H::Out, Prefix, T)>) {
+		for (key, prefix, value) in values {
+			self.emplace(key, prefix, value);
+		}
+	}
+
+	/// Removes every value with its key and prefix.
+	fn remove_batch(&mut self, keys: &[(H::Out, Prefix)]) {
+		for (key, prefix) in keys {
+			self.remove(key, *prefix);
+		}
+	}
+}
+
+/// Inserts every hashed node into the database.
+struct DbWriter<'a, H: Hasher, T> {
+	db: &'a mut dyn HashDB<H, T>,
+	/// Prepended to the prefix of every node.
+	keyspace: &'a [u8],
+}
+
+impl<'a, H: Hasher, T> NodeVisitor<H> for DbWriter<'a, H, T>
+where
+	T: for<'b> From<&'b [u8]>,
+{
+	fn visit(&mut self, path: &[u8], encoded: &[u8], hash: Option<&H::Out>) {
+		if let Some(hash) = hash {
+			let (packed, last) = node_prefix(self.keyspace, path);
+			self.db.emplace(*hash, (&packed, last), T::from(encoded));
+		}
+	}
+}
+
+/// Implements `hash_db::AsHashDB<$hasher, $value>` for the `HashDB` implementation `$db`, so
+/// that it can be passed where `&dyn HashDB` is expected.
+///
+/// Generic impls can't be provided for every `HashDB` outside of `hash_db`, as both the trait and
+/// the implementing type would be foreign. `verify_proof_in_db` has an example.
+#[macro_export]
+macro_rules! impl_as_hash_db {
+	($db:ty, $hasher:ty, $value:ty) => {
+		impl ::hash_db::AsHashDB<$hasher, $value> for $db {
+			fn as_hash_db(&self) -> &dyn ::hash_db::HashDB<$hasher, $value> {
+				self
+			}
+
+			fn as_hash_db_mut<'a>(&'a mut self) -> &'a mut (dyn ::hash_db::HashDB<$hasher, $value> + 'a) {
+				self
+			}
+		}
+	};
+}
+
+/// Implements `hash_db::AsPlainDB<$key, $value>` for the `PlainDB` implementation `$db`, like
+/// `impl_as_hash_db`.
+///
+/// ```
+/// use hash_db::{AsPlainDB, PlainDB};
+/// use std::collections::HashMap;
+///
+/// #[derive(Default)]
+/// struct Db(HashMap<Vec<u8>, Vec<u8>>);
+///
+/// impl PlainDB<Vec<u8>, Vec<u8>> for Db {
+/// 	fn get(&self, key: &Vec<u8>) -> Option<Vec<u8>> {
+/// 		self.0.get(key).cloned()
+/// 	}
+///
+/// 	fn contains(&self, key: &Vec<u8>) -> bool {
+/// 		self.0.contains_key(key)
+/// 	}
+///
+/// 	fn emplace(&mut self, key: Vec<u8>, value: Vec<u8>) {
+/// 		self.0.insert(key, value);
+/// 	}
+///
+/// 	fn remove(&mut self, key: &Vec<u8>) {
+/// 		self.0.remove(key);
+/// 	}
+/// }
+///
+/// triehash::impl_as_plain_db!(Db, Vec<u8>, Vec<u8>);
+///
+/// let mut db = Db::default();
+/// db.as_plain_db_mut().emplace(b"doe".to_vec(), b"reindeer".to_vec());
+/// assert!(db.as_plain_db().contains(&b"doe".to_vec()));
+/// ```
+#[macro_export]
+macro_rules! impl_as_plain_db {
+	($db:ty, $key:ty, $value:ty) => {
+		impl ::hash_db::AsPlainDB<$key, $value> for $db {
+			fn as_plain_db(&self) -> &dyn ::hash_db::PlainDB<$key, $value> {
+				self
+			}
+
+			fn as_plain_db_mut<'a>(&'a mut self) -> &'a mut (dyn ::hash_db::PlainDB<$key, $value> + 'a) {
+				self
+			}
+		}
+	};
+}
+
+/// Owned `Prefix`.
+pub(crate) type PackedPrefix = (Vec<u8>, Option<u8>);
+
+/// Collects every hashed node, to be inserted at once.
+struct BatchCollector<H: Hasher> {
+	/// Hash, packed prefix and encoding of the nodes.
+	nodes: Vec<(H::Out, PackedPrefix, Vec<u8>)>,
+}
+
+impl<H: Hasher> NodeVisitor<H> for BatchCollector<H> {
+	fn visit(&mut self, path: &[u8], encoded: &[u8], hash: Option<&H::Out>) {
+		if let Some(hash) = hash {
+			self.nodes.push((*hash, node_prefix(&[], path), encoded.to_vec()));
+		}
+	}
+}
+
+/// Packs the nibbles of a node path into the `Prefix` representation, after `keyspace`;
+/// the odd nibble is kept in the high bits of the last byte.
+pub(crate) fn node_prefix(keyspace: &[u8], path: &[u8]) -> PackedPrefix {
+	let packed = keyspace.iter().copied().chain(path.chunks_exact(2).map(|pair| pair[0] << 4 | pair[1])).collect();
+	let last = if path.len() % 2 == 1 { Some(path[path.len() - 1] << 4) } else { None };
+	(packed, last)
+}
+
+/// Generates a trie root hash for a vector of key-value tuples and inserts all nodes
+/// of the trie into `db`.
+///
+/// Every node referenced by hash (including the root) is inserted, keyed by its hash
+/// and prefixed with the path leading to it. Nodes shorter than a hash are inlined into
+/// their parents and are not inserted separately.
+pub fn trie_root_into_db<H, I, A, B, T>(input: I, db: &mut dyn HashDB<H, T>) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	H: Hasher,
+	T: for<'a> From<&'a [u8]>,
+{
+	trie_root_into_db_with_keyspace::<H, _, _, _, _>(input, db, &[])
+}
+
+/// Generates a trie root hash for a vector of key-value tuples and inserts all nodes
+/// of the trie into `db`, with `keyspace` prepended to their prefixes.
+///
+/// Several tries (e.g. child tries, or one per column) can then share a database without
+/// their nodes being mixed up, as long as their keyspaces are distinct and of equal length.
+pub fn trie_root_into_db_with_keyspace<H, I, A, B, T>(input: I, db: &mut dyn HashDB<H, T>, keyspace: &[u8]) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	H: Hasher,
+	T: for<'a> From<&'a [u8]>,
+{
+	trie_root_with_visitor::<H, _, _, _, _>(input, &mut DbWriter { db, keyspace })
+}
+
+/// Generates a trie root hash for a vector of key-value tuples and inserts all nodes
+/// of the trie into `db` with a single `emplace_batch` call.
+///
+/// The nodes are the same as those inserted by `trie_root_into_db`, but they are all held in
+/// memory until the root is known.
+pub fn trie_root_into_db_batched<H, I, A, B, T>(input: I, db: &mut dyn BatchHashDB<H, T>) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	H: Hasher,
+	T: for<'a> From<&'a [u8]>,
+{
+	let mut collector = BatchCollector::<H> { nodes: Vec::new() };
+	let root = trie_root_with_visitor::<H, _, _, _, _>(input, &mut collector);
+	let values = collector
+		.nodes
+		.iter()
+		.map(|(hash, (packed, last), encoded)| (*hash, (&packed[..], *last), T::from(encoded)))
+		.collect();
+	db.emplace_batch(values);
+	root
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		node_prefix, trie_root_into_db, trie_root_into_db_batched, trie_root_into_db_with_keyspace, BatchHashDB,
+	};
+	use crate::test_support::{indexed_input, test_inputs};
+	use crate::{trie_root, verify_proof_in_db};
+	use hash_db::{HashDB, Hasher, Prefix};
+	use keccak_hasher::KeccakHasher;
+	use std::collections::{BTreeMap, HashMap};
+
+	/// Nodes keyed by their prefix and hash, so that lookups with the wrong prefix fail.
+	#[derive(Default)]
+	struct TestDb {
+		nodes: HashMap<(Vec<u8>, [u8; 32]), Vec<u8>>,
+		batches: usize,
+	}
+
+	fn prefixed(prefix: Prefix) -> Vec<u8> {
+		prefix.0.iter().cloned().chain(prefix.1).collect()
+	}
+
+	impl HashDB<KeccakHasher, Vec<u8>> for TestDb {
+		fn get(&self, key: &[u8; 32], prefix: Prefix) -> Option<Vec<u8>> {
+			self.nodes.get(&(prefixed(prefix), *key)).cloned()
+		}
+
+		fn contains(&self, key: &[u8; 32], prefix: Prefix) -> bool {
+			self.nodes.contains_key(&(prefixed(prefix), *key))
+		}
+
+		fn insert(&mut self, prefix: Prefix, value: &[u8]) -> [u8; 32] {
+			let key = KeccakHasher::hash(value);
+			self.emplace(key, prefix, value.to_vec());
+			key
+		}
+
+		fn emplace(&mut self, key: [u8; 32], prefix: Prefix, value: Vec<u8>) {
+			self.nodes.insert((prefixed(prefix), key), value);
+		}
+
+		fn remove(&mut self, key: &[u8; 32], prefix: Prefix) {
+			self.nodes.remove(&(prefixed(prefix), *key));
+		}
+	}
+
+	impl BatchHashDB<KeccakHasher, Vec<u8>> for TestDb {
+		fn emplace_batch(&mut self, values: Vec<([u8; 32], Prefix, Vec<u8>)>) {
+			self.batches += 1;
+			for (key, prefix, value) in values {
+				self.emplace(key, prefix, value);
+			}
+		}
+	}
+
+	crate::impl_as_hash_db!(TestDb, KeccakHasher, Vec<u8>);
+
+	#[test]
+	fn inserts_hashed_nodes() {
+		for input in test_inputs::<KeccakHasher>() {
+			let mut db = TestDb::default();
+			let root = trie_root_into_db::<KeccakHasher, _, _, _, _>(input.clone(), &mut db);
+			assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(input.clone()));
+
+			assert!(db.contains(&root, (&[], None)));
+			for ((_, hash), node) in &db.nodes {
+				assert_eq!(&KeccakHasher::hash(node), hash);
+			}
+
+			// the database holds all nodes needed to prove any key, under the prefix they're looked up by
+			let db: &dyn HashDB<KeccakHasher, Vec<u8>> = &db;
+			for (key, value) in input.into_iter().collect::<BTreeMap<_, _>>() {
+				assert!(verify_proof_in_db::<KeccakHasher, _>(&root, &key, Some(&value), &db));
+			}
+		}
+	}
+
+	#[test]
+	fn inserts_nodes_in_one_batch() {
+		let input = indexed_input(100);
+		let (mut db, mut batched) = (TestDb::default(), TestDb::default());
+		let root = trie_root_into_db::<KeccakHasher, _, _, _, _>(input.clone(), &mut db);
+		assert_eq!(trie_root_into_db_batched::<KeccakHasher, _, _, _, _>(input, &mut batched), root);
+		assert_eq!(batched.nodes, db.nodes);
+		assert_eq!(batched.batches, 1);
+
+		let keys = batched.nodes.keys().cloned().collect::<Vec<_>>();
+		let keys = keys.iter().map(|(prefix, key)| (*key, (&prefix[..], None))).collect::<Vec<_>>();
+		batched.remove_batch(&keys);
+		assert!(batched.nodes.is_empty());
+
+		let hashes = batched.insert_batch(&[((&[], None), b"doe"), ((&[], None), b"reindeer")]);
+		assert_eq!(hashes, vec![KeccakHasher::hash(b"doe"), KeccakHasher::hash(b"reindeer")]);
+	}
+
+	#[test]
+	fn packs_node_prefix() {
+		assert_eq!(node_prefix(&[], &[]), (vec![], None));
+		assert_eq!(node_prefix(&[], &[1, 2, 3]), (vec![0x12], Some(0x30)));
+		assert_eq!(node_prefix(&[], &[1, 2, 3, 4]), (vec![0x12, 0x34], None));
+		assert_eq!(node_prefix(&[0xff], &[1, 2, 3]), (vec![0xff, 0x12], Some(0x30)));
+	}
+
+	#[test]
+	fn prefixes_nodes_with_keyspace() {
+		let input = indexed_input(100);
+		let mut db = TestDb::default();
+		let root = trie_root_into_db_with_keyspace::<KeccakHasher, _, _, _, _>(input.clone(), &mut db, b"child");
+		assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(input.clone()));
+		assert!(db.contains(&root, (b"child", None)));
+		assert!(db.nodes.keys().all(|(prefix, _)| prefix.starts_with(b"child")));
+
+		// the nodes aren't found without the keyspace
+		let db: &dyn HashDB<KeccakHasher, Vec<u8>> = &db;
+		assert!(!verify_proof_in_db::<KeccakHasher, _>(&root, &input[0].0, Some(&input[0].1), &db));
+	}
+}
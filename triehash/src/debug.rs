@@ -0,0 +1,215 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Human-readable rendering of tries.
+
+use core::fmt::Write;
+
+use crate::stream::{TrieStream, Value};
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+
+use hash_db::Hasher;
+
+/// Writes bytes in hex, prefixed by `0x`.
+fn write_hex(out: &mut String, bytes: &[u8]) {
+	out.push_str("0x");
+	for byte in bytes {
+		let _ = write!(out, "{:02x}", byte);
+	}
+}
+
+/// Writes a partial key in brackets, one hex digit per symbol.
+fn write_partial(out: &mut String, partial: &[u8]) {
+	out.push('[');
+	for digit in partial {
+		let _ = write!(out, "{:x}", digit);
+	}
+	out.push(']');
+}
+
+/// Writes a value, `hash 0x...` if it is stored by hash.
+fn write_value(out: &mut String, value: Value) {
+	if let Value::Hashed(_) = value {
+		out.push_str("hash ");
+	}
+	write_hex(out, value.as_bytes());
+}
+
+/// Wraps the stream `S`, rendering the structure of the trie as indented text.
+///
+/// Every node takes a line with its kind and partial key (one hex digit per symbol), followed
+/// by its children, each introduced by its slot (`->` below extensions) and its reference
+/// (`inline` or its hash) and indented below. Branch values come after the children. The
+/// encoding of `S` is unchanged, so the rendering of two formats can be compared node by node
+/// when they disagree on a root.
+///
+/// ```
+/// use triehash::{trie_root_stream, DebugTrieStream, RlpTrieStream};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy")];
+/// let stream = trie_root_stream::<KeccakHasher, DebugTrieStream<RlpTrieStream>, _, _, _>(v);
+/// assert_eq!(
+/// 	stream.text(),
+/// 	"extension [646f6]
+///   -> 0xf226ef598ed9195f2211546cf5b2860dc27b4da07ff7ab5108ee68107f0c9d00
+///     branch
+///       5: inline
+///         leaf [] = 0x7265696e64656572
+///       7: inline
+///         leaf [] = 0x7075707079
+/// ",
+/// );
+/// ```
+pub struct DebugTrieStream<S> {
+	stream: S,
+	text: String,
+	/// Slot of the next child of a branch, `None` below extensions.
+	next_slot: Option<usize>,
+}
+
+impl<S> DebugTrieStream<S> {
+	/// Rendering of the node and the nodes below it.
+	pub fn text(&self) -> &str {
+		&self.text
+	}
+
+	/// Writes the line introducing a child and its reference.
+	fn write_child(&mut self, reference: Option<&[u8]>) {
+		match self.next_slot {
+			Some(ref mut slot) => {
+				let _ = write!(self.text, "  {:x}: ", slot);
+				*slot += 1;
+			}
+			None => self.text.push_str("  -> "),
+		}
+		match reference {
+			Some(hash) => write_hex(&mut self.text, hash),
+			None => self.text.push_str("inline"),
+		}
+		self.text.push('\n');
+	}
+}
+
+impl<S: TrieStream> TrieStream for DebugTrieStream<S> {
+	fn new() -> Self {
+		DebugTrieStream { stream: S::new(), text: String::new(), next_slot: None }
+	}
+
+	fn with_capacity(items: usize, key_len: usize) -> Self {
+		DebugTrieStream { stream: S::with_capacity(items, key_len), text: String::new(), next_slot: None }
+	}
+
+	fn clear(&mut self) {
+		self.stream.clear();
+		self.text.clear();
+		self.next_slot = None;
+	}
+
+	fn append_empty_data(&mut self) {
+		self.text.push_str("empty\n");
+		self.stream.append_empty_data();
+	}
+
+	fn append_leaf(&mut self, partial: &[u8], value: Value) {
+		self.text.push_str("leaf ");
+		write_partial(&mut self.text, partial);
+		self.text.push_str(" = ");
+		write_value(&mut self.text, value);
+		self.text.push('\n');
+		self.stream.append_leaf(partial, value);
+	}
+
+	fn begin_extension(&mut self, partial: &[u8]) {
+		self.text.push_str("extension ");
+		write_partial(&mut self.text, partial);
+		self.text.push('\n');
+		self.stream.begin_extension(partial);
+	}
+
+	fn begin_branch(&mut self, has_value: bool, children: impl Iterator<Item = bool>) {
+		self.text.push_str("branch\n");
+		self.next_slot = Some(0);
+		self.stream.begin_branch(has_value, children);
+	}
+
+	fn append_empty_child(&mut self) {
+		if let Some(ref mut slot) = self.next_slot {
+			*slot += 1;
+		}
+		self.stream.append_empty_child();
+	}
+
+	fn end_branch(&mut self, value: Option<Value>) {
+		if let Some(value) = value {
+			self.text.push_str("  value = ");
+			write_value(&mut self.text, value);
+			self.text.push('\n');
+		}
+		self.stream.end_branch(value);
+	}
+
+	fn has_value_slot(&self) -> bool {
+		self.stream.has_value_slot()
+	}
+
+	fn has_extension_nodes(&self) -> bool {
+		self.stream.has_extension_nodes()
+	}
+
+	fn append_substream<H: Hasher>(&mut self, child: &Self) -> Option<H::Out> {
+		let hash = self.stream.append_substream::<H>(&child.stream);
+		self.write_child(hash.as_ref().map(|hash| hash.as_ref()));
+		for line in child.text.lines() {
+			self.text.push_str("    ");
+			self.text.push_str(line);
+			self.text.push('\n');
+		}
+		hash
+	}
+
+	fn append_hashed_subtree(&mut self, hash: &[u8]) {
+		self.write_child(Some(hash));
+		self.stream.append_hashed_subtree(hash);
+	}
+
+	fn as_raw(&self) -> &[u8] {
+		self.stream.as_raw()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::DebugTrieStream;
+	use crate::{trie_root_stream, RlpTrieStream, TrieStream};
+	use keccak_hasher::KeccakHasher;
+
+	#[test]
+	fn renders_branch_values_and_keeps_the_encoding() {
+		let input = vec![(&b"do"[..], &b"verb"[..]), (b"dog", b"puppy"), (b"doge", b"coin")];
+		let stream = trie_root_stream::<KeccakHasher, DebugTrieStream<RlpTrieStream>, _, _, _>(input.clone());
+		let plain = trie_root_stream::<KeccakHasher, RlpTrieStream, _, _, _>(input);
+		assert_eq!(stream.as_raw(), plain.as_raw());
+		assert_eq!(
+			stream.text(),
+			"extension [646f]
+  -> 0xd43b87fdcd4217013ccc92d04662e12d36e4cc25dc690077cd821a1956fc3e36
+    branch
+      6: inline
+        extension [7]
+          -> inline
+            branch
+              6: inline
+                leaf [5] = 0x636f696e
+              value = 0x7075707079
+      value = 0x76657262
+"
+		);
+	}
+}
@@ -0,0 +1,239 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Node formats chosen at runtime.
+
+use core::any::Any;
+
+use crate::stream::{TrieStream, Value};
+use crate::value::AsValue;
+use crate::{hash256rlp_with, sorted_nibbles, Layout};
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, vec::Vec};
+
+use hash_db::Hasher;
+
+/// Object-safe version of `TrieStream`, for node formats chosen at runtime.
+///
+/// `DynStream` adapts every `TrieStream`, see `TrieStream` for the methods. Streams hash with
+/// `H` and only accept children of their own type.
+pub trait DynTrieStream<H: Hasher> {
+	/// Creates an empty stream of the same type.
+	fn new_stream(&self) -> Box<dyn DynTrieStream<H>>;
+
+	/// Empties the stream, so it can be reused for another node.
+	fn clear(&mut self);
+
+	/// Encodes the empty node, i.e. the root of an empty trie.
+	fn append_empty_data(&mut self);
+
+	/// Encodes a leaf node holding the rest of a key and its value.
+	fn append_leaf(&mut self, partial: &[u8], value: Value);
+
+	/// Starts encoding an extension node, its only child is appended next.
+	fn begin_extension(&mut self, partial: &[u8]);
+
+	/// Starts encoding a branch node.
+	fn begin_branch(&mut self, has_value: bool, children: &mut dyn Iterator<Item = bool>);
+
+	/// Appends an empty slot of a branch node.
+	fn append_empty_child(&mut self);
+
+	/// Completes a branch node, with the value of the key ending at the branch.
+	fn end_branch(&mut self, value: Option<Value>);
+
+	/// Whether branch nodes hold the value of the key ending at them.
+	fn has_value_slot(&self) -> bool;
+
+	/// Whether extension nodes are encoded as nodes of their own.
+	fn has_extension_nodes(&self) -> bool;
+
+	/// Appends a reference to the complete node encoded in `child`.
+	///
+	/// # Panics
+	///
+	/// Panics if `child` is a stream of another type.
+	fn append_substream(&mut self, child: &dyn DynTrieStream<H>) -> Option<H::Out>;
+
+	/// Appends a reference to a child node by its already computed `hash`.
+	fn append_hashed_subtree(&mut self, hash: &[u8]);
+
+	/// Encoding of the node.
+	fn as_raw(&self) -> &[u8];
+
+	/// The stream itself, to downcast children to the type of their parent.
+	fn as_any(&self) -> &dyn Any;
+}
+
+/// Adapts a `TrieStream` to `DynTrieStream`.
+pub struct DynStream<S>(S);
+
+impl<S: TrieStream> DynStream<S> {
+	/// Creates an empty stream.
+	pub fn new() -> Self {
+		DynStream(S::new())
+	}
+}
+
+impl<S: TrieStream> Default for DynStream<S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<H: Hasher, S: TrieStream + 'static> DynTrieStream<H> for DynStream<S> {
+	fn new_stream(&self) -> Box<dyn DynTrieStream<H>> {
+		Box::new(DynStream(S::new()))
+	}
+
+	fn clear(&mut self) {
+		self.0.clear()
+	}
+
+	fn append_empty_data(&mut self) {
+		self.0.append_empty_data()
+	}
+
+	fn append_leaf(&mut self, partial: &[u8], value: Value) {
+		self.0.append_leaf(partial, value)
+	}
+
+	fn begin_extension(&mut self, partial: &[u8]) {
+		self.0.begin_extension(partial)
+	}
+
+	fn begin_branch(&mut self, has_value: bool, children: &mut dyn Iterator<Item = bool>) {
+		self.0.begin_branch(has_value, children)
+	}
+
+	fn append_empty_child(&mut self) {
+		self.0.append_empty_child()
+	}
+
+	fn end_branch(&mut self, value: Option<Value>) {
+		self.0.end_branch(value)
+	}
+
+	fn has_value_slot(&self) -> bool {
+		self.0.has_value_slot()
+	}
+
+	fn has_extension_nodes(&self) -> bool {
+		self.0.has_extension_nodes()
+	}
+
+	fn append_substream(&mut self, child: &dyn DynTrieStream<H>) -> Option<H::Out> {
+		let child = child.as_any().downcast_ref::<Self>().expect("children have the type of their parent");
+		self.0.append_substream::<H>(&child.0)
+	}
+
+	fn append_hashed_subtree(&mut self, hash: &[u8]) {
+		self.0.append_hashed_subtree(hash)
+	}
+
+	fn as_raw(&self) -> &[u8] {
+		self.0.as_raw()
+	}
+
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+}
+
+/// Boxed stream, created from the stream given to `trie_root_with_dyn_stream`.
+struct BoxedStream<H: Hasher>(Box<dyn DynTrieStream<H>>);
+
+impl<H: Hasher> TrieStream for BoxedStream<H> {
+	fn new() -> Self {
+		unreachable!("boxed streams are created with `DynTrieStream::new_stream`")
+	}
+
+	fn clear(&mut self) {
+		self.0.clear()
+	}
+
+	fn append_empty_data(&mut self) {
+		self.0.append_empty_data()
+	}
+
+	fn append_leaf(&mut self, partial: &[u8], value: Value) {
+		self.0.append_leaf(partial, value)
+	}
+
+	fn begin_extension(&mut self, partial: &[u8]) {
+		self.0.begin_extension(partial)
+	}
+
+	fn begin_branch(&mut self, has_value: bool, mut children: impl Iterator<Item = bool>) {
+		self.0.begin_branch(has_value, &mut children)
+	}
+
+	fn append_empty_child(&mut self) {
+		self.0.append_empty_child()
+	}
+
+	fn end_branch(&mut self, value: Option<Value>) {
+		self.0.end_branch(value)
+	}
+
+	fn has_value_slot(&self) -> bool {
+		self.0.has_value_slot()
+	}
+
+	fn has_extension_nodes(&self) -> bool {
+		self.0.has_extension_nodes()
+	}
+
+	fn append_substream<H2: Hasher>(&mut self, child: &Self) -> Option<H2::Out> {
+		// streams of a trie are only given the hasher of the trie
+		self.0.append_substream(&*child.0).map(|hash| {
+			let mut out = H2::Out::default();
+			out.as_mut().copy_from_slice(hash.as_ref());
+			out
+		})
+	}
+
+	fn append_hashed_subtree(&mut self, hash: &[u8]) {
+		self.0.append_hashed_subtree(hash)
+	}
+
+	fn as_raw(&self) -> &[u8] {
+		self.0.as_raw()
+	}
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, with nodes encoded by streams
+/// of the type of `stream`.
+///
+/// The node format is chosen at runtime, e.g. to serve chains with different formats from one
+/// binary, at the cost of a dynamic call per encoding step. `stream` itself isn't used.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_v1, trie_root_with_dyn_stream};
+/// use triehash::{CodecTrieStreamV1, DynStream, DynTrieStream, RlpTrieStream};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+///
+/// let formats: Vec<Box<dyn DynTrieStream<KeccakHasher>>> =
+/// 	vec![Box::new(DynStream::<RlpTrieStream>::new()), Box::new(DynStream::<CodecTrieStreamV1>::new())];
+/// let roots = formats.iter().map(|stream| trie_root_with_dyn_stream(v.clone(), &**stream)).collect::<Vec<_>>();
+/// assert_eq!(roots, vec![trie_root::<KeccakHasher, _, _, _>(v.clone()), trie_root_v1::<KeccakHasher, _, _, _>(v)]);
+/// ```
+pub fn trie_root_with_dyn_stream<H, I, A, B>(input: I, stream: &dyn DynTrieStream<H>) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+{
+	let input = sorted_nibbles(input.into_iter().collect::<Vec<_>>());
+	let new_stream = |_, _| BoxedStream(stream.new_stream());
+	let root = hash256rlp_with::<H, _, _, _, _, _>(&input, 0, Layout::HEX, &mut (), new_stream);
+	H::hash(root.as_raw())
+}
@@ -0,0 +1,344 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Streams which can fail to encode a node.
+
+use core::cell::RefCell;
+
+use crate::stream::{TrieStream, Value};
+use crate::value::AsValue;
+use crate::{hash256rlp_with, sorted_nibbles, Layout, NodeVisitor};
+
+use hash_db::Hasher;
+
+/// Version of `TrieStream` whose methods can fail, e.g. for streams enforcing a maximum node
+/// size or writing the nodes to I/O.
+///
+/// See `TrieStream` for the methods. Building the trie stops at the first error.
+pub trait TryTrieStream {
+	/// Error encoding a node.
+	type Error;
+
+	/// Creates an empty stream.
+	fn new() -> Self;
+
+	/// Empties the stream, so it can be reused for another node.
+	fn clear(&mut self);
+
+	/// Encodes the empty node, i.e. the root of an empty trie.
+	fn append_empty_data(&mut self) -> Result<(), Self::Error>;
+
+	/// Encodes a leaf node holding the rest of a key and its value.
+	fn append_leaf(&mut self, partial: &[u8], value: Value) -> Result<(), Self::Error>;
+
+	/// Starts encoding an extension node, its only child is appended next.
+	fn begin_extension(&mut self, partial: &[u8]) -> Result<(), Self::Error>;
+
+	/// Starts encoding a branch node.
+	fn begin_branch(&mut self, has_value: bool, children: impl Iterator<Item = bool>) -> Result<(), Self::Error>;
+
+	/// Appends an empty slot of a branch node.
+	fn append_empty_child(&mut self) -> Result<(), Self::Error>;
+
+	/// Completes a branch node, with the value of the key ending at the branch.
+	fn end_branch(&mut self, value: Option<Value>) -> Result<(), Self::Error>;
+
+	/// Whether branch nodes hold the value of the key ending at them. Defaults to true.
+	fn has_value_slot(&self) -> bool {
+		true
+	}
+
+	/// Whether extension nodes are encoded as nodes of their own. Defaults to true.
+	fn has_extension_nodes(&self) -> bool {
+		true
+	}
+
+	/// Appends a reference to the complete node encoded in `child`.
+	///
+	/// Returns the hash the child is referenced by, or `None` if it is inlined.
+	fn append_substream<H: Hasher>(&mut self, child: &Self) -> Result<Option<H::Out>, Self::Error>;
+
+	/// Appends a reference to a child node by its already computed `hash`.
+	fn append_hashed_subtree(&mut self, hash: &[u8]) -> Result<(), Self::Error>;
+
+	/// Encoding of the node.
+	fn as_raw(&self) -> &[u8];
+}
+
+/// Adapts a `TryTrieStream` to `TrieStream`, recording the first error of any stream of the trie.
+struct Fallible<'a, S: TryTrieStream> {
+	stream: S,
+	error: &'a RefCell<Option<S::Error>>,
+}
+
+/// Runs `f` unless an error has been recorded already, recording its error.
+fn record<T, E>(error: &RefCell<Option<E>>, f: impl FnOnce() -> Result<T, E>) -> Option<T> {
+	if error.borrow().is_some() {
+		return None;
+	}
+	f().map_err(|e| *error.borrow_mut() = Some(e)).ok()
+}
+
+impl<'a, S: TryTrieStream> TrieStream for Fallible<'a, S> {
+	fn new() -> Self {
+		unreachable!("fallible streams are created by `try_hash256rlp_with`")
+	}
+
+	fn clear(&mut self) {
+		self.stream.clear()
+	}
+
+	fn append_empty_data(&mut self) {
+		let stream = &mut self.stream;
+		record(self.error, || stream.append_empty_data());
+	}
+
+	fn append_leaf(&mut self, partial: &[u8], value: Value) {
+		let stream = &mut self.stream;
+		record(self.error, || stream.append_leaf(partial, value));
+	}
+
+	fn begin_extension(&mut self, partial: &[u8]) {
+		let stream = &mut self.stream;
+		record(self.error, || stream.begin_extension(partial));
+	}
+
+	fn begin_branch(&mut self, has_value: bool, children: impl Iterator<Item = bool>) {
+		let stream = &mut self.stream;
+		record(self.error, || stream.begin_branch(has_value, children));
+	}
+
+	fn append_empty_child(&mut self) {
+		let stream = &mut self.stream;
+		record(self.error, || stream.append_empty_child());
+	}
+
+	fn end_branch(&mut self, value: Option<Value>) {
+		let stream = &mut self.stream;
+		record(self.error, || stream.end_branch(value));
+	}
+
+	fn has_value_slot(&self) -> bool {
+		self.stream.has_value_slot()
+	}
+
+	fn has_extension_nodes(&self) -> bool {
+		self.stream.has_extension_nodes()
+	}
+
+	fn append_substream<H: Hasher>(&mut self, child: &Self) -> Option<H::Out> {
+		let stream = &mut self.stream;
+		record(self.error, || stream.append_substream::<H>(&child.stream)).flatten()
+	}
+
+	fn append_hashed_subtree(&mut self, hash: &[u8]) {
+		let stream = &mut self.stream;
+		record(self.error, || stream.append_hashed_subtree(hash));
+	}
+
+	fn as_raw(&self) -> &[u8] {
+		self.stream.as_raw()
+	}
+}
+
+/// Stops building the trie once a stream failed.
+struct StopOnError<'a, E>(&'a RefCell<Option<E>>);
+
+impl<'a, H: Hasher, E> NodeVisitor<H> for StopOnError<'a, E> {
+	fn visit(&mut self, _path: &[u8], _encoded: &[u8], _hash: Option<&H::Out>) {}
+
+	fn is_cancelled(&mut self) -> bool {
+		self.0.borrow().is_some()
+	}
+}
+
+/// Encodes the root node of the trie of a vector of key-value tuples with the fallible stream
+/// `S`, returning the stream or the first error of any stream of the trie.
+///
+/// Like `trie_root_stream`, but building the trie stops as soon as a stream fails.
+pub fn try_trie_root_stream<H, S, I, A, B>(input: I) -> Result<S, S::Error>
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+	S: TryTrieStream,
+{
+	let input = sorted_nibbles(input.into_iter().collect());
+	try_hash256rlp_with::<H, S, _, _, _>(&input, S::new)
+}
+
+/// Encodes the node holding all `input` items (sorted nibbles) with streams created by
+/// `new_stream`, stopping at the first error.
+pub(crate) fn try_hash256rlp_with<H, S, A, B, F>(input: &[(A, B)], new_stream: F) -> Result<S, S::Error>
+where
+	A: AsRef<[u8]>,
+	B: AsValue,
+	H: Hasher,
+	S: TryTrieStream,
+	F: Fn() -> S,
+{
+	let error = RefCell::new(None);
+	let new_stream = |_, _| Fallible { stream: new_stream(), error: &error };
+	let Fallible { stream, .. } =
+		hash256rlp_with::<H, _, _, _, _, _>(input, 0, Layout::HEX, &mut StopOnError(&error), new_stream);
+	match error.into_inner() {
+		Some(e) => Err(e),
+		None => Ok(stream),
+	}
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, with the nodes encoded by the
+/// fallible stream `S`.
+///
+/// Returns the first error of any stream of the trie, e.g. of a stream rejecting nodes above a
+/// maximum size.
+///
+/// ```
+/// use triehash::{trie_root, try_trie_root, RlpTrieStream, TrieStream, TryTrieStream, Value};
+/// use hash_db::Hasher;
+/// use keccak_hasher::KeccakHasher;
+///
+/// /// RLP stream rejecting leaves with long values.
+/// struct ShortValues(RlpTrieStream);
+///
+/// impl TryTrieStream for ShortValues {
+/// 	type Error = usize;
+///
+/// 	fn new() -> Self { ShortValues(RlpTrieStream::new()) }
+/// 	fn clear(&mut self) { self.0.clear() }
+/// 	fn append_empty_data(&mut self) -> Result<(), usize> { Ok(self.0.append_empty_data()) }
+/// 	fn append_leaf(&mut self, partial: &[u8], value: Value) -> Result<(), usize> {
+/// 		match value.as_bytes().len() {
+/// 			len if len > 8 => Err(len),
+/// 			_ => Ok(self.0.append_leaf(partial, value)),
+/// 		}
+/// 	}
+/// 	fn begin_extension(&mut self, partial: &[u8]) -> Result<(), usize> { Ok(self.0.begin_extension(partial)) }
+/// 	fn begin_branch(&mut self, has_value: bool, children: impl Iterator<Item = bool>) -> Result<(), usize> {
+/// 		Ok(self.0.begin_branch(has_value, children))
+/// 	}
+/// 	fn append_empty_child(&mut self) -> Result<(), usize> { Ok(self.0.append_empty_child()) }
+/// 	fn end_branch(&mut self, value: Option<Value>) -> Result<(), usize> { Ok(self.0.end_branch(value)) }
+/// 	fn append_substream<H: Hasher>(&mut self, child: &Self) -> Result<Option<H::Out>, usize> {
+/// 		Ok(self.0.append_substream::<H>(&child.0))
+/// 	}
+/// 	fn append_hashed_subtree(&mut self, hash: &[u8]) -> Result<(), usize> { Ok(self.0.append_hashed_subtree(hash)) }
+/// 	fn as_raw(&self) -> &[u8] { self.0.as_raw() }
+/// }
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy")];
+/// let root = try_trie_root::<KeccakHasher, ShortValues, _, _, _>(v.clone());
+/// assert_eq!(root, Ok(trie_root::<KeccakHasher, _, _, _>(v)));
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "a very long value")];
+/// assert_eq!(try_trie_root::<KeccakHasher, ShortValues, _, _, _>(v), Err(17));
+/// ```
+pub fn try_trie_root<H, S, I, A, B>(input: I) -> Result<H::Out, S::Error>
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+	S: TryTrieStream,
+{
+	try_trie_root_stream::<H, S, _, _, _>(input).map(|stream| H::hash(stream.as_raw()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{try_trie_root, TryTrieStream};
+	use crate::test_support::indexed_input;
+	use crate::{trie_root, RlpTrieStream, TrieStream, Value};
+	use core::cell::Cell;
+	use hash_db::Hasher;
+	use keccak_hasher::KeccakHasher;
+
+	thread_local! {
+		static LEAVES: Cell<usize> = const { Cell::new(0) };
+	}
+
+	/// RLP stream rejecting branches longer than 100 bytes, counting the encoded leaves.
+	struct MaxBranchSize(RlpTrieStream);
+
+	impl TryTrieStream for MaxBranchSize {
+		type Error = usize;
+
+		fn new() -> Self {
+			MaxBranchSize(RlpTrieStream::new())
+		}
+
+		fn clear(&mut self) {
+			self.0.clear()
+		}
+
+		fn append_empty_data(&mut self) -> Result<(), usize> {
+			self.0.append_empty_data();
+			Ok(())
+		}
+
+		fn append_leaf(&mut self, partial: &[u8], value: Value) -> Result<(), usize> {
+			LEAVES.with(|leaves| leaves.set(leaves.get() + 1));
+			self.0.append_leaf(partial, value);
+			Ok(())
+		}
+
+		fn begin_extension(&mut self, partial: &[u8]) -> Result<(), usize> {
+			self.0.begin_extension(partial);
+			Ok(())
+		}
+
+		fn begin_branch(&mut self, has_value: bool, children: impl Iterator<Item = bool>) -> Result<(), usize> {
+			self.0.begin_branch(has_value, children);
+			Ok(())
+		}
+
+		fn append_empty_child(&mut self) -> Result<(), usize> {
+			self.0.append_empty_child();
+			Ok(())
+		}
+
+		fn end_branch(&mut self, value: Option<Value>) -> Result<(), usize> {
+			self.0.end_branch(value);
+			match self.0.as_raw().len() {
+				len if len > 100 => Err(len),
+				_ => Ok(()),
+			}
+		}
+
+		fn append_substream<H: Hasher>(&mut self, child: &Self) -> Result<Option<H::Out>, usize> {
+			Ok(self.0.append_substream::<H>(&child.0))
+		}
+
+		fn append_hashed_subtree(&mut self, hash: &[u8]) -> Result<(), usize> {
+			self.0.append_hashed_subtree(hash);
+			Ok(())
+		}
+
+		fn as_raw(&self) -> &[u8] {
+			self.0.as_raw()
+		}
+	}
+
+	#[test]
+	fn matches_trie_root_without_errors() {
+		let input = (0..3u8).map(|i| (vec![i], vec![i; 4])).collect::<Vec<_>>();
+		let root = try_trie_root::<KeccakHasher, MaxBranchSize, _, _, _>(input.clone());
+		assert_eq!(root, Ok(trie_root::<KeccakHasher, _, _, _>(input)));
+	}
+
+	#[test]
+	fn stops_at_first_error() {
+		LEAVES.with(|leaves| leaves.set(0));
+		let input = indexed_input(1000);
+		let root = try_trie_root::<KeccakHasher, MaxBranchSize, _, _, _>(input);
+		assert!(root.unwrap_err() > 100);
+		// only the leaves of the first branch are encoded
+		assert_eq!(LEAVES.with(Cell::get), 16);
+	}
+}
@@ -0,0 +1,217 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Hashing data given in parts.
+
+use core::marker::PhantomData;
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use hash_db::Hasher;
+
+/// State of a hash computation, fed with the data to hash in parts.
+pub trait HashBuilder {
+	/// Output of the hash.
+	type Out;
+
+	/// Appends `data` to the hashed data.
+	fn update(&mut self, data: &[u8]);
+
+	/// Completes the hash of all data given so far.
+	fn finalize(self) -> Self::Out;
+}
+
+/// `Hasher` which can hash data given in parts, e.g. a large value and its length prefix,
+/// without concatenating them into a single buffer first.
+///
+/// It is used by `Keyed` hashers and by `ssz_root`, which hashes every pair of chunks in two
+/// parts. Hashers without an incremental API implement it with `Buffered`.
+pub trait IncrementalHasher: Hasher {
+	/// State of a hash computation.
+	type Builder: HashBuilder<Out = Self::Out>;
+
+	/// Starts a hash computation.
+	fn builder() -> Self::Builder;
+
+	/// Hashes the concatenation of `parts`.
+	fn hash_parts<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> Self::Out {
+		let mut builder = Self::builder();
+		for part in parts {
+			builder.update(part);
+		}
+		builder.finalize()
+	}
+}
+
+/// `Hasher` which can hash many inputs at once, e.g. with SIMD instructions or on several
+/// threads.
+pub trait BatchHasher: Hasher {
+	/// Hashes every input, in order. Defaults to hashing them one by one.
+	fn hash_batch(inputs: &[&[u8]]) -> Vec<Self::Out> {
+		inputs.iter().map(|input| Self::hash(input)).collect()
+	}
+}
+
+/// `IncrementalHasher` which can be keyed, e.g. to separate the hashes of trie nodes from the
+/// hashes of other data.
+///
+/// Hashers with a key or personalization input should use it, like `blake2_hasher::Blake2Hasher`
+/// with its `triehash` feature.
+pub trait KeyedHasher: IncrementalHasher {
+	/// Starts a hash computation keyed with `key`.
+	fn keyed_builder(key: &[u8]) -> Self::Builder;
+}
+
+/// Key of the hashes of `Keyed` hashers.
+pub trait HashDomain {
+	/// Key passed to `KeyedHasher::keyed_builder`.
+	const KEY: &'static [u8];
+}
+
+/// `Hasher` hashing with `H` keyed with `D::KEY`, to compute domain-separated trie roots with
+/// e.g. `trie_root::<Keyed<H, D>, _, _, _>`.
+pub struct Keyed<H, D>(PhantomData<fn() -> (H, D)>);
+
+impl<H: KeyedHasher, D: HashDomain> Hasher for Keyed<H, D> {
+	type Out = H::Out;
+	type StdHasher = H::StdHasher;
+	const LENGTH: usize = H::LENGTH;
+
+	fn hash(x: &[u8]) -> H::Out {
+		let mut builder = H::keyed_builder(D::KEY);
+		builder.update(x);
+		builder.finalize()
+	}
+}
+
+/// Builder of any `Hasher`, which collects the parts into a buffer and hashes it once complete.
+///
+/// This makes hashers without an incremental API usable as `IncrementalHasher::Builder`.
+pub struct Buffered<H> {
+	buffer: Vec<u8>,
+	_hasher: PhantomData<H>,
+}
+
+impl<H> Default for Buffered<H> {
+	fn default() -> Self {
+		Buffered { buffer: Vec::new(), _hasher: PhantomData }
+	}
+}
+
+impl<H: Hasher> HashBuilder for Buffered<H> {
+	type Out = H::Out;
+
+	fn update(&mut self, data: &[u8]) {
+		self.buffer.extend_from_slice(data);
+	}
+
+	fn finalize(self) -> H::Out {
+		H::hash(&self.buffer)
+	}
+}
+
+/// State of a Keccak-256 computation, enabled by the `keccak` feature.
+#[cfg(feature = "keccak")]
+pub struct KeccakBuilder(tiny_keccak::Keccak);
+
+#[cfg(feature = "keccak")]
+impl HashBuilder for KeccakBuilder {
+	type Out = [u8; 32];
+
+	fn update(&mut self, data: &[u8]) {
+		tiny_keccak::Hasher::update(&mut self.0, data);
+	}
+
+	fn finalize(self) -> [u8; 32] {
+		let mut out = [0; 32];
+		tiny_keccak::Hasher::finalize(self.0, &mut out);
+		out
+	}
+}
+
+#[cfg(feature = "keccak")]
+impl IncrementalHasher for keccak_hasher::KeccakHasher {
+	type Builder = KeccakBuilder;
+
+	fn builder() -> KeccakBuilder {
+		KeccakBuilder(tiny_keccak::Keccak::v256())
+	}
+}
+
+#[cfg(feature = "keccak")]
+impl BatchHasher for keccak_hasher::KeccakHasher {}
+
+/// Keccak has no key input, so the key is prepended along with its length (4 bytes, big endian).
+#[cfg(feature = "keccak")]
+impl KeyedHasher for keccak_hasher::KeccakHasher {
+	fn keyed_builder(key: &[u8]) -> KeccakBuilder {
+		let mut builder = Self::builder();
+		builder.update(&(key.len() as u32).to_be_bytes());
+		builder.update(key);
+		builder
+	}
+}
+
+#[cfg(all(test, feature = "keccak"))]
+mod tests {
+	use super::{BatchHasher, Buffered, HashBuilder, HashDomain, IncrementalHasher, Keyed};
+	use crate::{sec_trie_root, sec_trie_root_batched, trie_root, trie_root_with_proof, verify_proof_batched};
+	use hash_db::Hasher;
+	use keccak_hasher::KeccakHasher;
+
+	struct TrieNode;
+
+	impl HashDomain for TrieNode {
+		const KEY: &'static [u8] = b"trie-node";
+	}
+
+	#[test]
+	fn parts_hash_like_their_concatenation() {
+		let parts = [&b"doe"[..], b"", b"reindeer", &[7; 300]];
+		let concatenated = parts.concat();
+		assert_eq!(KeccakHasher::hash_parts(parts.iter().copied()), KeccakHasher::hash(&concatenated));
+
+		let mut buffered = Buffered::<KeccakHasher>::default();
+		for part in &parts {
+			buffered.update(part);
+		}
+		assert_eq!(buffered.finalize(), KeccakHasher::hash(&concatenated));
+	}
+
+	#[test]
+	fn batch_hashes_in_order() {
+		let inputs = [&b"doe"[..], b"dog", b""];
+		let hashes = inputs.iter().map(|input| KeccakHasher::hash(input)).collect::<Vec<_>>();
+		assert_eq!(KeccakHasher::hash_batch(&inputs), hashes);
+
+		let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+		assert_eq!(
+			sec_trie_root_batched::<KeccakHasher, KeccakHasher, _, _, _>(v.clone()),
+			sec_trie_root::<KeccakHasher, _, _, _>(v.clone())
+		);
+
+		let (root, proof) = trie_root_with_proof::<KeccakHasher, _, _, _>(v, b"dog");
+		assert!(verify_proof_batched::<KeccakHasher, _>(&root, b"dog", Some(b"puppy"), &proof));
+		assert!(!verify_proof_batched::<KeccakHasher, _>(&root, b"dog", Some(b"kitten"), &proof));
+		assert!(verify_proof_batched::<KeccakHasher, _>(&root, b"do", None, &proof));
+	}
+
+	#[test]
+	fn keyed_hashes_prepend_the_key_length_and_key() {
+		let data = b"reindeer";
+		let prefixed = [&9u32.to_be_bytes()[..], b"trie-node", data].concat();
+		assert_eq!(Keyed::<KeccakHasher, TrieNode>::hash(data), KeccakHasher::hash(&prefixed));
+
+		let v = vec![("doe", "reindeer"), ("dog", "puppy")];
+		assert_ne!(
+			trie_root::<Keyed<KeccakHasher, TrieNode>, _, _, _>(v.clone()),
+			trie_root::<KeccakHasher, _, _, _>(v)
+		);
+	}
+}
@@ -0,0 +1,157 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Hex-prefix encoding of the partial keys stored in trie nodes.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+use core::iter::once;
+
+/// Hex-prefix Notation. First nibble has flags: oddness = 2^0 & termination = 2^1.
+///
+/// The "termination marker" and "leaf-node" specifier are completely equivalent.
+///
+/// Input values are in range `[0, 0xf]`.
+///
+/// ```markdown
+///  [0,0,1,2,3,4,5]   0x10012345 // 7 > 4
+///  [0,1,2,3,4,5]     0x00012345 // 6 > 4
+///  [1,2,3,4,5]       0x112345   // 5 > 3
+///  [0,0,1,2,3,4]     0x00001234 // 6 > 3
+///  [0,1,2,3,4]       0x101234   // 5 > 3
+///  [1,2,3,4]         0x001234   // 4 > 3
+///  [0,0,1,2,3,4,5,T] 0x30012345 // 7 > 4
+///  [0,0,1,2,3,4,T]   0x20001234 // 6 > 4
+///  [0,1,2,3,4,5,T]   0x20012345 // 6 > 4
+///  [1,2,3,4,5,T]     0x312345   // 5 > 3
+///  [1,2,3,4,T]       0x201234   // 4 > 3
+/// ```
+pub fn hex_prefix_encode<'a>(nibbles: &'a [u8], leaf: bool) -> impl Iterator<Item = u8> + 'a {
+	let inlen = nibbles.len();
+	let oddness_factor = inlen % 2;
+
+	let first_byte = {
+		let mut bits = ((inlen as u8 & 1) + (2 * leaf as u8)) << 4;
+		if oddness_factor == 1 {
+			bits += nibbles[0];
+		}
+		bits
+	};
+	once(first_byte).chain(nibbles[oddness_factor..].chunks(2).map(|ch| ch[0] << 4 | ch[1]))
+}
+
+/// Tells whether a hex-prefix encoded partial key belongs to a leaf, from its flags.
+///
+/// Leaves and extension nodes of the Ethereum trie are both lists of two items, this tells
+/// them apart. Returns false for an empty partial key, which isn't hex-prefix encoded.
+///
+/// ```
+/// use triehash::hex_prefix::{hex_prefix_encode, hex_prefix_is_leaf};
+///
+/// let leaf = hex_prefix_encode(&[1, 2, 3], true).collect::<Vec<_>>();
+/// let extension = hex_prefix_encode(&[1, 2, 3], false).collect::<Vec<_>>();
+/// assert!(hex_prefix_is_leaf(&leaf));
+/// assert!(!hex_prefix_is_leaf(&extension));
+/// ```
+pub fn hex_prefix_is_leaf(encoded: &[u8]) -> bool {
+	encoded.first().is_some_and(|flags| flags & 0x20 != 0)
+}
+
+/// Inverse of `hex_prefix_encode`, returns the nibbles and whether they belong to a leaf.
+///
+/// Returns `None` if `encoded` is empty, has flags other than oddness and termination, or pads
+/// an even number of nibbles with a nonzero nibble.
+///
+/// ```
+/// use triehash::hex_prefix::hex_prefix_decode;
+///
+/// assert_eq!(hex_prefix_decode(&[0x31, 0x23]), Some((vec![1, 2, 3], true)));
+/// assert_eq!(hex_prefix_decode(&[0x00, 0x12]), Some((vec![1, 2], false)));
+/// assert_eq!(hex_prefix_decode(&[0x40, 0x12]), None);
+/// assert_eq!(hex_prefix_decode(&[0x01, 0x12]), None);
+/// assert_eq!(hex_prefix_decode(&[]), None);
+/// ```
+pub fn hex_prefix_decode(encoded: &[u8]) -> Option<(Vec<u8>, bool)> {
+	let (&first, rest) = encoded.split_first()?;
+	let (flags, padding) = (first >> 4, first & 0x0F);
+	if flags > 3 {
+		return None;
+	}
+	let mut nibbles = Vec::with_capacity(encoded.len() * 2);
+	// odd number of nibbles, the first one is stored with the flags
+	if flags & 1 != 0 {
+		nibbles.push(padding);
+	} else if padding != 0 {
+		return None;
+	}
+	for &b in rest {
+		nibbles.push(b >> 4);
+		nibbles.push(b & 0x0F);
+	}
+	Some((nibbles, flags & 2 != 0))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{hex_prefix_decode, hex_prefix_encode};
+
+	#[test]
+	fn test_hex_prefix_encode() {
+		let v = vec![0, 0, 1, 2, 3, 4, 5];
+		let e = vec![0x10, 0x01, 0x23, 0x45];
+		let h = hex_prefix_encode(&v, false).collect::<Vec<_>>();
+		assert_eq!(h, e);
+
+		let v = vec![0, 1, 2, 3, 4, 5];
+		let e = vec![0x00, 0x01, 0x23, 0x45];
+		let h = hex_prefix_encode(&v, false).collect::<Vec<_>>();
+		assert_eq!(h, e);
+
+		let v = vec![0, 1, 2, 3, 4, 5];
+		let e = vec![0x20, 0x01, 0x23, 0x45];
+		let h = hex_prefix_encode(&v, true).collect::<Vec<_>>();
+		assert_eq!(h, e);
+
+		let v = vec![1, 2, 3, 4, 5];
+		let e = vec![0x31, 0x23, 0x45];
+		let h = hex_prefix_encode(&v, true).collect::<Vec<_>>();
+		assert_eq!(h, e);
+
+		let v = vec![1, 2, 3, 4];
+		let e = vec![0x00, 0x12, 0x34];
+		let h = hex_prefix_encode(&v, false).collect::<Vec<_>>();
+		assert_eq!(h, e);
+
+		let v = vec![4, 1];
+		let e = vec![0x20, 0x41];
+		let h = hex_prefix_encode(&v, true).collect::<Vec<_>>();
+		assert_eq!(h, e);
+	}
+
+	#[test]
+	fn decode_inverts_encode() {
+		for len in 0..8 {
+			let nibbles = (0..len).map(|i| (i * 7 % 16) as u8).collect::<Vec<_>>();
+			for &leaf in &[false, true] {
+				let encoded = hex_prefix_encode(&nibbles, leaf).collect::<Vec<_>>();
+				assert_eq!(hex_prefix_decode(&encoded), Some((nibbles.clone(), leaf)));
+			}
+		}
+	}
+
+	#[test]
+	fn decode_rejects_malformed_flags() {
+		for flags in 4..16u8 {
+			assert_eq!(hex_prefix_decode(&[flags << 4, 0x12]), None);
+		}
+		for padding in 1..16u8 {
+			assert_eq!(hex_prefix_decode(&[0x20 | padding]), None);
+		}
+		assert_eq!(hex_prefix_decode(&[]), None);
+	}
+}
@@ -0,0 +1,195 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Node counts and sizes of any node format.
+
+use crate::stream::{TrieStream, Value};
+
+use hash_db::Hasher;
+
+/// Number and total encoded size of the nodes of one kind.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct KindStats {
+	/// Number of nodes.
+	pub count: usize,
+	/// Total size of their encodings, whether they are inlined or not.
+	pub bytes: usize,
+}
+
+impl KindStats {
+	fn add(&mut self, other: &KindStats) {
+		self.count += other.count;
+		self.bytes += other.bytes;
+	}
+}
+
+/// Node counts and sizes by kind, as recorded by `InstrumentedStream`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NodeStats {
+	/// Empty nodes, i.e. the root of the empty trie.
+	pub empty: KindStats,
+	/// Leaf nodes.
+	pub leaf: KindStats,
+	/// Extension nodes.
+	pub extension: KindStats,
+	/// Branch nodes.
+	pub branch: KindStats,
+}
+
+impl NodeStats {
+	fn add(&mut self, other: &NodeStats) {
+		self.empty.add(&other.empty);
+		self.leaf.add(&other.leaf);
+		self.extension.add(&other.extension);
+		self.branch.add(&other.branch);
+	}
+}
+
+/// Kind of the node encoded in a stream.
+#[derive(Clone, Copy)]
+enum NodeKind {
+	Empty,
+	Leaf,
+	Extension,
+	Branch,
+}
+
+/// Wraps the stream `S`, recording the number and size of the encoded nodes by kind.
+///
+/// The statistics of a node include those of its children, so the stream of the root node
+/// holds them for the whole trie.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_stream, InstrumentedStream, RlpTrieStream, TrieStream};
+/// use hash_db::Hasher;
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+///
+/// let root = trie_root_stream::<KeccakHasher, InstrumentedStream<RlpTrieStream>, _, _, _>(v.clone());
+/// assert_eq!(KeccakHasher::hash(root.inner().as_raw()), trie_root::<KeccakHasher, _, _, _>(v));
+///
+/// let stats = root.stats();
+/// assert_eq!((stats.leaf.count, stats.extension.count, stats.branch.count), (2, 1, 2));
+/// ```
+pub struct InstrumentedStream<S> {
+	stream: S,
+	kind: Option<NodeKind>,
+	/// Statistics of the children appended so far.
+	children: NodeStats,
+}
+
+impl<S: TrieStream> InstrumentedStream<S> {
+	/// Statistics of the node and all nodes below it.
+	pub fn stats(&self) -> NodeStats {
+		let mut stats = self.children;
+		let own = KindStats { count: 1, bytes: self.stream.as_raw().len() };
+		match self.kind {
+			Some(NodeKind::Empty) => stats.empty.add(&own),
+			Some(NodeKind::Leaf) => stats.leaf.add(&own),
+			Some(NodeKind::Extension) => stats.extension.add(&own),
+			Some(NodeKind::Branch) => stats.branch.add(&own),
+			None => {}
+		}
+		stats
+	}
+
+	/// The wrapped stream.
+	pub fn inner(&self) -> &S {
+		&self.stream
+	}
+}
+
+impl<S: TrieStream> TrieStream for InstrumentedStream<S> {
+	fn new() -> Self {
+		InstrumentedStream { stream: S::new(), kind: None, children: NodeStats::default() }
+	}
+
+	fn with_capacity(items: usize, key_len: usize) -> Self {
+		InstrumentedStream { stream: S::with_capacity(items, key_len), kind: None, children: NodeStats::default() }
+	}
+
+	fn clear(&mut self) {
+		self.stream.clear();
+		self.kind = None;
+		self.children = NodeStats::default();
+	}
+
+	fn append_empty_data(&mut self) {
+		self.kind = Some(NodeKind::Empty);
+		self.stream.append_empty_data();
+	}
+
+	fn append_leaf(&mut self, partial: &[u8], value: Value) {
+		self.kind = Some(NodeKind::Leaf);
+		self.stream.append_leaf(partial, value);
+	}
+
+	fn begin_extension(&mut self, partial: &[u8]) {
+		self.kind = Some(NodeKind::Extension);
+		self.stream.begin_extension(partial);
+	}
+
+	fn begin_branch(&mut self, has_value: bool, children: impl Iterator<Item = bool>) {
+		self.kind = Some(NodeKind::Branch);
+		self.stream.begin_branch(has_value, children);
+	}
+
+	fn append_empty_child(&mut self) {
+		self.stream.append_empty_child();
+	}
+
+	fn end_branch(&mut self, value: Option<Value>) {
+		self.stream.end_branch(value);
+	}
+
+	fn has_value_slot(&self) -> bool {
+		self.stream.has_value_slot()
+	}
+
+	fn has_extension_nodes(&self) -> bool {
+		self.stream.has_extension_nodes()
+	}
+
+	fn append_substream<H: Hasher>(&mut self, child: &Self) -> Option<H::Out> {
+		self.children.add(&child.stats());
+		self.stream.append_substream::<H>(&child.stream)
+	}
+
+	fn append_hashed_subtree(&mut self, hash: &[u8]) {
+		self.stream.append_hashed_subtree(hash);
+	}
+
+	fn as_raw(&self) -> &[u8] {
+		self.stream.as_raw()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{InstrumentedStream, KindStats, NodeStats};
+	use crate::{trie_root_stream, trie_root_with_stats, RlpTrieStream};
+	use keccak_hasher::KeccakHasher;
+
+	#[test]
+	fn matches_trie_stats() {
+		let input = (0..200u32).map(|i| (i.to_be_bytes(), vec![i as u8; i as usize % 50])).collect::<Vec<_>>();
+		let stream = trie_root_stream::<KeccakHasher, InstrumentedStream<RlpTrieStream>, _, _, _>(input.clone());
+		let stats = stream.stats();
+		let (_, expected) = trie_root_with_stats::<KeccakHasher, _, _, _>(input);
+		assert_eq!(stats.leaf.count, expected.leaf_count);
+		assert_eq!(stats.extension.count, expected.extension_count);
+		assert_eq!(stats.branch.count, expected.branch_count);
+
+		let empty = trie_root_stream::<KeccakHasher, InstrumentedStream<RlpTrieStream>, _, _, _>(Vec::<(
+			Vec<u8>,
+			Vec<u8>,
+		)>::new());
+		assert_eq!(empty.stats(), NodeStats { empty: KindStats { count: 1, bytes: 1 }, ..Default::default() });
+	}
+}
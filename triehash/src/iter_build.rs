@@ -0,0 +1,196 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Trie root calculation over sorted input.
+//!
+//! Unlike `trie_root`, which needs the whole input in memory, this builder consumes
+//! the input one item at a time and only keeps the branches on the path of the last
+//! inserted key. Everything that can no longer change is hashed and dropped immediately.
+
+use core::marker::PhantomData;
+
+use crate::hex_prefix::hex_prefix_encode;
+use crate::shared_prefix_len;
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, vec::Vec};
+
+use hash_db::Hasher;
+use rlp::RlpStream;
+
+/// Branch node which may still receive children.
+struct Branch<B> {
+	/// Nibbles of the path leading to this branch.
+	path: Vec<u8>,
+	/// Encoded references (inline node or hash) to the children.
+	children: [Option<Vec<u8>>; 16],
+	value: Option<B>,
+}
+
+impl<B: AsRef<[u8]>> Branch<B> {
+	fn new(path: Vec<u8>, value: Option<B>) -> Self {
+		Branch { path, children: Default::default(), value }
+	}
+
+	/// Encodes `node` as a child of this branch and stores a reference to it.
+	fn attach<H: Hasher>(&mut self, node: Node<B>) {
+		let depth = self.path.len();
+		let nibble = node.path()[depth] as usize;
+		let encoded = node.encode::<H>(depth + 1);
+		self.children[nibble] = Some(node_reference::<H>(encoded));
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut stream = RlpStream::new_list(17);
+		for child in &self.children {
+			match child {
+				Some(reference) => stream.append_raw(reference, 1),
+				None => stream.append_empty_data(),
+			};
+		}
+		match self.value {
+			Some(ref value) => stream.append(&value.as_ref()),
+			None => stream.append_empty_data(),
+		};
+		stream.out().to_vec()
+	}
+}
+
+/// Complete node whose position in the trie is not known yet.
+enum Node<B> {
+	/// Full key (in nibbles) and value.
+	Leaf(Vec<u8>, B),
+	Branch(Box<Branch<B>>),
+}
+
+impl<B: AsRef<[u8]>> Node<B> {
+	fn path(&self) -> &[u8] {
+		match self {
+			Node::Leaf(key, _) => key,
+			Node::Branch(branch) => &branch.path,
+		}
+	}
+
+	/// Encodes the node, assuming its partial key starts at nibble `start`.
+	///
+	/// Branches deeper than `start` get wrapped into an extension node.
+	fn encode<H: Hasher>(self, start: usize) -> Vec<u8> {
+		match self {
+			Node::Leaf(key, value) => {
+				let mut stream = RlpStream::new_list(2);
+				stream.append_iter(hex_prefix_encode(&key[start..], true));
+				stream.append(&value.as_ref());
+				stream.out().to_vec()
+			}
+			Node::Branch(branch) => {
+				let encoded = branch.encode();
+				if branch.path.len() == start {
+					return encoded;
+				}
+				let mut stream = RlpStream::new_list(2);
+				stream.append_iter(hex_prefix_encode(&branch.path[start..], false));
+				stream.append_raw(&node_reference::<H>(encoded), 1);
+				stream.out().to_vec()
+			}
+		}
+	}
+}
+
+/// Returns the encoded node itself if it is short enough to be inlined, its rlp-encoded hash otherwise.
+pub(crate) fn node_reference<H: Hasher>(encoded: Vec<u8>) -> Vec<u8> {
+	match encoded.len() {
+		0..=31 => encoded,
+		_ => rlp::encode(&H::hash(&encoded).as_ref()).to_vec(),
+	}
+}
+
+/// Incremental trie builder fed with keys in ascending order.
+pub(crate) struct SortedTrieBuilder<H, B> {
+	/// Branches on the path of the last key, ordered by depth.
+	stack: Vec<Branch<B>>,
+	/// Last inserted key (in nibbles) and its value.
+	last: Option<(Vec<u8>, B)>,
+	_marker: PhantomData<H>,
+}
+
+impl<H: Hasher, B: AsRef<[u8]>> SortedTrieBuilder<H, B> {
+	pub(crate) fn new() -> Self {
+		SortedTrieBuilder { stack: Vec::new(), last: None, _marker: PhantomData }
+	}
+
+	/// Inserts the next key, given in nibbles. Inserting the same key twice replaces its value.
+	pub(crate) fn insert(&mut self, key: Vec<u8>, value: B) {
+		let (last_key, last_value) = match self.last.take() {
+			Some(last) => last,
+			None => {
+				self.last = Some((key, value));
+				return;
+			}
+		};
+
+		if last_key == key {
+			self.last = Some((key, value));
+			return;
+		}
+		debug_assert!(last_key < key, "trie input must be sorted");
+
+		let common = shared_prefix_len(&last_key, &key);
+		if common == last_key.len() {
+			// previous key is a prefix of the current one,
+			// so it becomes the value of a new branch
+			self.stack.push(Branch::new(last_key, Some(last_value)));
+		} else {
+			// every branch deeper than the shared prefix is complete now
+			let mut node = Node::Leaf(last_key, last_value);
+			loop {
+				let depth = self.stack.last().map(|branch| branch.path.len());
+				match depth {
+					Some(depth) if depth > common => {
+						let mut branch = self.stack.pop().expect("stack is not empty; qed");
+						branch.attach::<H>(node);
+						node = Node::Branch(Box::new(branch));
+					}
+					Some(depth) if depth == common => {
+						self.stack.last_mut().expect("stack is not empty; qed").attach::<H>(node);
+						break;
+					}
+					_ => {
+						let mut branch = Branch::new(node.path()[..common].to_vec(), None);
+						branch.attach::<H>(node);
+						self.stack.push(branch);
+						break;
+					}
+				}
+			}
+		}
+
+		self.last = Some((key, value));
+	}
+
+	/// Closes all remaining branches and returns the root hash.
+	pub(crate) fn finish(mut self) -> H::Out {
+		let mut node = match self.last.take() {
+			Some((key, value)) => Node::Leaf(key, value),
+			None => return H::hash(&rlp::NULL_RLP),
+		};
+		while let Some(mut branch) = self.stack.pop() {
+			branch.attach::<H>(node);
+			node = Node::Branch(Box::new(branch));
+		}
+		H::hash(&node.encode::<H>(0))
+	}
+}
+
+/// Splits the key into nibbles.
+pub(crate) fn key_nibbles(key: &[u8]) -> Vec<u8> {
+	let mut nibbles = Vec::with_capacity(key.len() * 2);
+	for &b in key {
+		nibbles.push(b >> 4);
+		nibbles.push(b & 0x0F);
+	}
+	nibbles
+}
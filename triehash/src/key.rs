@@ -0,0 +1,107 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Decomposition of keys into the symbols of their path in the trie.
+
+use core::iter::once;
+
+use crate::hex_prefix::hex_prefix_encode;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use rlp::RlpStream;
+
+/// Converts keys into the path leading to their value in the trie.
+///
+/// Every symbol of the path selects a child of a branch node. Items are ordered by their
+/// paths, so the encoding doesn't need to preserve the order of the keys.
+pub trait KeyEncode {
+	/// Number of children of a branch node. Symbols of the path are below `RADIX`,
+	/// which is at most 256.
+	const RADIX: usize;
+
+	/// Appends the path symbols of `key` to `path`.
+	fn encode_key(key: &[u8], path: &mut Vec<u8>);
+
+	/// Appends the partial path stored in a leaf (if `leaf` is true) or an extension node.
+	///
+	/// Defaults to the hex-prefix encoding, which needs symbols below 16.
+	fn append_partial(partial: &[u8], leaf: bool, stream: &mut RlpStream) {
+		stream.append_iter(hex_prefix_encode(partial, leaf));
+	}
+}
+
+/// Splits every byte into two nibbles, high nibble first. This is the Ethereum trie.
+pub struct HexNibbles;
+
+impl KeyEncode for HexNibbles {
+	const RADIX: usize = 16;
+
+	fn encode_key(key: &[u8], path: &mut Vec<u8>) {
+		path.reserve(key.len() * 2);
+		for &b in key {
+			path.push(b >> 4);
+			path.push(b & 0x0F);
+		}
+	}
+}
+
+/// Splits every byte into eight bits, most significant first.
+pub struct Bits;
+
+impl KeyEncode for Bits {
+	const RADIX: usize = 2;
+
+	fn encode_key(key: &[u8], path: &mut Vec<u8>) {
+		path.reserve(key.len() * 8);
+		for &b in key {
+			path.extend((0..8).rev().map(|shift| b >> shift & 1));
+		}
+	}
+}
+
+/// Uses every byte as a symbol, for radix-256 tries.
+///
+/// Partial paths are encoded as a flag byte, `0x20` for leaves and `0` for extensions,
+/// followed by the path.
+pub struct Bytes;
+
+impl KeyEncode for Bytes {
+	const RADIX: usize = 256;
+
+	fn encode_key(key: &[u8], path: &mut Vec<u8>) {
+		path.extend_from_slice(key);
+	}
+
+	fn append_partial(partial: &[u8], leaf: bool, stream: &mut RlpStream) {
+		let flag = if leaf { 0x20 } else { 0 };
+		stream.append_iter(once(flag).chain(partial.iter().cloned()));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Bits, Bytes, HexNibbles, KeyEncode};
+	use rlp::RlpStream;
+
+	#[test]
+	fn split_keys_into_symbols() {
+		let mut path = Vec::new();
+		HexNibbles::encode_key(&[0xa5], &mut path);
+		Bits::encode_key(&[0xa5], &mut path);
+		assert_eq!(path, vec![0xa, 0x5, 1, 0, 1, 0, 0, 1, 0, 1]);
+	}
+
+	#[test]
+	fn byte_partial_paths() {
+		let mut stream = RlpStream::new_list(2);
+		Bytes::append_partial(&[0xab, 0xcd], true, &mut stream);
+		Bytes::append_partial(&[0xab], false, &mut stream);
+		assert_eq!(stream.out(), vec![0xc7, 0x83, 0x20, 0xab, 0xcd, 0x82, 0x00, 0xab]);
+	}
+}
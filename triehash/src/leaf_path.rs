@@ -0,0 +1,194 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Positions of the values in the trie.
+
+use crate::stream::{TrieStream, Value};
+use crate::trie_root_stream;
+use crate::value::AsValue;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use hash_db::Hasher;
+
+/// Keys and their paths of child slots in the trie.
+pub type LeafPaths = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Wraps the stream `S`, recording the position of every value in the trie.
+///
+/// The position of a value is its path of child slots from the root: the slot taken in every
+/// branch, and slot 0 below extensions. Values of branches end their path at the branch.
+pub struct LeafPathStream<S> {
+	stream: S,
+	/// Keys (in nibbles) and paths of the values below the node, relative to the node.
+	leaves: LeafPaths,
+	/// Partial key of an extension node, or the slot of the next child of a branch.
+	next_child: NextChild,
+}
+
+/// Position of the next child appended to a node.
+enum NextChild {
+	None,
+	Extension(Vec<u8>),
+	Branch(u8),
+}
+
+impl<S> LeafPathStream<S> {
+	/// Keys (in nibbles) and paths of the values below the node, relative to the node.
+	pub fn leaves(&self) -> &[(Vec<u8>, Vec<u8>)] {
+		&self.leaves
+	}
+}
+
+impl<S: TrieStream> TrieStream for LeafPathStream<S> {
+	fn new() -> Self {
+		LeafPathStream { stream: S::new(), leaves: Vec::new(), next_child: NextChild::None }
+	}
+
+	fn with_capacity(items: usize, key_len: usize) -> Self {
+		LeafPathStream { stream: S::with_capacity(items, key_len), leaves: Vec::new(), next_child: NextChild::None }
+	}
+
+	fn clear(&mut self) {
+		self.stream.clear();
+		self.leaves.clear();
+		self.next_child = NextChild::None;
+	}
+
+	fn append_empty_data(&mut self) {
+		self.stream.append_empty_data();
+	}
+
+	fn append_leaf(&mut self, partial: &[u8], value: Value) {
+		self.leaves.push((partial.to_vec(), Vec::new()));
+		self.stream.append_leaf(partial, value);
+	}
+
+	fn begin_extension(&mut self, partial: &[u8]) {
+		self.next_child = NextChild::Extension(partial.to_vec());
+		self.stream.begin_extension(partial);
+	}
+
+	fn begin_branch(&mut self, has_value: bool, children: impl Iterator<Item = bool>) {
+		self.next_child = NextChild::Branch(0);
+		self.stream.begin_branch(has_value, children);
+	}
+
+	fn append_empty_child(&mut self) {
+		if let NextChild::Branch(ref mut slot) = self.next_child {
+			*slot += 1;
+		}
+		self.stream.append_empty_child();
+	}
+
+	fn end_branch(&mut self, value: Option<Value>) {
+		if value.is_some() {
+			self.leaves.push((Vec::new(), Vec::new()));
+		}
+		self.stream.end_branch(value);
+	}
+
+	fn has_value_slot(&self) -> bool {
+		self.stream.has_value_slot()
+	}
+
+	fn has_extension_nodes(&self) -> bool {
+		self.stream.has_extension_nodes()
+	}
+
+	fn append_substream<H: Hasher>(&mut self, child: &Self) -> Option<H::Out> {
+		let (key_prefix, slot) = match self.next_child {
+			NextChild::Extension(ref partial) => (&partial[..], 0),
+			NextChild::Branch(ref mut slot) => {
+				*slot += 1;
+				(&[*slot - 1][..], *slot - 1)
+			}
+			NextChild::None => (&[][..], 0),
+		};
+		for (key, path) in &child.leaves {
+			let key = key_prefix.iter().chain(key).cloned().collect();
+			let path = Some(slot).into_iter().chain(path.iter().cloned()).collect();
+			self.leaves.push((key, path));
+		}
+		self.stream.append_substream::<H>(&child.stream)
+	}
+
+	fn append_hashed_subtree(&mut self, hash: &[u8]) {
+		if let NextChild::Branch(ref mut slot) = self.next_child {
+			*slot += 1;
+		}
+		self.stream.append_hashed_subtree(hash);
+	}
+
+	fn as_raw(&self) -> &[u8] {
+		self.stream.as_raw()
+	}
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, with nodes encoded by `S`,
+/// together with the path of every key in the trie.
+///
+/// Paths are the child slots taken from the root to the node holding the value, see
+/// `LeafPathStream`. Keys are sorted and the position of a node in the trie doesn't depend on
+/// the node format, only on the shape of the trie.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_with_leaf_paths, RlpTrieStream};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+///
+/// let (root, paths) = trie_root_with_leaf_paths::<KeccakHasher, RlpTrieStream, _, _, _>(v.clone());
+/// assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(v));
+/// assert_eq!(
+/// 	paths,
+/// 	vec![
+/// 		(b"doe".to_vec(), vec![0, 5]),
+/// 		(b"dog".to_vec(), vec![0, 7]),
+/// 		(b"dogglesworth".to_vec(), vec![0, 7, 6]),
+/// 	]
+/// );
+/// ```
+pub fn trie_root_with_leaf_paths<H, S, I, A, B>(input: I) -> (H::Out, LeafPaths)
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+	S: TrieStream,
+{
+	let stream = trie_root_stream::<H, LeafPathStream<S>, _, _, _>(input);
+	let mut paths = stream
+		.leaves
+		.iter()
+		.map(|(nibbles, path)| (nibbles.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect::<Vec<_>>(), path.clone()))
+		.collect::<Vec<_>>();
+	// values of branches are recorded after the values below them
+	paths.sort();
+	(H::hash(stream.as_raw()), paths)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::trie_root_with_leaf_paths;
+	use crate::{trie_root, CodecTrieStreamV1, RlpTrieStream};
+	use keccak_hasher::KeccakHasher;
+
+	#[test]
+	fn paths_depend_on_the_shape_only() {
+		let input = (0..200u32).map(|i| ((i * 37).to_be_bytes()[..2 + i as usize % 3].to_vec(), vec![i as u8; 8]));
+		let input = input.collect::<std::collections::BTreeMap<_, _>>();
+
+		let (root, paths) = trie_root_with_leaf_paths::<KeccakHasher, RlpTrieStream, _, _, _>(input.clone());
+		assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(input.clone()));
+		assert_eq!(paths.iter().map(|(key, _)| key).collect::<Vec<_>>(), input.keys().collect::<Vec<_>>());
+
+		let (_, codec_paths) = trie_root_with_leaf_paths::<KeccakHasher, CodecTrieStreamV1, _, _, _>(input);
+		assert_eq!(codec_paths, paths);
+	}
+}
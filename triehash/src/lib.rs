@@ -17,24 +17,199 @@ extern crate alloc;
 
 #[cfg(feature = "std")]
 mod rstd {
-	pub use std::collections::BTreeMap;
+	pub use std::collections::{BTreeMap, BTreeSet};
 }
 
 #[cfg(not(feature = "std"))]
 mod rstd {
-	pub use alloc::collections::BTreeMap;
+	pub use alloc::collections::{BTreeMap, BTreeSet};
 	pub use alloc::vec::Vec;
 }
 
+mod binary;
+mod buffer;
+mod builder;
+mod cache;
+mod cancel;
+mod cbor;
+mod codec;
+mod compress;
+mod db;
+mod debug;
+mod dynamic;
+mod fallible;
+mod hasher;
+pub mod hex_prefix;
+mod instrumented;
+mod iter_build;
+mod key;
+mod leaf_path;
+mod limit;
+mod memo;
+mod offsets;
+mod partition;
+mod progress;
+mod proof;
+#[cfg(feature = "std")]
+mod reader;
+mod root;
+#[cfg(feature = "std")]
+mod sort;
+mod ssz;
+mod state;
+mod stats;
+mod stream;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+mod value;
+#[cfg(feature = "std")]
+mod writer;
+
 use core::cmp;
-use core::iter::once;
+use core::convert::TryInto;
 use rstd::*;
 
 use hash_db::Hasher;
-use rlp::RlpStream;
+use smallvec::SmallVec;
+
+pub use binary::BinaryTrieStream;
+pub use buffer::{trie_root_in_buffer, BufferOverflow};
+pub use builder::{trie_roots_diff, TrieRootBuilder};
+pub use cache::{trie_root_with_cache, TrieRootCache};
+pub use cancel::trie_root_with_cancel;
+pub use cbor::CborTrieStream;
+pub use codec::{
+	child_storage_key, trie_root_v1_with_child_roots, CodecTrieStream, CodecTrieStreamV0, CodecTrieStreamV1,
+	NodeHeader, CHILD_STORAGE_KEY_PREFIX,
+};
+#[cfg(feature = "lz4_flex")]
+pub use compress::Lz4;
+#[cfg(feature = "snap")]
+pub use compress::Snappy;
+pub use compress::{decompress_node, Compress, CompressedNodeError, CompressedStream};
+pub use db::{trie_root_into_db, trie_root_into_db_batched, trie_root_into_db_with_keyspace, BatchHashDB};
+pub use debug::DebugTrieStream;
+pub use dynamic::{trie_root_with_dyn_stream, DynStream, DynTrieStream};
+pub use fallible::{try_trie_root, try_trie_root_stream, TryTrieStream};
+#[cfg(feature = "keccak")]
+pub use hasher::KeccakBuilder;
+pub use hasher::{BatchHasher, Buffered, HashBuilder, HashDomain, IncrementalHasher, Keyed, KeyedHasher};
+pub use instrumented::{InstrumentedStream, KindStats, NodeStats};
+pub use key::{Bits, Bytes, HexNibbles, KeyEncode};
+pub use leaf_path::{trie_root_with_leaf_paths, LeafPathStream, LeafPaths};
+pub use limit::{trie_root_with_max_node_size, NodeTooLarge};
+pub use memo::MemoizingStream;
+pub use offsets::{unhashed_trie_with_offsets, NodeOffset};
+pub use partition::{combine_partial_roots, partial_root, PartialRoot};
+pub use progress::trie_root_with_progress;
+pub use proof::{
+	trie_root_with_key_proofs, trie_root_with_multi_proof, trie_root_with_proof, verify_proof, verify_proof_batched,
+	verify_proof_in_db, KeyProof,
+};
+#[cfg(feature = "std")]
+pub use reader::{trie_root_from_reader, write_record};
+pub use root::{DefaultTrieRoot, TrieRoot};
+#[cfg(feature = "std")]
+pub use sort::{trie_root_with_sort, SortStrategy};
+pub use ssz::{ssz_root, SszLimits};
+pub use state::{state_root, AccountEncoder, RlpAccountEncoder};
+pub use stats::{trie_root_with_stats, TrieStats};
+pub use stream::{RlpTrieStream, TrieStream, Value, HASH_LENGTH};
+pub use value::{AsValue, LazyValue};
+#[cfg(feature = "std")]
+pub use writer::write_trie_nodes;
+
+/// Nibbles of a key, stored inline for keys of up to 32 bytes (e.g. hashes).
+type Nibbles = SmallVec<[u8; 64]>;
+
+/// Returns the length of the longest common prefix of both slices.
+///
+/// Compares eight bytes at a time, keys of nibbles are long and this is on the hot path.
+fn shared_prefix_len(first: &[u8], second: &[u8]) -> usize {
+	const WORD: usize = core::mem::size_of::<u64>();
+	let len = cmp::min(first.len(), second.len());
+	let mut offset = 0;
+	for (f, s) in first[..len].chunks_exact(WORD).zip(second[..len].chunks_exact(WORD)) {
+		let diff = u64::from_le_bytes(f.try_into().expect("chunk has WORD bytes; qed"))
+			^ u64::from_le_bytes(s.try_into().expect("chunk has WORD bytes; qed"));
+		if diff != 0 {
+			// the first differing byte is the lowest one in little endian
+			return offset + diff.trailing_zeros() as usize / 8;
+		}
+		offset += WORD;
+	}
+	first[offset..len].iter().zip(&second[offset..len]).position(|(f, s)| f != s).map_or(len, |i| offset + i)
+}
+
+/// Number of nibbles shared by the keys of `input`, which is sorted by key.
+fn sorted_shared_prefix_len<A: AsRef<[u8]>, B>(input: &[(A, B)]) -> usize {
+	match (input.first(), input.last()) {
+		// keys are sorted, so the prefix shared by all of them is the one shared by the first and the last key
+		(Some((first, _)), Some((last, _))) => shared_prefix_len(first.as_ref(), last.as_ref()),
+		_ => 0,
+	}
+}
+
+/// Number of children of a branch node, i.e. how many bits of the key every level of the trie consumes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+	/// Binary trie, every level consumes a single bit of the key.
+	Binary,
+	/// Hexary trie, every level consumes a nibble of the key. This is the Ethereum trie.
+	Hex,
+	/// Radix-256 trie, every level consumes a byte of the key.
+	Byte,
+}
+
+/// Encoding of the indices keying the values of `ordered_trie_root_with_index_encoding`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexEncoding {
+	/// RLP encoded index, as in the transactions and receipts roots of Ethereum.
+	Rlp,
+	/// SCALE compact encoded index, as in Substrate's extrinsics root.
+	Scale,
+}
+
+impl IndexEncoding {
+	/// Encodes `index`.
+	fn encode(self, index: usize) -> Vec<u8> {
+		let index = index as u64;
+		match self {
+			IndexEncoding::Rlp => rlp::encode(&index).to_vec(),
+			IndexEncoding::Scale if index < 1 << 6 => [(index as u8) << 2].to_vec(),
+			IndexEncoding::Scale if index < 1 << 14 => ((index as u16) << 2 | 1).to_le_bytes().to_vec(),
+			IndexEncoding::Scale if index < 1 << 30 => ((index as u32) << 2 | 2).to_le_bytes().to_vec(),
+			IndexEncoding::Scale => {
+				// the number of bytes less four, followed by the significant little-endian bytes
+				let bytes = 8 - index.leading_zeros() as usize / 8;
+				let mut encoded = [((bytes - 4) as u8) << 2 | 3].to_vec();
+				encoded.extend_from_slice(&index.to_le_bytes()[..bytes]);
+				encoded
+			}
+		}
+	}
+}
 
-fn shared_prefix_len<T: Eq>(first: &[T], second: &[T]) -> usize {
-	first.iter().zip(second.iter()).position(|(f, s)| f != s).unwrap_or_else(|| cmp::min(first.len(), second.len()))
+/// Shape of the trie, independent of the encoding of its nodes.
+#[derive(Clone, Copy)]
+struct Layout {
+	/// Number of children of a branch node.
+	width: usize,
+	/// Values of at least this many bytes are replaced by their hash.
+	value_threshold: Option<usize>,
+}
+
+impl Layout {
+	/// The Ethereum trie layout.
+	const HEX: Layout = Layout { width: 16, value_threshold: None };
+
+	/// Calls `f` with the value stored in a node, hashing it if it exceeds the threshold.
+	fn with_value<H: Hasher, B: AsValue, R, F: FnOnce(Value) -> R>(self, value: &B, f: F) -> R {
+		value.with_value(|value| match self.value_threshold {
+			Some(threshold) if value.len() >= threshold => f(Value::Hashed(H::hash(value).as_ref())),
+			_ => f(Value::Inline(value)),
+		})
+	}
 }
 
 /// Generates a trie root hash for a vector of values
@@ -52,19 +227,680 @@ fn shared_prefix_len<T: Eq>(first: &[T], second: &[T]) -> usize {
 pub fn ordered_trie_root<H, I>(input: I) -> H::Out
 where
 	I: IntoIterator,
-	I::Item: AsRef<[u8]>,
+	I::Item: AsValue,
 	H: Hasher,
 	<H as hash_db::Hasher>::Out: cmp::Ord,
 {
 	trie_root::<H, _, _, _>(input.into_iter().enumerate().map(|(i, v)| (rlp::encode(&i), v)))
 }
 
-/// Generates a trie root hash for a vector of key-value tuples
+/// Generates a trie root hash for a vector of values, keyed by their index encoded as given.
+///
+/// `IndexEncoding::Rlp` gives the same root as `ordered_trie_root`.
+///
+/// ```
+/// use triehash::{ordered_trie_root, ordered_trie_root_with_index_encoding, IndexEncoding};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = &["doe", "reindeer"];
+/// let rlp = ordered_trie_root_with_index_encoding::<KeccakHasher, _>(v, IndexEncoding::Rlp);
+/// assert_eq!(rlp, ordered_trie_root::<KeccakHasher, _>(v));
+/// assert_ne!(ordered_trie_root_with_index_encoding::<KeccakHasher, _>(v, IndexEncoding::Scale), rlp);
+/// ```
+pub fn ordered_trie_root_with_index_encoding<H, I>(input: I, encoding: IndexEncoding) -> H::Out
+where
+	I: IntoIterator,
+	I::Item: AsValue,
+	H: Hasher,
+	<H as hash_db::Hasher>::Out: cmp::Ord,
+{
+	trie_root::<H, _, _, _>(input.into_iter().enumerate().map(|(i, v)| (encoding.encode(i), v)))
+}
+
+/// Generates a trie root hash for a vector of key-value tuples
+///
+/// ```
+/// use hex_literal::hex;
+/// use triehash::trie_root;
+/// use ethereum_types::H256;
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let root = H256::from(hex!("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"));
+/// assert_eq!(trie_root::<KeccakHasher, _, _, _>(v), root.as_ref());
+/// ```
+pub fn trie_root<H, I, A, B>(input: I) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+	<H as hash_db::Hasher>::Out: cmp::Ord,
+{
+	trie_root_with_visitor::<H, _, _, _, _>(input, &mut ())
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, using branch nodes of the given radix.
+///
+/// `Radix::Hex` gives the same root as `trie_root`. With `Radix::Binary` every level of the trie
+/// consumes a single bit of the key: branch nodes are lists of two children followed by the value,
+/// and partial keys are encoded in hex-prefix notation with one bit per nibble.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_with_radix, Radix};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let hex = trie_root_with_radix::<KeccakHasher, _, _, _>(v.clone(), Radix::Hex);
+/// assert_eq!(hex, trie_root::<KeccakHasher, _, _, _>(v.clone()));
+/// assert_ne!(trie_root_with_radix::<KeccakHasher, _, _, _>(v, Radix::Binary), hex);
+/// ```
+pub fn trie_root_with_radix<H, I, A, B>(input: I, radix: Radix) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+{
+	match radix {
+		Radix::Binary => trie_root_with_key_encoding::<H, Bits, _, _, _>(input),
+		Radix::Hex => trie_root_with_key_encoding::<H, HexNibbles, _, _, _>(input),
+		Radix::Byte => trie_root_with_key_encoding::<H, Bytes, _, _, _>(input),
+	}
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, with keys converted into
+/// paths by `E`.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_with_key_encoding, KeyEncode};
+/// use keccak_hasher::KeccakHasher;
+///
+/// /// Paths of little-endian integer keys, most significant nibble first.
+/// struct LittleEndian;
+///
+/// impl KeyEncode for LittleEndian {
+/// 	const RADIX: usize = 16;
+///
+/// 	fn encode_key(key: &[u8], path: &mut Vec<u8>) {
+/// 		for &b in key.iter().rev() {
+/// 			path.push(b >> 4);
+/// 			path.push(b & 0x0F);
+/// 		}
+/// 	}
+/// }
+///
+/// let v = vec![(1u32.to_le_bytes(), "one"), (256u32.to_le_bytes(), "two hundred fifty six")];
+/// let big_endian = vec![(1u32.to_be_bytes(), "one"), (256u32.to_be_bytes(), "two hundred fifty six")];
+/// assert_eq!(
+/// 	trie_root_with_key_encoding::<KeccakHasher, LittleEndian, _, _, _>(v),
+/// 	trie_root::<KeccakHasher, _, _, _>(big_endian),
+/// );
+/// ```
+pub fn trie_root_with_key_encoding<H, E, I, A, B>(input: I) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]>,
+	B: AsValue,
+	H: Hasher,
+	E: KeyEncode,
+{
+	key_encoded_root::<H, E, RlpTrieStream<E>, _, _, _>(input)
+}
+
+/// Generates the root of a binary trie for a vector of key-value tuples, with the nodes encoded
+/// by `BinaryTrieStream`.
+///
+/// Keys are split into bits like with `Radix::Binary`, which uses RLP encoded nodes instead.
+///
+/// # Panics
+///
+/// If a key is longer than 8191 bytes, whose length in bits doesn't fit into the node encoding.
+///
+/// ```
+/// use triehash::{binary_trie_root, trie_root_with_radix, Radix};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy")];
+/// let rlp = trie_root_with_radix::<KeccakHasher, _, _, _>(v.clone(), Radix::Binary);
+/// assert_ne!(binary_trie_root::<KeccakHasher, _, _, _>(v), rlp);
+/// ```
+pub fn binary_trie_root<H, I, A, B>(input: I) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]>,
+	B: AsValue,
+	H: Hasher,
+{
+	key_encoded_root::<H, Bits, BinaryTrieStream, _, _, _>(input)
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, with keys converted into paths
+/// by `E` and the nodes encoded by `S`.
+fn key_encoded_root<H, E, S, I, A, B>(input: I) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]>,
+	B: AsValue,
+	H: Hasher,
+	E: KeyEncode,
+	S: TrieStream,
+{
+	let input = input
+		.into_iter()
+		.map(|(k, v)| {
+			let mut path = Vec::new();
+			E::encode_key(k.as_ref(), &mut path);
+			(path, v)
+		})
+		.collect::<BTreeMap<_, _>>();
+	let (paths, values): (Vec<_>, Vec<_>) = input.into_iter().unzip();
+	let input = paths.iter().map(|path| &path[..]).zip(values).collect::<Vec<_>>();
+
+	hash_sorted::<H, S, _, _, _>(&input, Layout { width: E::RADIX, ..Layout::HEX }, &mut ())
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, with the nodes encoded by `S`.
+///
+/// The trie has the shape of the Ethereum trie, only the encoding of its nodes (and thus the
+/// root) depends on the stream.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_with_stream, RlpTrieStream};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let root = trie_root_with_stream::<KeccakHasher, RlpTrieStream, _, _, _>(v.clone());
+/// assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(v));
+/// ```
+pub fn trie_root_with_stream<H, S, I, A, B>(input: I) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+	S: TrieStream,
+{
+	stream_root_with_visitor::<H, S, _, _, _, _>(input, &mut ())
+}
+
+/// Encodes the root node of the trie of a vector of key-value tuples with the stream `S`,
+/// returning the stream.
+///
+/// The trie root is the hash of the encoded root node. Streams recording more than the encoding,
+/// like `InstrumentedStream`, can be inspected afterwards.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_stream, RlpTrieStream, TrieStream};
+/// use hash_db::Hasher;
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy")];
+/// let stream = trie_root_stream::<KeccakHasher, RlpTrieStream, _, _, _>(v.clone());
+/// assert_eq!(KeccakHasher::hash(stream.as_raw()), trie_root::<KeccakHasher, _, _, _>(v));
+/// ```
+pub fn trie_root_stream<H, S, I, A, B>(input: I) -> S
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+	S: TrieStream,
+{
+	let input = sorted_nibbles(input.into_iter().collect());
+	hash256rlp::<H, S, _, _, _>(&input, 0, Layout::HEX, &mut ())
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, replacing values of at least
+/// `threshold` bytes by their hash in the node encoding.
+///
+/// This is the layout of Substrate's state version 1, which keeps nodes small no matter how big
+/// the values are; the values themselves are expected to be stored separately, keyed by their hash.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_with_value_threshold};
+/// use hash_db::Hasher;
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy")];
+/// let root = trie_root_with_value_threshold::<KeccakHasher, _, _, _>(v, 8);
+///
+/// // "reindeer" is replaced by its hash, "puppy" is kept
+/// let reindeer = KeccakHasher::hash(b"reindeer");
+/// let hashed = vec![(&b"doe"[..], &reindeer[..]), (b"dog", b"puppy")];
+/// assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(hashed));
+/// ```
+pub fn trie_root_with_value_threshold<H, I, A, B>(input: I, threshold: usize) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+{
+	let input = sorted_nibbles(input.into_iter().collect());
+
+	hash_sorted::<H, RlpTrieStream, _, _, _>(
+		&input,
+		Layout { value_threshold: Some(threshold), ..Layout::HEX },
+		&mut (),
+	)
+}
+
+/// Generates the root of Substrate's trie (state version 1) for a vector of key-value tuples.
+///
+/// Nodes are encoded by `CodecTrieStreamV1` and values of at least 33 bytes are replaced by
+/// their hash.
+///
+/// ```
+/// use triehash::{trie_root_v1, trie_root_with_stream, CodecTrieStreamV1};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy")];
+///
+/// // no value is long enough to be hashed
+/// let root = trie_root_with_stream::<KeccakHasher, CodecTrieStreamV1, _, _, _>(v.clone());
+/// assert_eq!(trie_root_v1::<KeccakHasher, _, _, _>(v), root);
+/// ```
+pub fn trie_root_v1<H, I, A, B>(input: I) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+{
+	let input = sorted_nibbles(input.into_iter().collect());
+
+	hash_sorted::<H, CodecTrieStreamV1, _, _, _>(&input, Layout { value_threshold: Some(33), ..Layout::HEX }, &mut ())
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, treating empty values as deletions.
+///
+/// Keys whose (last) value is empty are left out of the trie, as in the Ethereum state where
+/// setting a storage slot to an empty value removes it.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_skip_empty};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("doe", "")];
+/// assert_eq!(trie_root_skip_empty::<KeccakHasher, _, _, _>(v), trie_root::<KeccakHasher, _, _, _>(vec![("dog", "puppy")]));
+/// ```
+pub fn trie_root_skip_empty<H, I, A, B>(input: I) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	H: Hasher,
+{
+	let mut input = sorted_nibbles(input.into_iter().collect());
+	input.retain(|(_, value)| !value.as_ref().is_empty());
+
+	hash_sorted::<H, RlpTrieStream, _, _, _>(&input, Layout::HEX, &mut ())
+}
+
+/// Generates the root hash of the subtrie holding the keys which start with `prefix` (in nibbles).
+///
+/// The subtrie is encoded as if it was the node at `prefix` in the trie of all keys, i.e. only
+/// the nibbles following the prefix are stored in it. Where the trie of all keys has a branch
+/// at `prefix`, the subtrie root is the hash of the child at that position, so parts of a trie
+/// (e.g. shards of a storage trie) can be checked independently.
+///
+/// ```
+/// use hex_literal::hex;
+/// use triehash::{subtrie_root, trie_root};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("cat", "kitten")];
+///
+/// // "doe" and "dog" start with the nibbles 6, 4, 6, f, 6 and branch on the last one
+/// let shard = subtrie_root::<KeccakHasher, _, _, _>(v.clone(), &[6, 4, 6, 15, 6]);
+/// assert_eq!(shard, hex!("f226ef598ed9195f2211546cf5b2860dc27b4da07ff7ab5108ee68107f0c9d00"));
+/// assert_eq!(subtrie_root::<KeccakHasher, _, _, _>(v.clone(), &[]), trie_root::<KeccakHasher, _, _, _>(v));
+/// ```
+pub fn subtrie_root<H, I, A, B>(input: I, prefix: &[u8]) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+{
+	let mut input = sorted_nibbles(input.into_iter().collect());
+	input.retain(|(key, _)| key.starts_with(prefix));
+
+	let stream = hash256rlp::<H, RlpTrieStream, _, _, _>(&input, prefix.len(), Layout::HEX, &mut ());
+	H::hash(stream.as_raw())
+}
+
+/// Generates a trie root hash for key-value tuples which are already sorted by key.
+///
+/// The input is consumed lazily and only the nodes on the path of the last key are kept
+/// in memory, so arbitrarily large inputs can be hashed. Keys must be in ascending order;
+/// if the same key is given more than once, its last value is used. The result is unspecified
+/// for unsorted input.
+///
+/// ```
+/// use hex_literal::hex;
+/// use triehash::trie_root_from_sorted_iter;
+/// use ethereum_types::H256;
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let root = H256::from(hex!("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"));
+/// assert_eq!(trie_root_from_sorted_iter::<KeccakHasher, _, _, _>(v), root.as_ref());
+/// ```
+pub fn trie_root_from_sorted_iter<H, I, A, B>(input: I) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]>,
+	B: AsRef<[u8]>,
+	H: Hasher,
+{
+	let mut builder = iter_build::SortedTrieBuilder::<H, _>::new();
+	for (key, value) in input {
+		builder.insert(iter_build::key_nibbles(key.as_ref()), value);
+	}
+	builder.finish()
+}
+
+/// Generates a trie root hash for key-value tuples which are sorted by key and contain
+/// no duplicate keys, e.g. the contents of a database column.
+///
+/// This skips the sorting done by `trie_root`. The result is unspecified if the input
+/// is not sorted or has duplicates; debug builds panic on such input.
+///
+/// ```
+/// use hex_literal::hex;
+/// use triehash::trie_root_from_sorted_unique;
+/// use ethereum_types::H256;
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let root = H256::from(hex!("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"));
+/// assert_eq!(trie_root_from_sorted_unique::<KeccakHasher, _, _, _>(v), root.as_ref());
+/// ```
+pub fn trie_root_from_sorted_unique<H, I, A, B>(input: I) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]>,
+	B: AsValue,
+	H: Hasher,
+{
+	let input = input.into_iter().map(|(k, v)| (split_key(k.as_ref()), v)).collect::<Vec<_>>();
+	debug_assert!(input.windows(2).all(|w| w[0].0 < w[1].0), "trie input must be sorted and without duplicates");
+
+	hash_sorted::<H, RlpTrieStream, _, _, _>(&input, Layout::HEX, &mut ())
+}
+
+/// Generates the encoded root node of the trie for a vector of key-value tuples, without hashing it.
+///
+/// Child nodes are referenced by hash as usual, only the root itself is left unhashed.
+/// This is useful for small tries whose root node is shorter than a hash and gets inlined
+/// into another structure. Hashing the result gives the same hash as `trie_root`.
+///
+/// ```
+/// use triehash::{trie_root, unhashed_trie};
+/// use hash_db::Hasher;
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "reindeer")];
+///
+/// let root = unhashed_trie::<KeccakHasher, _, _, _>(v.clone());
+/// assert_eq!(root, vec![0xce, 0x84, 0x20, b'd', b'o', b'e', 0x88, b'r', b'e', b'i', b'n', b'd', b'e', b'e', b'r']);
+/// assert_eq!(KeccakHasher::hash(&root), trie_root::<KeccakHasher, _, _, _>(v));
+/// ```
+pub fn unhashed_trie<H, I, A, B>(input: I) -> Vec<u8>
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+{
+	let input = sorted_nibbles(input.into_iter().collect());
+
+	hash256rlp::<H, RlpTrieStream, _, _, _>(&input, 0, Layout::HEX, &mut ()).as_raw().to_vec()
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, together with the encoded root node.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_with_encoded, unhashed_trie};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let (root, encoded) = trie_root_with_encoded::<KeccakHasher, _, _, _>(v.clone());
+/// assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(v.clone()));
+/// assert_eq!(encoded, unhashed_trie::<KeccakHasher, _, _, _>(v));
+/// ```
+pub fn trie_root_with_encoded<H, I, A, B>(input: I) -> (H::Out, Vec<u8>)
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+{
+	let encoded = unhashed_trie::<H, _, _, _>(input);
+	(H::hash(&encoded), encoded)
+}
+
+/// Generates a key-hashed (secure) trie root hash for a vector of key-value tuples.
+///
+/// ```
+/// use hex_literal::hex;
+/// use ethereum_types::H256;
+/// use triehash::sec_trie_root;
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let root = H256::from(hex!("d4cd937e4a4368d7931a9cf51686b7e10abb3dce38a39000fd7902a092b64585"));
+/// assert_eq!(sec_trie_root::<KeccakHasher, _, _, _>(v), root.as_ref());
+/// ```
+pub fn sec_trie_root<H, I, A, B>(input: I) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]>,
+	B: AsValue,
+	H: Hasher,
+	<H as hash_db::Hasher>::Out: cmp::Ord,
+{
+	sec_trie_root_with::<H, H, _, _, _>(input)
+}
+
+/// Generates a key-hashed (secure) trie root hash for a vector of key-value tuples,
+/// hashing the keys with `KH` and the trie nodes with `NH`.
+///
+/// ```
+/// use triehash::{sec_trie_root, sec_trie_root_with};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// assert_eq!(
+/// 	sec_trie_root_with::<KeccakHasher, KeccakHasher, _, _, _>(v.clone()),
+/// 	sec_trie_root::<KeccakHasher, _, _, _>(v),
+/// );
+/// ```
+pub fn sec_trie_root_with<KH, NH, I, A, B>(input: I) -> NH::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]>,
+	B: AsValue,
+	KH: Hasher,
+	NH: Hasher,
+	<KH as hash_db::Hasher>::Out: cmp::Ord,
+	<NH as hash_db::Hasher>::Out: cmp::Ord,
+{
+	trie_root::<NH, _, _, _>(input.into_iter().map(|(k, v)| (KH::hash(k.as_ref()), v)))
+}
+
+/// Generates a key-hashed (secure) trie root hash for a vector of key-value tuples, hashing
+/// all keys in a single `KH::hash_batch` call and the trie nodes with `NH`.
+///
+/// ```
+/// # #[cfg(feature = "keccak")] {
+/// use hex_literal::hex;
+/// use ethereum_types::H256;
+/// use triehash::sec_trie_root_batched;
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let root = H256::from(hex!("d4cd937e4a4368d7931a9cf51686b7e10abb3dce38a39000fd7902a092b64585"));
+/// assert_eq!(sec_trie_root_batched::<KeccakHasher, KeccakHasher, _, _, _>(v), root.as_ref());
+/// # }
+/// ```
+pub fn sec_trie_root_batched<KH, NH, I, A, B>(input: I) -> NH::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]>,
+	B: AsValue,
+	KH: BatchHasher,
+	NH: Hasher,
+	<KH as hash_db::Hasher>::Out: cmp::Ord,
+	<NH as hash_db::Hasher>::Out: cmp::Ord,
+{
+	let (keys, values): (Vec<_>, Vec<_>) = input.into_iter().unzip();
+	let keys = KH::hash_batch(&keys.iter().map(AsRef::as_ref).collect::<Vec<_>>());
+	trie_root::<NH, _, _, _>(keys.into_iter().zip(values))
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, encoding the subtries below
+/// the topmost branch node on the rayon thread pool.
+///
+/// The result is the same as that of `trie_root`.
+///
+/// ```
+/// use hex_literal::hex;
+/// use triehash::par_trie_root;
+/// use ethereum_types::H256;
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let root = H256::from(hex!("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"));
+/// assert_eq!(par_trie_root::<KeccakHasher, _, _, _>(v), root.as_ref());
+/// ```
+#[cfg(feature = "rayon")]
+pub fn par_trie_root<H, I, A, B>(input: I) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]> + Sync,
+	H: Hasher,
+	<H as hash_db::Hasher>::Out: cmp::Ord,
+{
+	use rayon::prelude::*;
+	use rlp::RlpStream;
+
+	let input = sorted_nibbles(input.into_iter().collect());
+
+	// nothing to split, the root is a leaf (or empty)
+	if input.len() < 2 {
+		return H::hash(hash256rlp::<H, RlpTrieStream, _, _, _>(&input, 0, Layout::HEX, &mut ()).as_raw());
+	}
+
+	let (key, value) = (&input[0].0[..], &input[0].1);
+	let depth = sorted_shared_prefix_len(&input);
+
+	// if first key len is equal to the depth of the branch, it is the branch value
+	let mut begin = if depth == key.len() { 1 } else { 0 };
+	let mut groups = Vec::with_capacity(16);
+	for i in 0..16 {
+		let len = input[begin..].iter().take_while(|pair| pair.0[depth] == i).count();
+		groups.push(&input[begin..(begin + len)]);
+		begin += len;
+	}
+
+	let children = groups
+		.into_par_iter()
+		.map(|group| {
+			let mut stream = RlpTrieStream::<HexNibbles>::new();
+			match group.len() {
+				0 => stream.append_empty_child(),
+				_ => {
+					let child = hash256rlp::<H, RlpTrieStream, _, _, _>(group, depth + 1, Layout::HEX, &mut ());
+					stream.append_substream::<H>(&child);
+				}
+			}
+			stream.as_raw().to_vec()
+		})
+		.collect::<Vec<_>>();
+
+	let mut branch = RlpStream::new_list(17);
+	for child in &children {
+		branch.append_raw(child, 1);
+	}
+	if depth == key.len() {
+		branch.append(&value.as_ref());
+	} else {
+		branch.append_empty_data();
+	}
+	let branch = branch.out();
+
+	if depth == 0 {
+		return H::hash(&branch);
+	}
+
+	// all keys share a prefix, so the branch is below an extension node
+	let mut stream = RlpStream::new_list(2);
+	stream.append_iter(hex_prefix::hex_prefix_encode(&key[..depth], false));
+	stream.append_raw(&iter_build::node_reference::<H>(branch.to_vec()), 1);
+	H::hash(&stream.out())
+}
+
+/// Generates a key-hashed (secure) trie root hash for a vector of key-value tuples, hashing
+/// the keys and encoding the trie on the rayon thread pool.
+///
+/// The result is the same as that of `sec_trie_root`.
 ///
 /// ```
-/// use hex_literal::hex;
-/// use triehash::trie_root;
-/// use ethereum_types::H256;
+/// use triehash::{par_sec_trie_root, sec_trie_root};
 /// use keccak_hasher::KeccakHasher;
 ///
 /// let v = vec![
@@ -73,45 +909,71 @@ where
 /// 	("dogglesworth", "cat"),
 /// ];
 ///
-/// let root = H256::from(hex!("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"));
-/// assert_eq!(trie_root::<KeccakHasher, _, _, _>(v), root.as_ref());
+/// assert_eq!(par_sec_trie_root::<KeccakHasher, _, _, _>(v.clone()), sec_trie_root::<KeccakHasher, _, _, _>(v));
 /// ```
-pub fn trie_root<H, I, A, B>(input: I) -> H::Out
+#[cfg(feature = "rayon")]
+pub fn par_sec_trie_root<H, I, A, B>(input: I) -> H::Out
 where
 	I: IntoIterator<Item = (A, B)>,
-	A: AsRef<[u8]> + Ord,
-	B: AsRef<[u8]>,
+	A: AsRef<[u8]> + Send,
+	B: AsRef<[u8]> + Send + Sync,
 	H: Hasher,
 	<H as hash_db::Hasher>::Out: cmp::Ord,
 {
-	// first put elements into btree to sort them and to remove duplicates
-	let input = input.into_iter().collect::<BTreeMap<_, _>>();
-
-	let mut nibbles = Vec::with_capacity(input.keys().map(|k| k.as_ref().len()).sum::<usize>() * 2);
-	let mut lens = Vec::with_capacity(input.len() + 1);
-	lens.push(0);
-	for k in input.keys() {
-		for &b in k.as_ref() {
-			nibbles.push(b >> 4);
-			nibbles.push(b & 0x0F);
+	use rayon::prelude::*;
+
+	let input = input.into_iter().collect::<Vec<_>>();
+	let input = input.into_par_iter().map(|(k, v)| (H::hash(k.as_ref()), v)).collect::<Vec<_>>();
+	par_trie_root::<H, _, _, _>(input)
+}
+
+/// Sorts the items by key and splits the keys into nibbles. The last value of a duplicated key wins.
+///
+/// Items are sorted in place rather than collected into a map, and then converted in a single
+/// pass, so large inputs are held in memory at most twice.
+fn sorted_nibbles<A: AsRef<[u8]> + Ord, B>(mut input: Vec<(A, B)>) -> Vec<(Nibbles, B)> {
+	// the sort is stable, so the last value of a key follows all other ones
+	input.sort_by(|a, b| a.0.cmp(&b.0));
+	input.dedup_by(|next, prev| {
+		let duplicate = next.0 == prev.0;
+		if duplicate {
+			core::mem::swap(&mut next.1, &mut prev.1);
 		}
-		lens.push(nibbles.len());
+		duplicate
+	});
+	input.into_iter().map(|(k, v)| (split_key(k.as_ref()), v)).collect()
+}
+
+/// Splits a key into nibbles.
+fn split_key(key: &[u8]) -> Nibbles {
+	let mut nibbles = Nibbles::with_capacity(key.len() * 2);
+	for b in key {
+		nibbles.push(b >> 4);
+		nibbles.push(b & 0x0F);
 	}
+	nibbles
+}
 
-	// then move them to a vector
-	let input = input.into_iter().zip(lens.windows(2)).map(|((_, v), w)| (&nibbles[w[0]..w[1]], v)).collect::<Vec<_>>();
+/// Passes every hashed node to a callback.
+struct HashedNodes<F>(F);
 
-	let mut stream = RlpStream::new();
-	hash256rlp::<H, _, _>(&input, 0, &mut stream);
-	H::hash(&stream.out())
+impl<H: Hasher, F: FnMut(&H::Out, &[u8])> NodeVisitor<H> for HashedNodes<F> {
+	fn visit(&mut self, _path: &[u8], encoded: &[u8], hash: Option<&H::Out>) {
+		if let Some(hash) = hash {
+			(self.0)(hash, encoded);
+		}
+	}
 }
 
-/// Generates a key-hashed (secure) trie root hash for a vector of key-value tuples.
+/// Generates a trie root hash for a vector of key-value tuples, calling `on_node` with the hash
+/// and the encoding of every node referenced by hash, including the root.
+///
+/// Nodes are reported bottom-up, as soon as they are encoded, so they can be written out without
+/// keeping the whole trie in memory. Nodes shorter than a hash are inlined into their parents and
+/// are not reported separately.
 ///
 /// ```
-/// use hex_literal::hex;
-/// use ethereum_types::H256;
-/// use triehash::sec_trie_root;
+/// use triehash::{trie_root, trie_root_with_node_callback};
 /// use keccak_hasher::KeccakHasher;
 ///
 /// let v = vec![
@@ -120,182 +982,437 @@ where
 /// 	("dogglesworth", "cat"),
 /// ];
 ///
-/// let root = H256::from(hex!("d4cd937e4a4368d7931a9cf51686b7e10abb3dce38a39000fd7902a092b64585"));
-/// assert_eq!(sec_trie_root::<KeccakHasher, _, _, _>(v), root.as_ref());
+/// let mut nodes = Vec::new();
+/// let root = trie_root_with_node_callback::<KeccakHasher, _, _, _, _>(v.clone(), |hash, encoded| {
+/// 	nodes.push((*hash, encoded.to_vec()));
+/// });
+/// assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(v));
+/// assert_eq!(nodes.last().map(|node| node.0), Some(root));
 /// ```
-pub fn sec_trie_root<H, I, A, B>(input: I) -> H::Out
+pub fn trie_root_with_node_callback<H, I, A, B, F>(input: I, on_node: F) -> H::Out
 where
 	I: IntoIterator<Item = (A, B)>,
-	A: AsRef<[u8]>,
-	B: AsRef<[u8]>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
 	H: Hasher,
-	<H as hash_db::Hasher>::Out: cmp::Ord,
+	F: FnMut(&H::Out, &[u8]),
 {
-	trie_root::<H, _, _, _>(input.into_iter().map(|(k, v)| (H::hash(k.as_ref()), v)))
+	trie_root_with_visitor::<H, _, _, _, _>(input, &mut HashedNodes(on_node))
 }
 
-/// Hex-prefix Notation. First nibble has flags: oddness = 2^0 & termination = 2^1.
+/// Returns all nodes of the trie referenced by hash, with nodes encoded by `S`.
 ///
-/// The "termination marker" and "leaf-node" specifier are completely equivalent.
+/// Every node is paired with its hash, children come before their parents and the root node
+/// is the last one. Nodes shorter than a hash are inlined into their parents and are not
+/// returned separately. This is the set of nodes to store or serve for the trie, e.g. in
+/// warp-sync chunks.
 ///
-/// Input values are in range `[0, 0xf]`.
+/// ```
+/// use triehash::{trie_nodes, trie_root, RlpTrieStream};
+/// use hash_db::Hasher;
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
 ///
-/// ```markdown
-///  [0,0,1,2,3,4,5]   0x10012345 // 7 > 4
-///  [0,1,2,3,4,5]     0x00012345 // 6 > 4
-///  [1,2,3,4,5]       0x112345   // 5 > 3
-///  [0,0,1,2,3,4]     0x00001234 // 6 > 3
-///  [0,1,2,3,4]       0x101234   // 5 > 3
-///  [1,2,3,4]         0x001234   // 4 > 3
-///  [0,0,1,2,3,4,5,T] 0x30012345 // 7 > 4
-///  [0,0,1,2,3,4,T]   0x20001234 // 6 > 4
-///  [0,1,2,3,4,5,T]   0x20012345 // 6 > 4
-///  [1,2,3,4,5,T]     0x312345   // 5 > 3
-///  [1,2,3,4,T]       0x201234   // 4 > 3
+/// let nodes = trie_nodes::<KeccakHasher, RlpTrieStream, _, _, _>(v.clone());
+/// assert_eq!(nodes.last().map(|node| node.0), Some(trie_root::<KeccakHasher, _, _, _>(v)));
+/// assert!(nodes.iter().all(|(hash, encoded)| KeccakHasher::hash(encoded) == *hash));
 /// ```
-fn hex_prefix_encode<'a>(nibbles: &'a [u8], leaf: bool) -> impl Iterator<Item = u8> + 'a {
-	let inlen = nibbles.len();
-	let oddness_factor = inlen % 2;
+pub fn trie_nodes<H, S, I, A, B>(input: I) -> Vec<(H::Out, Vec<u8>)>
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+	S: TrieStream,
+{
+	let mut nodes = Vec::new();
+	let on_node = |hash: &H::Out, encoded: &[u8]| nodes.push((*hash, encoded.to_vec()));
+	stream_root_with_visitor::<H, S, _, _, _, _>(input, &mut HashedNodes(on_node));
+	nodes
+}
+
+/// Receives every node built while generating a trie root.
+trait NodeVisitor<H: Hasher> {
+	/// Called with the key (in nibbles) leading to the node, the node encoding and its hash.
+	///
+	/// Nodes which are inlined into their parent have no hash. Children are visited before
+	/// their parents, the root node is visited last.
+	fn visit(&mut self, path: &[u8], encoded: &[u8], hash: Option<&H::Out>);
+
+	/// Called before encoding every node, returning true stops building the trie.
+	///
+	/// The root generated after stopping is meaningless.
+	fn is_cancelled(&mut self) -> bool {
+		false
+	}
+
+	/// Called before encoding the node at `path` holding the items `begin..end` of the sorted
+	/// input, returning the hash of the node if it is known, so that the node and its children
+	/// aren't encoded (nor visited) again.
+	///
+	/// Only the hashes of nodes which were visited with a hash may be returned. Every node which
+	/// isn't skipped is visited once its children are, so lookups and visits nest like the
+	/// nodes do. Defaults to `None`.
+	fn known_hash(&mut self, path: &[u8], begin: usize, end: usize) -> Option<H::Out> {
+		let _ = (path, begin, end);
+		None
+	}
+}
 
-	let first_byte = {
-		let mut bits = ((inlen as u8 & 1) + (2 * leaf as u8)) << 4;
-		if oddness_factor == 1 {
-			bits += nibbles[0];
+impl<H: Hasher> NodeVisitor<H> for () {
+	fn visit(&mut self, _path: &[u8], _encoded: &[u8], _hash: Option<&H::Out>) {}
+}
+
+/// Generates a trie root hash, passing every node of the trie to `visitor`.
+fn trie_root_with_visitor<H, I, A, B, V>(input: I, visitor: &mut V) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+	V: NodeVisitor<H>,
+{
+	stream_root_with_visitor::<H, RlpTrieStream, _, _, _, _>(input, visitor)
+}
+
+/// Generates a trie root hash with nodes encoded by `S`, passing every node of the trie to `visitor`.
+fn stream_root_with_visitor<H, S, I, A, B, V>(input: I, visitor: &mut V) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+	S: TrieStream,
+	V: NodeVisitor<H>,
+{
+	let mut input = input.into_iter();
+	let mut small = SmallVec::<[(A, B); 2]>::new();
+	small.extend(input.by_ref().take(3));
+	if small.len() < 3 {
+		return hash_small::<H, S, _, _, _>(small, visitor);
+	}
+
+	let input = sorted_nibbles(small.into_iter().chain(input).collect());
+	hash_sorted::<H, S, _, _, _>(&input, Layout::HEX, visitor)
+}
+
+/// Generates the trie root hash of at most two items, without allocating a `Vec` for them.
+///
+/// Empty and tiny tries (e.g. receipts of near-empty blocks) are common and their
+/// roots are dominated by the fixed cost of the general path.
+fn hash_small<H, S, A, B, V>(mut input: SmallVec<[(A, B); 2]>, visitor: &mut V) -> H::Out
+where
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+	S: TrieStream,
+	V: NodeVisitor<H>,
+{
+	if input.len() == 2 {
+		match input[0].0.cmp(&input[1].0) {
+			cmp::Ordering::Less => {}
+			cmp::Ordering::Equal => {
+				// the last value of a duplicated key wins
+				input.swap_remove(0);
+			}
+			cmp::Ordering::Greater => input.swap(0, 1),
 		}
-		bits
-	};
-	once(first_byte).chain(nibbles[oddness_factor..].chunks(2).map(|ch| ch[0] << 4 | ch[1]))
+	}
+	let input = input.into_iter().map(|(k, v)| (split_key(k.as_ref()), v)).collect::<SmallVec<[_; 2]>>();
+	hash_sorted::<H, S, _, _, _>(&input, Layout::HEX, visitor)
 }
 
-fn hash256rlp<H, A, B>(input: &[(A, B)], pre_len: usize, stream: &mut RlpStream)
+/// Generates the trie root hash of input sorted by key (in digits of the layout radix) without duplicates.
+fn hash_sorted<H, S, A, B, V>(input: &[(A, B)], layout: Layout, visitor: &mut V) -> H::Out
 where
 	A: AsRef<[u8]>,
-	B: AsRef<[u8]>,
+	B: AsValue,
+	H: Hasher,
+	S: TrieStream,
+	V: NodeVisitor<H>,
+{
+	let stream = hash256rlp::<H, S, _, _, _>(input, 0, layout, visitor);
+	let out = stream.as_raw();
+	let root = H::hash(out);
+	#[cfg(feature = "tracing")]
+	tracing::debug!(target: "triehash", items = input.len(), root_len = out.len(), "generated trie root");
+	visitor.visit(&[], out, Some(&root));
+	root
+}
+
+/// Encodes the node holding all `input` items.
+///
+/// Nodes are encoded depth-first using an explicit stack rather than recursion,
+/// so deep tries don't overflow small (e.g. Wasm) call stacks. Streams of complete
+/// nodes are kept in a pool and reused for the following nodes, so only as many
+/// buffers are allocated as the trie is deep.
+fn hash256rlp<H, S, A, B, V>(input: &[(A, B)], pre_len: usize, layout: Layout, visitor: &mut V) -> S
+where
+	A: AsRef<[u8]>,
+	B: AsValue,
 	H: Hasher,
+	S: TrieStream,
+	V: NodeVisitor<H>,
 {
-	let inlen = input.len();
+	hash256rlp_with::<H, S, _, _, _, _>(input, pre_len, layout, visitor, S::with_capacity)
+}
 
-	// in case of empty slice, just append empty data
-	if inlen == 0 {
-		stream.append_empty_data();
-		return;
+/// Encodes the node holding all `input` items like `hash256rlp`, with streams created by `new_stream`.
+fn hash256rlp_with<H, S, A, B, V, F>(
+	input: &[(A, B)],
+	pre_len: usize,
+	layout: Layout,
+	visitor: &mut V,
+	new_stream: F,
+) -> S
+where
+	A: AsRef<[u8]>,
+	B: AsValue,
+	H: Hasher,
+	S: TrieStream,
+	V: NodeVisitor<H>,
+	F: Fn(usize, usize) -> S,
+{
+	// a single leaf (or empty) node has no children, encode it in place
+	let key_len = input.iter().map(|(key, _)| key.as_ref().len() - pre_len).sum::<usize>();
+	if input.len() < 2 {
+		let mut stream = new_stream(input.len(), key_len);
+		match input.first() {
+			Some((key, value)) => {
+				layout.with_value::<H, _, _, _>(value, |value| stream.append_leaf(&key.as_ref()[pre_len..], value))
+			}
+			None => stream.append_empty_data(),
+		}
+		return stream;
 	}
 
-	// take slices
-	let key: &[u8] = &input[0].0.as_ref();
-	let value: &[u8] = &input[0].1.as_ref();
+	let mut pool = Vec::new();
+	let mut stack = Vec::new();
+	let stream = new_stream(input.len(), key_len);
+	stack.push(Frame::new::<H, _, _>(input, 0, input.len(), pre_len, layout, stream));
+	loop {
+		if visitor.is_cancelled() {
+			return new_stream(0, 0);
+		}
+		let frame = stack.last_mut().expect("stack is not empty; qed");
+		if let Some((begin, end, pre_len)) = frame.advance::<H, _, _>(input, layout) {
+			if let Some(hash) = visitor.known_hash(&input[begin].0.as_ref()[..pre_len], begin, end) {
+				frame.stream.append_hashed_subtree(hash.as_ref());
+				continue;
+			}
+			// keys have about the same length, so the total length of the child's keys is estimated
+			let stream = pool.pop().unwrap_or_else(|| new_stream(end - begin, key_len / input.len() * (end - begin)));
+			stack.push(Frame::new::<H, _, _>(input, begin, end, pre_len, layout, stream));
+			continue;
+		}
 
-	// if the slice contains just one item, append the suffix of the key
-	// and then append value
-	if inlen == 1 {
-		stream.begin_list(2);
-		stream.append_iter(hex_prefix_encode(&key[pre_len..], true));
-		stream.append(&value);
-		return;
+		let mut frame = stack.pop().expect("stack is not empty; qed");
+		#[cfg(feature = "tracing")]
+		tracing::trace!(
+			target: "triehash",
+			kind = frame.children.kind(),
+			depth = frame.pre_len,
+			items = frame.end - frame.begin,
+			len = frame.stream.as_raw().len(),
+			"encoded node",
+		);
+		match stack.last_mut() {
+			Some(parent) => {
+				let path = &input[frame.begin].0.as_ref()[..frame.pre_len];
+				let hash = parent.stream.append_substream::<H>(&frame.stream);
+				visitor.visit(path, frame.stream.as_raw(), hash.as_ref());
+				frame.stream.clear();
+				pool.push(frame.stream);
+			}
+			None => return frame.stream,
+		}
 	}
+}
 
-	// get length of the longest shared prefix in slice keys
-	let shared_prefix = input
-		.iter()
-		// skip first tuple
-		.skip(1)
-		// get minimum number of shared nibbles between first and each successive
-		.fold(key.len(), |acc, &(ref k, _)| cmp::min(shared_prefix_len(key, k.as_ref()), acc));
+/// Children of a node which still have to be encoded.
+enum Children {
+	/// Leaf or empty node, which has no children.
+	Leaf,
+	/// Extension node, with the number of nibbles leading to its child until the child is encoded.
+	Extension(Option<usize>),
+	/// Branch node, with the nibble of the next child and the index of the first item below it.
+	Branch { nibble: usize, begin: usize },
+}
 
-	// if shared prefix is higher than current prefix append its
-	// new part of the key to the stream
-	// then recursively append suffixes of all items who had this key
-	if shared_prefix > pre_len {
-		stream.begin_list(2);
-		stream.append_iter(hex_prefix_encode(&key[pre_len..shared_prefix], false));
-		hash256aux::<H, _, _>(input, shared_prefix, stream);
-		return;
+#[cfg(feature = "tracing")]
+impl Children {
+	/// Name of the node kind, for tracing.
+	fn kind(&self) -> &'static str {
+		match self {
+			Children::Leaf => "leaf",
+			Children::Extension(_) => "extension",
+			Children::Branch { .. } => "branch",
+		}
 	}
+}
+
+/// Node which is being encoded.
+struct Frame<S> {
+	/// Index of the first item below the node.
+	begin: usize,
+	/// Index past the last item below the node.
+	end: usize,
+	/// Number of nibbles leading to the node.
+	pre_len: usize,
+	stream: S,
+	children: Children,
+}
 
-	// an item for every possible nibble/suffix
-	// + 1 for data
-	stream.begin_list(17);
+impl<S: TrieStream> Frame<S> {
+	/// Starts encoding the node holding the items `begin..end` into an empty `stream`.
+	fn new<H, A, B>(input: &[(A, B)], begin: usize, end: usize, pre_len: usize, layout: Layout, mut stream: S) -> Self
+	where
+		A: AsRef<[u8]>,
+		B: AsValue,
+		H: Hasher,
+	{
+		// in case of empty slice, just append empty data
+		if begin == end {
+			stream.append_empty_data();
+			return Frame { begin, end, pre_len, stream, children: Children::Leaf };
+		}
 
-	// if first key len is equal to prefix_len, move to next element
-	let mut begin = if pre_len == key.len() { 1 } else { 0 };
+		// take slices
+		let key: &[u8] = input[begin].0.as_ref();
 
-	// iterate over all possible nibbles
-	for i in 0..16 {
-		// count how many successive elements have same next nibble
-		let len = input.iter().skip(begin).take_while(|pair| pair.0.as_ref()[pre_len] == i).count();
-
-		// if at least 1 successive element has the same nibble
-		// append their suffixes
-		match len {
-			0 => {
-				stream.append_empty_data();
-			}
-			_ => hash256aux::<H, _, _>(&input[begin..(begin + len)], pre_len + 1, stream),
+		// if the slice contains just one item, append the suffix of the key
+		// and then append value
+		if end - begin == 1 {
+			layout.with_value::<H, _, _, _>(&input[begin].1, |value| stream.append_leaf(&key[pre_len..], value));
+			return Frame { begin, end, pre_len, stream, children: Children::Leaf };
 		}
-		begin += len;
+
+		// get length of the longest shared prefix in slice keys
+		let shared_prefix = sorted_shared_prefix_len(&input[begin..end]);
+
+		// if shared prefix is higher than current prefix append its
+		// new part of the key to the stream
+		// then append suffixes of all items who had this key as its only child
+		if shared_prefix > pre_len {
+			stream.begin_extension(&key[pre_len..shared_prefix]);
+			return Frame { begin, end, pre_len, stream, children: Children::Extension(Some(shared_prefix)) };
+		}
+
+		// if first key len is equal to prefix_len, it is the value of the branch
+		// and the children start at the next element
+		let first = if pre_len == key.len() { begin + 1 } else { begin };
+		let children = child_slots(&input[first..end], pre_len, layout.width);
+		stream.begin_branch_with_arity(layout.width, first > begin, children);
+		Frame { begin, end, pre_len, stream, children: Children::Branch { nibble: 0, begin: first } }
 	}
 
-	// if fist key len is equal prefix, append its value
-	if pre_len == key.len() {
-		stream.append(&value);
-	} else {
-		stream.append_empty_data();
+	/// Appends the encoding up to the next child which has to be encoded first.
+	///
+	/// Returns the items of that child and the number of nibbles leading to it,
+	/// or `None` if the node is complete.
+	fn advance<H, A, B>(&mut self, input: &[(A, B)], layout: Layout) -> Option<(usize, usize, usize)>
+	where
+		A: AsRef<[u8]>,
+		B: AsValue,
+		H: Hasher,
+	{
+		match self.children {
+			Children::Leaf => None,
+			Children::Extension(ref mut child) => {
+				child.take().map(|shared_prefix| (self.begin, self.end, shared_prefix))
+			}
+			Children::Branch { ref mut nibble, ref mut begin } => {
+				let pre_len = self.pre_len;
+
+				// iterate over the remaining nibbles
+				while *nibble < layout.width {
+					// count how many successive elements have same next nibble
+					let len = input[*begin..self.end]
+						.iter()
+						.take_while(|pair| pair.0.as_ref()[pre_len] as usize == *nibble)
+						.count();
+					*nibble += 1;
+
+					// if at least 1 successive element has the same nibble
+					// their suffixes form the next child
+					if len > 0 {
+						let child = (*begin, *begin + len, pre_len + 1);
+						*begin += len;
+						return Some(child);
+					}
+					self.stream.append_empty_child();
+				}
+
+				// if fist key len is equal prefix, append its value
+				let (key, value) = &input[self.begin];
+				let has_value = pre_len == key.as_ref().len();
+				if has_value && !self.stream.has_value_slot() {
+					// the value is a leaf of its own, ending the branch
+					if *nibble == layout.width {
+						*nibble += 1;
+						return Some((self.begin, self.begin + 1, pre_len));
+					}
+					self.stream.end_branch(None);
+					return None;
+				}
+				let stream = &mut self.stream;
+				if has_value {
+					layout.with_value::<H, _, _, _>(value, |value| stream.end_branch(Some(value)));
+				} else {
+					stream.end_branch(None);
+				}
+				None
+			}
+		}
 	}
 }
 
-fn hash256aux<H, A, B>(input: &[(A, B)], pre_len: usize, stream: &mut RlpStream)
-where
-	A: AsRef<[u8]>,
-	B: AsRef<[u8]>,
-	H: Hasher,
-{
-	let mut s = RlpStream::new();
-	hash256rlp::<H, _, _>(input, pre_len, &mut s);
-	let out = s.out();
-	match out.len() {
-		0..=31 => stream.append_raw(&out, 1),
-		_ => stream.append(&H::hash(&out).as_ref()),
-	};
+/// Tells for every digit of the radix whether some of the `items` (sorted by key) continue with it after `pre_len`.
+fn child_slots<A: AsRef<[u8]>, B>(items: &[(A, B)], pre_len: usize, width: usize) -> impl Iterator<Item = bool> + '_ {
+	let mut digits = items.iter().map(move |(key, _)| key.as_ref()[pre_len] as usize).peekable();
+	(0..width).map(move |digit| {
+		let mut found = false;
+		while digits.peek() == Some(&digit) {
+			digits.next();
+			found = true;
+		}
+		found
+	})
 }
 
 #[cfg(test)]
 mod tests {
-	use super::{hex_prefix_encode, shared_prefix_len, trie_root};
+	use super::hex_prefix::hex_prefix_encode;
+	use super::{
+		ordered_trie_root, ordered_trie_root_with_index_encoding, sec_trie_root_with, shared_prefix_len, subtrie_root,
+		trie_nodes, trie_root, trie_root_from_sorted_iter, trie_root_from_sorted_unique, trie_root_skip_empty,
+		trie_root_with_encoded, trie_root_with_node_callback, trie_root_with_radix, trie_root_with_value_threshold,
+		unhashed_trie, verify_proof, IndexEncoding, Radix, RlpTrieStream,
+	};
 	use ethereum_types::H256;
+	use hash_db::Hasher;
 	use hex_literal::hex;
 	use keccak_hasher::KeccakHasher;
+	use rlp::RlpStream;
+	use std::collections::BTreeMap;
 
-	#[test]
-	fn test_hex_prefix_encode() {
-		let v = vec![0, 0, 1, 2, 3, 4, 5];
-		let e = vec![0x10, 0x01, 0x23, 0x45];
-		let h = hex_prefix_encode(&v, false).collect::<Vec<_>>();
-		assert_eq!(h, e);
-
-		let v = vec![0, 1, 2, 3, 4, 5];
-		let e = vec![0x00, 0x01, 0x23, 0x45];
-		let h = hex_prefix_encode(&v, false).collect::<Vec<_>>();
-		assert_eq!(h, e);
-
-		let v = vec![0, 1, 2, 3, 4, 5];
-		let e = vec![0x20, 0x01, 0x23, 0x45];
-		let h = hex_prefix_encode(&v, true).collect::<Vec<_>>();
-		assert_eq!(h, e);
-
-		let v = vec![1, 2, 3, 4, 5];
-		let e = vec![0x31, 0x23, 0x45];
-		let h = hex_prefix_encode(&v, true).collect::<Vec<_>>();
-		assert_eq!(h, e);
-
-		let v = vec![1, 2, 3, 4];
-		let e = vec![0x00, 0x12, 0x34];
-		let h = hex_prefix_encode(&v, false).collect::<Vec<_>>();
-		assert_eq!(h, e);
-
-		let v = vec![4, 1];
-		let e = vec![0x20, 0x41];
-		let h = hex_prefix_encode(&v, true).collect::<Vec<_>>();
-		assert_eq!(h, e);
+	/// Pseudo-random input with keys of different lengths, including keys which are
+	/// prefixes of other keys and values both shorter and longer than a hash.
+	fn test_input(count: usize) -> BTreeMap<Vec<u8>, Vec<u8>> {
+		let mut input = BTreeMap::new();
+		for i in 0..count {
+			let seed = KeccakHasher::hash(&i.to_le_bytes());
+			let key = seed[..1 + seed[0] as usize % 8].to_vec();
+			let value = seed[..1 + seed[1] as usize % 32].repeat(1 + seed[2] as usize % 2);
+			input.insert(key[..key.len() / 2].to_vec(), value.clone());
+			input.insert(key, value);
+		}
+		input
 	}
 
 	#[test]
@@ -345,4 +1462,322 @@ mod tests {
 		let b = vec![1, 2, 3, 4, 5, 6];
 		assert_eq!(shared_prefix_len(&a, &b), 6);
 	}
+
+	#[test]
+	fn shared_prefix_matches_bytewise() {
+		for i in 0..500u32 {
+			let seed = KeccakHasher::hash(&i.to_le_bytes());
+			let a = vec![7u8; seed[0] as usize % 40];
+			let mut b = a[..seed[1] as usize % (a.len() + 1)].to_vec();
+			b.extend_from_slice(&seed[2..2 + seed[3] as usize % 8]);
+			let expected = a.iter().zip(&b).take_while(|(a, b)| a == b).count();
+			assert_eq!(shared_prefix_len(&a, &b), expected);
+			assert_eq!(shared_prefix_len(&b, &a), expected);
+		}
+	}
+
+	#[test]
+	fn sorted_iter_matches_trie_root() {
+		for &count in &[0, 1, 2, 3, 16, 100, 1000] {
+			let input = test_input(count);
+			assert_eq!(
+				trie_root_from_sorted_iter::<KeccakHasher, _, _, _>(input.clone()),
+				trie_root::<KeccakHasher, _, _, _>(input),
+			);
+		}
+	}
+
+	#[cfg(feature = "rayon")]
+	#[test]
+	fn par_trie_root_matches_trie_root() {
+		use super::par_trie_root;
+
+		for &count in &[0, 1, 2, 3, 16, 100, 1000] {
+			let input = test_input(count);
+			assert_eq!(
+				par_trie_root::<KeccakHasher, _, _, _>(input.clone()),
+				trie_root::<KeccakHasher, _, _, _>(input),
+			);
+		}
+
+		// all keys share a prefix, so the topmost branch is below an extension
+		let input = test_input(100).into_iter().map(|(k, v)| ([&[0xab, 0xc0][..], &k].concat(), v)).collect::<Vec<_>>();
+		assert_eq!(par_trie_root::<KeccakHasher, _, _, _>(input.clone()), trie_root::<KeccakHasher, _, _, _>(input));
+	}
+
+	#[test]
+	fn sorted_unique_matches_trie_root() {
+		for &count in &[0, 1, 2, 3, 16, 100, 1000] {
+			let input = test_input(count);
+			assert_eq!(
+				trie_root_from_sorted_unique::<KeccakHasher, _, _, _>(&input),
+				trie_root::<KeccakHasher, _, _, _>(input),
+			);
+		}
+	}
+
+	#[test]
+	fn small_inputs_are_sorted_and_deduplicated() {
+		let sorted = vec![(&b"a"[..], &b"one"[..]), (b"ab", b"two")];
+		let reversed = vec![(&b"ab"[..], &b"two"[..]), (b"a", b"one")];
+		assert_eq!(
+			trie_root::<KeccakHasher, _, _, _>(reversed),
+			trie_root_from_sorted_iter::<KeccakHasher, _, _, _>(sorted)
+		);
+
+		let duplicated = vec![(&b"a"[..], &b"one"[..]), (b"a", b"two")];
+		assert_eq!(
+			trie_root::<KeccakHasher, _, _, _>(duplicated),
+			trie_root::<KeccakHasher, _, _, _>(vec![("a", "two")])
+		);
+	}
+
+	#[test]
+	fn duplicated_keys_keep_last_value() {
+		let input = (0..300u32).map(|i| ((i % 100).to_be_bytes(), i.to_le_bytes())).collect::<Vec<_>>();
+		let last_values = input.iter().cloned().collect::<BTreeMap<_, _>>();
+		assert_eq!(
+			trie_root::<KeccakHasher, _, _, _>(input),
+			trie_root_from_sorted_iter::<KeccakHasher, _, _, _>(last_values)
+		);
+	}
+
+	#[test]
+	fn trie_nodes_prove_every_key() {
+		let input = test_input(100);
+		let nodes = trie_nodes::<KeccakHasher, RlpTrieStream, _, _, _>(input.clone());
+		let root = trie_root::<KeccakHasher, _, _, _>(input.clone());
+		assert_eq!(nodes.last().map(|node| node.0), Some(root));
+
+		let proof = nodes.into_iter().map(|(_, encoded)| encoded).collect::<Vec<_>>();
+		for (key, value) in &input {
+			assert!(verify_proof::<KeccakHasher, _>(&root, key, Some(value), &proof));
+		}
+	}
+
+	#[test]
+	fn skip_empty_drops_empty_values() {
+		let input = test_input(100);
+		let mut with_empty = input.clone();
+		for (i, value) in with_empty.values_mut().enumerate() {
+			if i % 3 == 0 {
+				value.clear();
+			}
+		}
+		let remaining = with_empty.iter().filter(|(_, value)| !value.is_empty()).collect::<Vec<_>>();
+		assert_eq!(
+			trie_root_skip_empty::<KeccakHasher, _, _, _>(&with_empty),
+			trie_root::<KeccakHasher, _, _, _>(remaining),
+		);
+		assert_eq!(trie_root_skip_empty::<KeccakHasher, _, _, _>(&input), trie_root::<KeccakHasher, _, _, _>(&input),);
+	}
+
+	#[test]
+	fn subtrie_roots_are_children_of_root_branch() {
+		let input = (0..500u32).map(|i| (KeccakHasher::hash(&i.to_le_bytes()), i.to_le_bytes())).collect::<Vec<_>>();
+		let (_, encoded) = trie_root_with_encoded::<KeccakHasher, _, _, _>(input.clone());
+		let branch = rlp::Rlp::new(&encoded);
+		for nibble in 0..16u8 {
+			let child = branch.at(nibble as usize).and_then(|child| child.data()).unwrap();
+			assert_eq!(&subtrie_root::<KeccakHasher, _, _, _>(input.clone(), &[nibble])[..], child);
+		}
+	}
+
+	#[cfg(feature = "rayon")]
+	#[test]
+	fn par_sec_trie_root_matches_sec_trie_root() {
+		use super::{par_sec_trie_root, sec_trie_root};
+
+		for &count in &[0, 1, 2, 100, 1000] {
+			let input = test_input(count);
+			assert_eq!(
+				par_sec_trie_root::<KeccakHasher, _, _, _>(input.clone()),
+				sec_trie_root::<KeccakHasher, _, _, _>(input),
+			);
+		}
+	}
+
+	#[test]
+	fn deep_trie_on_small_stack() {
+		// every key is a prefix of the next one, so each key adds a level to the trie
+		let input = (1..300).map(|len| (vec![0u8; len], vec![len as u8])).collect::<Vec<_>>();
+		let expected = trie_root_from_sorted_iter::<KeccakHasher, _, _, _>(input.clone());
+		let root = std::thread::Builder::new()
+			.stack_size(64 * 1024)
+			.spawn(move || trie_root::<KeccakHasher, _, _, _>(input))
+			.unwrap()
+			.join()
+			.unwrap();
+		assert_eq!(root, expected);
+	}
+
+	#[test]
+	fn hex_radix_matches_trie_root() {
+		let input = test_input(500);
+		assert_eq!(
+			trie_root_with_radix::<KeccakHasher, _, _, _>(&input, Radix::Hex),
+			trie_root::<KeccakHasher, _, _, _>(input),
+		);
+	}
+
+	#[test]
+	fn binary_radix_branch() {
+		// keys differ in the first bit, so the root is a binary branch with two leaves
+		let input = vec![([0x00u8], b"a"), ([0x80u8], b"b")];
+		let mut branch = RlpStream::new_list(3);
+		for value in &[b"a", b"b"] {
+			let mut leaf = RlpStream::new_list(2);
+			leaf.append_iter(hex_prefix_encode(&[0; 7], true));
+			leaf.append(&&value[..]);
+			branch.append_raw(&leaf.out(), 1);
+		}
+		branch.append_empty_data();
+
+		let root = trie_root_with_radix::<KeccakHasher, _, _, _>(input, Radix::Binary);
+		assert_eq!(root, KeccakHasher::hash(&branch.out()));
+	}
+
+	/// Hashes the reversed input, to tell apart from `KeccakHasher`.
+	struct ReversedKeccakHasher;
+
+	impl Hasher for ReversedKeccakHasher {
+		type Out = [u8; 32];
+		type StdHasher = <KeccakHasher as Hasher>::StdHasher;
+		const LENGTH: usize = 32;
+
+		fn hash(x: &[u8]) -> Self::Out {
+			KeccakHasher::hash(&x.iter().rev().cloned().collect::<Vec<_>>())
+		}
+	}
+
+	#[test]
+	fn sec_trie_root_with_separate_key_hasher() {
+		let input = test_input(100);
+		let hashed_keys = input.iter().map(|(k, v)| (ReversedKeccakHasher::hash(k), v)).collect::<Vec<_>>();
+		assert_eq!(
+			sec_trie_root_with::<ReversedKeccakHasher, KeccakHasher, _, _, _>(&input),
+			trie_root::<KeccakHasher, _, _, _>(hashed_keys),
+		);
+	}
+
+	#[test]
+	fn node_callback_reports_hashed_nodes() {
+		let input = test_input(200);
+		let mut nodes = Vec::new();
+		let root = trie_root_with_node_callback::<KeccakHasher, _, _, _, _>(&input, |hash, encoded| {
+			assert_eq!(&KeccakHasher::hash(encoded), hash);
+			assert!(encoded.len() >= 32);
+			nodes.push(*hash);
+		});
+		assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(&input));
+		assert_eq!(nodes.last(), Some(&root));
+	}
+
+	#[test]
+	fn unhashed_trie_hashes_to_trie_root() {
+		for &count in &[0, 1, 2, 100] {
+			let input = test_input(count);
+			let root = unhashed_trie::<KeccakHasher, _, _, _>(&input);
+			assert_eq!(KeccakHasher::hash(&root), trie_root::<KeccakHasher, _, _, _>(input));
+		}
+	}
+
+	#[test]
+	fn value_threshold_hashes_long_values() {
+		let input = test_input(300);
+		for &threshold in &[0, 16, 32, 1000] {
+			let hashed = input
+				.iter()
+				.map(|(k, v)| match v.len() >= threshold {
+					true => (k.clone(), KeccakHasher::hash(v).to_vec()),
+					false => (k.clone(), v.clone()),
+				})
+				.collect::<Vec<_>>();
+			assert_eq!(
+				trie_root_with_value_threshold::<KeccakHasher, _, _, _>(&input, threshold),
+				trie_root::<KeccakHasher, _, _, _>(hashed),
+			);
+		}
+	}
+
+	#[test]
+	fn byte_radix_branch() {
+		// keys differ in the first byte, so the root is a branch of 256 children
+		let input = vec![([1u8, 7], b"a"), ([2u8, 7], b"b")];
+		let mut branch = RlpStream::new_list(257);
+		branch.append_empty_data();
+		for value in &[b"a", b"b"] {
+			let mut leaf = RlpStream::new_list(2);
+			leaf.append(&vec![0x20, 7]);
+			leaf.append(&&value[..]);
+			branch.append_raw(&leaf.out(), 1);
+		}
+		for _ in 3..257 {
+			branch.append_empty_data();
+		}
+
+		let root = trie_root_with_radix::<KeccakHasher, _, _, _>(input, Radix::Byte);
+		assert_eq!(root, KeccakHasher::hash(&branch.out()));
+	}
+
+	#[test]
+	fn sorted_iter_uses_last_duplicate() {
+		let input = vec![(vec![1u8], vec![1u8]), (vec![1], vec![2]), (vec![2], vec![3])];
+		assert_eq!(
+			trie_root_from_sorted_iter::<KeccakHasher, _, _, _>(input.clone()),
+			trie_root::<KeccakHasher, _, _, _>(input),
+		);
+	}
+
+	#[test]
+	fn ordered_trie_root_keys_by_rlp_index() {
+		// indices above 127 take more than one byte in rlp
+		let values = (0..300u32).map(|i| i.to_be_bytes().to_vec()).collect::<Vec<_>>();
+		assert_eq!(
+			ordered_trie_root::<KeccakHasher, _>(&values),
+			trie_root::<KeccakHasher, _, _, _>(values.iter().enumerate().map(|(i, v)| (rlp::encode(&i), v))),
+		);
+		assert_eq!(ordered_trie_root::<KeccakHasher, Vec<Vec<u8>>>(vec![]), KeccakHasher::hash(&rlp::NULL_RLP));
+	}
+
+	#[test]
+	fn ordered_trie_root_known_vectors() {
+		let v = &["doe", "reindeer"];
+		assert_eq!(
+			ordered_trie_root::<KeccakHasher, _>(v),
+			hex!("e766d5d51b89dc39d981b41bda63248d7abce4f0225eefd023792a540bcffee3"),
+		);
+
+		// scale keys 0x00 and 0x04 share the nibble 0 and branch on the last one
+		let mut branch = RlpStream::new_list(17);
+		for i in 0..16 {
+			match i {
+				0 => branch.begin_list(2).append(&&[0x20u8][..]).append(&"doe"),
+				4 => branch.begin_list(2).append(&&[0x20u8][..]).append(&"reindeer"),
+				_ => branch.append_empty_data(),
+			};
+		}
+		branch.append_empty_data();
+		let branch = branch.out();
+		assert!(branch.len() >= 32);
+		let mut extension = RlpStream::new_list(2);
+		extension.append(&&[0x10u8][..]).append(&KeccakHasher::hash(&branch).as_ref());
+		assert_eq!(
+			ordered_trie_root_with_index_encoding::<KeccakHasher, _>(v, IndexEncoding::Scale),
+			KeccakHasher::hash(&extension.out()),
+		);
+	}
+
+	#[test]
+	fn scale_compact_indices() {
+		let encode = |i| IndexEncoding::Scale.encode(i);
+		assert_eq!(encode(0), vec![0x00]);
+		assert_eq!(encode(63), vec![0xfc]);
+		assert_eq!(encode(64), vec![0x01, 0x01]);
+		assert_eq!(encode(16383), vec![0xfd, 0xff]);
+		assert_eq!(encode(16384), vec![0x02, 0x00, 0x01, 0x00]);
+		assert_eq!(encode((1 << 30) - 1), vec![0xfe, 0xff, 0xff, 0xff]);
+		assert_eq!(encode(1 << 30), vec![0x03, 0x00, 0x00, 0x00, 0x40]);
+		assert_eq!(encode(1 << 32), vec![0x07, 0x00, 0x00, 0x00, 0x00, 0x01]);
+	}
 }
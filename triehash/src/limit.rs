@@ -0,0 +1,98 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Enforcing a maximum size of trie nodes.
+
+use crate::stream::TrieStream;
+use crate::value::AsValue;
+use crate::{stream_root_with_visitor, NodeVisitor};
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use hash_db::Hasher;
+
+/// A node is encoded into more bytes than allowed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeTooLarge {
+	/// Key (in nibbles) leading to the node.
+	pub prefix: Vec<u8>,
+	/// Length of the node encoding.
+	pub len: usize,
+}
+
+/// Stops building the trie at the first node longer than `max_len`.
+struct SizeLimit {
+	max_len: usize,
+	error: Option<NodeTooLarge>,
+}
+
+impl<H: Hasher> NodeVisitor<H> for SizeLimit {
+	fn visit(&mut self, path: &[u8], encoded: &[u8], _hash: Option<&H::Out>) {
+		if encoded.len() > self.max_len && self.error.is_none() {
+			self.error = Some(NodeTooLarge { prefix: path.to_vec(), len: encoded.len() });
+		}
+	}
+
+	fn is_cancelled(&mut self) -> bool {
+		self.error.is_some()
+	}
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, with the nodes encoded by `S`,
+/// unless a node is encoded into more than `max_len` bytes.
+///
+/// Building the trie stops at the first node which is too long, which is reported with the key
+/// leading to it, e.g. for chains limiting the size of nodes by consensus.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_with_max_node_size, NodeTooLarge, RlpTrieStream};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+/// let root = trie_root_with_max_node_size::<KeccakHasher, RlpTrieStream, _, _, _>(v.clone(), 64);
+/// assert_eq!(root, Ok(trie_root::<KeccakHasher, _, _, _>(v.clone())));
+///
+/// // the branch at "dog" holds its value and the leaf of "dogglesworth"
+/// let root = trie_root_with_max_node_size::<KeccakHasher, RlpTrieStream, _, _, _>(v, 32);
+/// assert_eq!(root, Err(NodeTooLarge { prefix: vec![6, 4, 6, 15, 6, 7], len: 37 }));
+/// ```
+pub fn trie_root_with_max_node_size<H, S, I, A, B>(input: I, max_len: usize) -> Result<H::Out, NodeTooLarge>
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+	S: TrieStream,
+{
+	let mut limit = SizeLimit { max_len, error: None };
+	let root = stream_root_with_visitor::<H, S, _, _, _, _>(input, &mut limit);
+	match limit.error {
+		Some(e) => Err(e),
+		None => Ok(root),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{trie_root_with_max_node_size, NodeTooLarge};
+	use crate::test_support::indexed_input;
+	use crate::{trie_root_v1, CodecTrieStreamV1, RlpTrieStream};
+	use keccak_hasher::KeccakHasher;
+
+	#[test]
+	fn reports_first_node_too_large() {
+		let input = indexed_input(1000);
+		let root = trie_root_with_max_node_size::<KeccakHasher, RlpTrieStream, _, _, _>(input.clone(), 100);
+		// branches of 16 hashes are the first nodes above the limit
+		assert_eq!(root, Err(NodeTooLarge { prefix: vec![0; 7], len: 532 }));
+
+		let v = vec![("doe", "reindeer"), ("dog", "puppy")];
+		let root = trie_root_with_max_node_size::<KeccakHasher, CodecTrieStreamV1, _, _, _>(v.clone(), 64);
+		assert_eq!(root, Ok(trie_root_v1::<KeccakHasher, _, _, _>(v)));
+	}
+}
@@ -0,0 +1,246 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reusing the hashes of repeated subtries.
+
+use core::marker::PhantomData;
+
+use crate::rstd::*;
+use crate::stream::{TrieStream, Value};
+
+use hash_db::Hasher;
+
+/// Number of child hashes kept by every stream, the cache is emptied when it is full.
+const MAX_ENTRIES: usize = 1024;
+
+/// Wraps the stream `S`, remembering the hashes (by `H`) of the children it referenced by hash.
+///
+/// Children are looked up by a fingerprint of their encoding, which is compared in full only
+/// when the fingerprints match. A child whose encoding was appended before isn't hashed again
+/// (children appended with another hasher than `H` are always hashed), which saves most of the work
+/// for inputs with many identical subtries, e.g. the same values below keys which differ only in
+/// their first nibbles. Every stream keeps its own cache across the nodes it encodes; streams are
+/// reused for nodes of about the same depth, where identical subtries are found.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_with_stream, MemoizingStream, RlpTrieStream};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = (0..1000u32).map(|i| (i.to_be_bytes(), [7u8; 40])).collect::<Vec<_>>();
+/// assert_eq!(
+/// 	trie_root_with_stream::<KeccakHasher, MemoizingStream<RlpTrieStream, KeccakHasher>, _, _, _>(v.clone()),
+/// 	trie_root::<KeccakHasher, _, _, _>(v),
+/// );
+/// ```
+pub struct MemoizingStream<S, H: Hasher> {
+	stream: S,
+	/// Encodings and hashes of the children referenced by hash, keyed by the fingerprint of the
+	/// encoding.
+	hashes: BTreeMap<u64, (Vec<u8>, H::Out)>,
+	/// Whether the stream is an extension node whose child hasn't been appended yet.
+	in_extension: bool,
+	_hasher: PhantomData<H>,
+}
+
+/// FNV-1a hash of `data`.
+fn fingerprint(data: &[u8]) -> u64 {
+	data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3))
+}
+
+/// Whether the hasher `H2` is `H`.
+///
+/// Hashers aren't `'static`, so their `TypeId` is unavailable. Equal addresses of the hash
+/// functions mean the same code, while a hasher mistaken for another one only misses the cache.
+fn same_hasher<H: Hasher, H2: Hasher>() -> bool {
+	let hash = H::hash as fn(&[u8]) -> H::Out as *const ();
+	let hash2 = H2::hash as fn(&[u8]) -> H2::Out as *const ();
+	H::LENGTH == H2::LENGTH && hash == hash2
+}
+
+impl<S: TrieStream, H: Hasher> TrieStream for MemoizingStream<S, H> {
+	fn new() -> Self {
+		MemoizingStream { stream: S::new(), hashes: BTreeMap::new(), in_extension: false, _hasher: PhantomData }
+	}
+
+	fn with_capacity(items: usize, key_len: usize) -> Self {
+		MemoizingStream {
+			stream: S::with_capacity(items, key_len),
+			hashes: BTreeMap::new(),
+			in_extension: false,
+			_hasher: PhantomData,
+		}
+	}
+
+	fn clear(&mut self) {
+		// the cached hashes are kept for the next node
+		self.stream.clear();
+		self.in_extension = false;
+	}
+
+	fn append_empty_data(&mut self) {
+		self.stream.append_empty_data();
+	}
+
+	fn append_leaf(&mut self, partial: &[u8], value: Value) {
+		self.stream.append_leaf(partial, value);
+	}
+
+	fn begin_extension(&mut self, partial: &[u8]) {
+		self.in_extension = true;
+		self.stream.begin_extension(partial);
+	}
+
+	fn begin_branch(&mut self, has_value: bool, children: impl Iterator<Item = bool>) {
+		self.stream.begin_branch(has_value, children);
+	}
+
+	fn append_empty_child(&mut self) {
+		self.stream.append_empty_child();
+	}
+
+	fn end_branch(&mut self, value: Option<Value>) {
+		self.stream.end_branch(value);
+	}
+
+	fn has_value_slot(&self) -> bool {
+		self.stream.has_value_slot()
+	}
+
+	fn has_extension_nodes(&self) -> bool {
+		self.stream.has_extension_nodes()
+	}
+
+	fn append_substream<H2: Hasher>(&mut self, child: &Self) -> Option<H2::Out> {
+		// streams without extension nodes store the child of an extension in the extension itself
+		let in_extension = core::mem::replace(&mut self.in_extension, false);
+		if in_extension || !same_hasher::<H, H2>() {
+			return self.stream.append_substream::<H2>(&child.stream);
+		}
+
+		let encoded = child.stream.as_raw();
+		let key = fingerprint(encoded);
+		if let Some((_, hash)) = self.hashes.get(&key).filter(|(node, _)| node == encoded) {
+			self.stream.append_hashed_subtree(hash.as_ref());
+			let mut out = H2::Out::default();
+			out.as_mut().copy_from_slice(hash.as_ref());
+			return Some(out);
+		}
+
+		let hash = self.stream.append_substream::<H2>(&child.stream)?;
+		if self.hashes.len() == MAX_ENTRIES {
+			self.hashes.clear();
+		}
+		let mut out = H::Out::default();
+		out.as_mut().copy_from_slice(hash.as_ref());
+		self.hashes.insert(key, (encoded.to_vec(), out));
+		Some(hash)
+	}
+
+	fn append_hashed_subtree(&mut self, hash: &[u8]) {
+		self.in_extension = false;
+		self.stream.append_hashed_subtree(hash);
+	}
+
+	fn as_raw(&self) -> &[u8] {
+		self.stream.as_raw()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::MemoizingStream;
+	use crate::{trie_root_with_stream, CodecTrieStreamV1, RlpTrieStream, TrieStream, Value};
+	use hash_db::Hasher;
+	use keccak_hasher::KeccakHasher;
+	use std::cell::Cell;
+
+	thread_local! {
+		static HASHED: Cell<usize> = const { Cell::new(0) };
+	}
+
+	/// Keccak hasher counting the hashed nodes.
+	struct CountingHasher;
+
+	impl Hasher for CountingHasher {
+		type Out = [u8; 32];
+		type StdHasher = <KeccakHasher as Hasher>::StdHasher;
+		const LENGTH: usize = 32;
+
+		fn hash(x: &[u8]) -> Self::Out {
+			HASHED.with(|hashed| hashed.set(hashed.get() + 1));
+			KeccakHasher::hash(x)
+		}
+	}
+
+	fn hashed_nodes<F: FnOnce() -> [u8; 32]>(root: F) -> ([u8; 32], usize) {
+		HASHED.with(|hashed| hashed.set(0));
+		let root = root();
+		(root, HASHED.with(|hashed| hashed.get()))
+	}
+
+	#[test]
+	fn repeated_subtries_are_hashed_once() {
+		let input = (0..4096u32).map(|i| ((i << 8).to_be_bytes(), [7u8; 40])).collect::<Vec<_>>();
+
+		let (root, plain) =
+			hashed_nodes(|| trie_root_with_stream::<CountingHasher, RlpTrieStream, _, _, _>(input.clone()));
+		let (memoized_root, memoized) = hashed_nodes(|| {
+			trie_root_with_stream::<CountingHasher, MemoizingStream<RlpTrieStream, CountingHasher>, _, _, _>(
+				input.clone(),
+			)
+		});
+		assert_eq!(memoized_root, root);
+		assert!(memoized * 2 < plain, "{} nodes hashed with memoization, {} without", memoized, plain);
+
+		// the child of an extension is stored in the extension itself
+		assert_eq!(
+			trie_root_with_stream::<KeccakHasher, MemoizingStream<CodecTrieStreamV1, KeccakHasher>, _, _, _>(
+				input.clone()
+			),
+			trie_root_with_stream::<KeccakHasher, CodecTrieStreamV1, _, _, _>(input),
+		);
+	}
+
+	#[test]
+	fn other_hashers_are_not_served_cached_hashes() {
+		let mut child = MemoizingStream::<RlpTrieStream, KeccakHasher>::new();
+		child.append_leaf(&[1, 2, 3], Value::Inline(&[7; 40]));
+
+		let mut stream = MemoizingStream::<RlpTrieStream, KeccakHasher>::new();
+		assert_eq!(stream.append_substream::<KeccakHasher>(&child), Some(KeccakHasher::hash(child.as_raw())));
+		// a hasher with the same output length hashes the child itself
+		let (hash, hashed) = hashed_nodes(|| stream.append_substream::<CountingHasher>(&child).unwrap());
+		assert_eq!((hash, hashed), (KeccakHasher::hash(child.as_raw()), 1));
+		assert_eq!(stream.append_substream::<Keccak160>(&child), Some(Keccak160::hash(child.as_raw())));
+
+		// while the hasher of the stream is served from the cache
+		let mut child = MemoizingStream::<RlpTrieStream, CountingHasher>::new();
+		child.append_leaf(&[1, 2, 3], Value::Inline(&[7; 40]));
+		let mut stream = MemoizingStream::<RlpTrieStream, CountingHasher>::new();
+		assert_eq!(stream.append_substream::<KeccakHasher>(&child), Some(KeccakHasher::hash(child.as_raw())));
+		let (_, hashed) = hashed_nodes(|| stream.append_substream::<CountingHasher>(&child).unwrap());
+		assert_eq!(hashed, 1);
+		let (_, hashed) = hashed_nodes(|| stream.append_substream::<CountingHasher>(&child).unwrap());
+		assert_eq!(hashed, 0);
+	}
+
+	/// Keccak-256 truncated to 20 bytes.
+	struct Keccak160;
+
+	impl Hasher for Keccak160 {
+		type Out = [u8; 20];
+		type StdHasher = <KeccakHasher as Hasher>::StdHasher;
+		const LENGTH: usize = 20;
+
+		fn hash(x: &[u8]) -> [u8; 20] {
+			let mut out = [0; 20];
+			out.copy_from_slice(&KeccakHasher::hash(x)[..20]);
+			out
+		}
+	}
+}
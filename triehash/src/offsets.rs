@@ -0,0 +1,118 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Positions of the nodes inlined into the encoded root node.
+
+use crate::hex_prefix::hex_prefix_decode;
+use crate::unhashed_trie;
+use crate::value::AsValue;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use hash_db::Hasher;
+use rlp::Rlp;
+
+/// Position of a node in the encoding of the root node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeOffset {
+	/// Key (in nibbles) leading to the node.
+	pub path: Vec<u8>,
+	/// Offset of the first byte of the node.
+	pub start: usize,
+	/// Offset following the last byte of the node.
+	pub end: usize,
+}
+
+/// Collects the offsets of `node` and of the nodes inlined into it, in the encoding starting at `base`.
+fn collect_offsets(node: Rlp, base: *const u8, path: &mut Vec<u8>, offsets: &mut Vec<NodeOffset>) {
+	let raw = node.as_raw();
+	let start = raw.as_ptr() as usize - base as usize;
+	offsets.push(NodeOffset { path: path.clone(), start, end: start + raw.len() });
+
+	let len = path.len();
+	let items = node.iter().collect::<Vec<_>>();
+	match items.len() {
+		2 => {
+			let partial = items[0].data().expect("partial keys are strings; qed");
+			let (partial, leaf) = hex_prefix_decode(partial).expect("partial keys are hex-prefix encoded; qed");
+			if !leaf && items[1].is_list() {
+				path.extend_from_slice(&partial);
+				collect_offsets(items[1].clone(), base, path, offsets);
+			}
+		}
+		17 => {
+			for (nibble, child) in items[..16].iter().enumerate().filter(|(_, child)| child.is_list()) {
+				path.push(nibble as u8);
+				collect_offsets(child.clone(), base, path, offsets);
+				path.truncate(len);
+			}
+		}
+		_ => {}
+	}
+	path.truncate(len);
+}
+
+/// Generates the encoded root node of the trie for a vector of key-value tuples, like
+/// `unhashed_trie`, together with the position of the root and of every node inlined into it.
+///
+/// Nodes are given in the order of their offsets, parents before their children, so the encoding
+/// of every node can be sliced out of the root, e.g. to build proofs, without encoding it again.
+/// Nodes referenced by hash aren't part of the root.
+///
+/// ```
+/// use triehash::{unhashed_trie_with_offsets, NodeOffset};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "a"), ("dog", "b")];
+/// let (encoded, offsets) = unhashed_trie_with_offsets::<KeccakHasher, _, _, _>(v);
+///
+/// // the branch below the root extension and its leaves are short enough to be inlined
+/// let paths = offsets.iter().map(|offset| offset.path.clone()).collect::<Vec<_>>();
+/// assert_eq!(paths, vec![vec![], vec![6, 4, 6, 15, 6], vec![6, 4, 6, 15, 6, 5], vec![6, 4, 6, 15, 6, 7]]);
+///
+/// let NodeOffset { start, end, .. } = offsets[3];
+/// assert_eq!(&encoded[start..end], &[0xc2, 0x20, b'b'][..]);
+/// ```
+pub fn unhashed_trie_with_offsets<H, I, A, B>(input: I) -> (Vec<u8>, Vec<NodeOffset>)
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+{
+	let encoded = unhashed_trie::<H, _, _, _>(input);
+	let mut offsets = Vec::new();
+	collect_offsets(Rlp::new(&encoded), encoded.as_ptr(), &mut Vec::new(), &mut offsets);
+	(encoded, offsets)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::unhashed_trie_with_offsets;
+	use crate::{trie_nodes, RlpTrieStream};
+	use keccak_hasher::KeccakHasher;
+
+	#[test]
+	fn slices_are_inlined_nodes() {
+		// a root branch of 4 inlined branches of 3 inlined leaves each
+		let input = (0..12u8).map(|i| (vec![((i / 3) << 4) | (i % 3)], vec![i])).collect::<Vec<_>>();
+		let (encoded, offsets) = unhashed_trie_with_offsets::<KeccakHasher, _, _, _>(input.clone());
+		assert_eq!((offsets[0].start, offsets[0].end), (0, encoded.len()));
+		assert!(offsets.windows(2).all(|w| w[0].start < w[1].start));
+
+		// every hashed node is either the root or referenced by hash, so only inlined nodes are sliced
+		let hashed = trie_nodes::<KeccakHasher, RlpTrieStream, _, _, _>(input);
+		assert_eq!(hashed.last().map(|(_, node)| node), Some(&encoded));
+		for offset in &offsets[1..] {
+			let node = &encoded[offset.start..offset.end];
+			assert!(node.len() < 32);
+			assert!(hashed.iter().all(|(_, hashed)| &hashed[..] != node));
+		}
+		assert_eq!(offsets.len(), 17);
+	}
+}
@@ -0,0 +1,215 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Trie root computed from independently generated parts.
+
+use crate::hex_prefix::hex_prefix_encode;
+use crate::iter_build::node_reference;
+use crate::{hash256rlp, sorted_nibbles, sorted_shared_prefix_len, Layout, RlpTrieStream, TrieStream};
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use hash_db::Hasher;
+use rlp::RlpStream;
+
+/// Root of the part of a trie holding the keys which start with one nibble.
+///
+/// Parts can be generated independently, e.g. on different machines, with `partial_root`
+/// and then combined into the root of the whole trie with `combine_partial_roots`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartialRoot {
+	/// The part has no keys.
+	Empty,
+	/// The part has a single key, given in nibbles.
+	Leaf { key: Vec<u8>, value: Vec<u8> },
+	/// The part has several keys, which share the `prefix` nibbles. `encoded` is the branch node
+	/// below that prefix.
+	Branch { prefix: Vec<u8>, encoded: Vec<u8> },
+}
+
+impl PartialRoot {
+	/// First nibble of the keys in the part, `None` if the part is empty or holds just the empty key.
+	fn nibble(&self) -> Option<u8> {
+		match self {
+			PartialRoot::Empty => None,
+			PartialRoot::Leaf { key, .. } => key.first().cloned(),
+			PartialRoot::Branch { prefix, .. } => prefix.first().cloned(),
+		}
+	}
+
+	/// Encodes the node of the part, assuming its partial key starts at nibble `start`.
+	fn encode<H: Hasher>(&self, start: usize) -> Vec<u8> {
+		match self {
+			PartialRoot::Empty => rlp::NULL_RLP.to_vec(),
+			PartialRoot::Leaf { key, value } => {
+				let mut stream = RlpStream::new_list(2);
+				stream.append_iter(hex_prefix_encode(&key[start..], true));
+				stream.append(value);
+				stream.out().to_vec()
+			}
+			PartialRoot::Branch { prefix, encoded } if prefix.len() == start => encoded.clone(),
+			PartialRoot::Branch { prefix, encoded } => {
+				let mut stream = RlpStream::new_list(2);
+				stream.append_iter(hex_prefix_encode(&prefix[start..], false));
+				stream.append_raw(&node_reference::<H>(encoded.clone()), 1);
+				stream.out().to_vec()
+			}
+		}
+	}
+}
+
+/// Generates the partial root of key-value tuples whose keys all start with the same nibble.
+///
+/// The empty key doesn't start with any nibble and has to be given in a part of its own.
+///
+/// # Panics
+///
+/// Panics if the keys don't share their first nibble.
+pub fn partial_root<H, I, A, B>(input: I) -> PartialRoot
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	H: Hasher,
+{
+	let input = sorted_nibbles(input.into_iter().collect());
+
+	match input.len() {
+		0 => PartialRoot::Empty,
+		1 => PartialRoot::Leaf { key: input[0].0.to_vec(), value: input[0].1.as_ref().to_vec() },
+		_ => {
+			let prefix = &input[0].0[..sorted_shared_prefix_len(&input)];
+			assert!(!prefix.is_empty(), "keys of a partial root must start with the same nibble");
+
+			let stream = hash256rlp::<H, RlpTrieStream, _, _, _>(&input, prefix.len(), Layout::HEX, &mut ());
+			PartialRoot::Branch { prefix: prefix.to_vec(), encoded: stream.as_raw().to_vec() }
+		}
+	}
+}
+
+/// Combines the partial roots of all parts of a trie into the trie root hash.
+///
+/// # Panics
+///
+/// Panics if two parts hold keys starting with the same nibble.
+///
+/// ```
+/// use triehash::{combine_partial_roots, partial_root, trie_root};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// 	("horse", "stallion"),
+/// ];
+///
+/// // "d" is 0x64 and "h" is 0x68, so both parts start with the nibble 6
+/// let parts = vec![
+/// 	partial_root::<KeccakHasher, _, _, _>(v.iter().cloned().filter(|(k, _)| k.as_bytes()[0] >> 4 == 6)),
+/// 	partial_root::<KeccakHasher, _, _, _>(v.iter().cloned().filter(|(k, _)| k.as_bytes()[0] >> 4 != 6)),
+/// ];
+/// assert_eq!(combine_partial_roots::<KeccakHasher, _>(parts), trie_root::<KeccakHasher, _, _, _>(v));
+/// ```
+pub fn combine_partial_roots<H, I>(parts: I) -> H::Out
+where
+	I: IntoIterator<Item = PartialRoot>,
+	H: Hasher,
+{
+	let mut children: [Option<PartialRoot>; 16] = Default::default();
+	let mut value = None;
+	for part in parts {
+		match (part.nibble(), part) {
+			(_, PartialRoot::Empty) => {}
+			(None, PartialRoot::Leaf { value: v, .. }) => value = Some(v),
+			(Some(nibble), part) => {
+				let child = &mut children[nibble as usize];
+				assert!(child.is_none(), "two parts hold keys starting with nibble {}", nibble);
+				*child = Some(part);
+			}
+			(None, PartialRoot::Branch { .. }) => panic!("keys of a partial root must start with the same nibble"),
+		}
+	}
+
+	let mut parts = children.iter().flatten();
+	let root = match (parts.next(), parts.next(), &value) {
+		(None, _, None) => return H::hash(&rlp::NULL_RLP),
+		// a single part is the whole trie
+		(Some(part), None, None) => part.encode::<H>(0),
+		// the empty key alone is a leaf with an empty partial key
+		(None, None, Some(value)) => PartialRoot::Leaf { key: Vec::new(), value: value.clone() }.encode::<H>(0),
+		_ => {
+			let mut stream = RlpStream::new_list(17);
+			for child in &children {
+				match child {
+					Some(part) => stream.append_raw(&node_reference::<H>(part.encode::<H>(1)), 1),
+					None => stream.append_empty_data(),
+				};
+			}
+			match value {
+				Some(value) => stream.append(&value),
+				None => stream.append_empty_data(),
+			};
+			stream.out().to_vec()
+		}
+	};
+	H::hash(&root)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{combine_partial_roots, partial_root, PartialRoot};
+	use crate::trie_root;
+	use hash_db::Hasher;
+	use keccak_hasher::KeccakHasher;
+	use std::collections::BTreeMap;
+
+	fn partition(input: &BTreeMap<Vec<u8>, Vec<u8>>) -> Vec<PartialRoot> {
+		let mut parts = vec![BTreeMap::new(); 17];
+		for (key, value) in input {
+			let part = key.first().map_or(16, |b| b >> 4) as usize;
+			parts[part].insert(key.clone(), value.clone());
+		}
+		parts.into_iter().map(partial_root::<KeccakHasher, _, _, _>).collect()
+	}
+
+	#[test]
+	fn combined_parts_match_trie_root() {
+		for &count in &[0, 1, 2, 5, 100, 1000] {
+			let mut input = BTreeMap::new();
+			for i in 0..count as u32 {
+				let seed = KeccakHasher::hash(&i.to_le_bytes());
+				let key = seed[..seed[0] as usize % 5].to_vec();
+				input.insert(key, seed[..1 + seed[1] as usize % 32].to_vec());
+			}
+			let root = combine_partial_roots::<KeccakHasher, _>(partition(&input));
+			assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(input));
+		}
+	}
+
+	#[test]
+	fn single_part() {
+		let input = (0..50u8).map(|i| (vec![0x12, 0x34, i], vec![i; 40])).collect::<BTreeMap<_, _>>();
+		let root = combine_partial_roots::<KeccakHasher, _>(partition(&input));
+		assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(input));
+	}
+
+	#[test]
+	fn empty_key_alone() {
+		let input = vec![(Vec::new(), vec![7u8; 40])].into_iter().collect::<BTreeMap<_, _>>();
+		let root = combine_partial_roots::<KeccakHasher, _>(partition(&input));
+		assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(input));
+	}
+
+	#[test]
+	#[should_panic]
+	fn overlapping_parts() {
+		let part = partial_root::<KeccakHasher, _, _, _>(vec![(b"a", b"b")]);
+		combine_partial_roots::<KeccakHasher, _>(vec![part.clone(), part]);
+	}
+}
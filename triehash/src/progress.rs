@@ -0,0 +1,106 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Progress reporting while generating a trie root.
+
+use crate::hex_prefix::hex_prefix_is_leaf;
+use crate::rstd::*;
+use crate::{trie_root_with_visitor, NodeVisitor};
+
+use hash_db::Hasher;
+use rlp::Rlp;
+
+/// Counts the items whose values have been encoded.
+struct ProgressReporter<F> {
+	processed: usize,
+	total: usize,
+	on_progress: F,
+}
+
+impl<H: Hasher, F: FnMut(usize, usize)> NodeVisitor<H> for ProgressReporter<F> {
+	fn visit(&mut self, _path: &[u8], encoded: &[u8], _hash: Option<&H::Out>) {
+		let rlp = Rlp::new(encoded);
+		let has_value = match rlp.item_count() {
+			Ok(17) => matches!(rlp.at(16), Ok(value) if !value.is_empty()),
+			Ok(2) => matches!(rlp.at(0).and_then(|partial| partial.data()), Ok(partial) if hex_prefix_is_leaf(partial)),
+			// empty trie
+			_ => false,
+		};
+
+		if has_value {
+			self.processed += 1;
+			(self.on_progress)(self.processed, self.total);
+		}
+	}
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, calling `on_progress` with the
+/// number of items processed so far and the total number of (distinct) items.
+///
+/// The callback is called once for every item, so the last call reports `(total, total)`. Empty
+/// values of keys which are prefixes of other keys can't be told apart from missing ones and are
+/// not reported.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_with_progress};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let mut last = (0, 0);
+/// let root = trie_root_with_progress::<KeccakHasher, _, _, _, _>(v.clone(), |processed, total| {
+/// 	last = (processed, total);
+/// });
+/// assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(v));
+/// assert_eq!(last, (3, 3));
+/// ```
+pub fn trie_root_with_progress<H, I, A, B, F>(input: I, on_progress: F) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	H: Hasher,
+	F: FnMut(usize, usize),
+{
+	let input = input.into_iter().collect::<BTreeMap<_, _>>();
+	let mut reporter = ProgressReporter { processed: 0, total: input.len(), on_progress };
+	trie_root_with_visitor::<H, _, _, _, _>(input, &mut reporter)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::trie_root_with_progress;
+	use crate::trie_root;
+	use keccak_hasher::KeccakHasher;
+
+	#[test]
+	fn reports_every_item() {
+		// includes keys which are prefixes of others, stored as branch values
+		let input = (0..1000u32).map(|i| ((i % 300).to_be_bytes()[..1 + i as usize % 4].to_vec(), vec![i as u8; 3]));
+		let input = input.collect::<Vec<_>>();
+		let mut calls = Vec::new();
+		let root = trie_root_with_progress::<KeccakHasher, _, _, _, _>(input.clone(), |processed, total| {
+			calls.push((processed, total));
+		});
+		assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(input));
+
+		let total = calls[0].1;
+		assert_eq!(calls, (1..=total).map(|processed| (processed, total)).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn no_progress_for_empty_trie() {
+		let mut called = false;
+		trie_root_with_progress::<KeccakHasher, Vec<(Vec<u8>, Vec<u8>)>, _, _, _>(vec![], |_, _| called = true);
+		assert!(!called);
+	}
+}
@@ -0,0 +1,499 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Merkle proofs generated alongside the trie root.
+
+use crate::db::node_prefix;
+use crate::hex_prefix::hex_prefix_decode;
+use crate::iter_build::key_nibbles;
+use crate::rstd::*;
+use crate::{trie_root_with_visitor, BatchHasher, NodeVisitor};
+#[cfg(not(feature = "std"))]
+use alloc::borrow::Cow;
+#[cfg(feature = "std")]
+use std::borrow::Cow;
+
+use hash_db::{HashDBRef, Hasher};
+use rlp::Rlp;
+
+/// Collects the nodes on the paths to a set of keys.
+struct ProofRecorder {
+	/// Keys in nibbles.
+	keys: Vec<Vec<u8>>,
+	/// Paths (in nibbles) and encodings of the nodes, in the order they were visited.
+	nodes: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl ProofRecorder {
+	fn new<K: AsRef<[u8]>>(keys: &[K]) -> Self {
+		ProofRecorder { keys: keys.iter().map(|key| key_nibbles(key.as_ref())).collect(), nodes: Vec::new() }
+	}
+}
+
+impl<H: Hasher> NodeVisitor<H> for ProofRecorder {
+	fn visit(&mut self, path: &[u8], encoded: &[u8], hash: Option<&H::Out>) {
+		// inlined nodes are part of their parent
+		if hash.is_some() && self.keys.iter().any(|key| key.starts_with(path)) {
+			self.nodes.push((path.to_vec(), encoded.to_vec()));
+		}
+	}
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, together with a proof for `key`.
+///
+/// The proof is the list of encoded nodes on the path from the root to `key`, starting with
+/// the root node. Nodes which are inlined into their parent are not listed separately.
+/// If `key` is not part of the input, the proof shows where its path ends.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_with_proof};
+/// use hash_db::Hasher;
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let (root, proof) = trie_root_with_proof::<KeccakHasher, _, _, _>(v.clone(), b"dog");
+/// assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(v));
+/// assert_eq!(KeccakHasher::hash(&proof[0]), root);
+/// ```
+pub fn trie_root_with_proof<H, I, A, B>(input: I, key: &[u8]) -> (H::Out, Vec<Vec<u8>>)
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	H: Hasher,
+{
+	trie_root_with_multi_proof::<H, _, _, _, _>(input, &[key])
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, together with a single proof
+/// for all of the `keys`.
+///
+/// Every node needed by any of the keys is included exactly once, starting with the root node:
+/// identical subtries below different paths share their nodes.
+/// The proof can be checked for each key separately with `verify_proof`.
+///
+/// ```
+/// use triehash::{trie_root_with_multi_proof, verify_proof};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let (root, proof) = trie_root_with_multi_proof::<KeccakHasher, _, _, _, _>(v, &["doe", "dog"]);
+/// assert!(verify_proof::<KeccakHasher, _>(&root, b"doe", Some(b"reindeer"), &proof));
+/// assert!(verify_proof::<KeccakHasher, _>(&root, b"dog", Some(b"puppy"), &proof));
+/// ```
+pub fn trie_root_with_multi_proof<H, I, A, B, K>(input: I, keys: &[K]) -> (H::Out, Vec<Vec<u8>>)
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	H: Hasher,
+	K: AsRef<[u8]>,
+{
+	let recorder = &mut ProofRecorder::new(keys);
+	let root = trie_root_with_visitor::<H, _, _, _, _>(input, recorder);
+	// nodes are visited bottom-up
+	let mut included = BTreeSet::new();
+	let proof = recorder.nodes.drain(..).rev().map(|(_, node)| node).filter(|node| included.insert(node.clone()));
+	(root, proof.collect())
+}
+
+/// Proof of a single key, in the format returned by `eth_getProof` (EIP-1186).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyProof {
+	/// The key, as given.
+	pub key: Vec<u8>,
+	/// Value of the key, `None` if the key is not part of the trie.
+	pub value: Option<Vec<u8>>,
+	/// Encoded nodes on the path from the root to the key, starting with the root node.
+	pub proof: Vec<Vec<u8>>,
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, together with a separate proof
+/// for each of the `keys`, in the order of `keys`.
+///
+/// Unlike `trie_root_with_multi_proof`, every proof lists all nodes on the path to its key,
+/// ordered from the root down, as expected by `eth_getProof` clients. For storage and account
+/// proofs the keys are hashed, so pass the hashed keys of a secure trie.
+///
+/// ```
+/// use triehash::{trie_root_with_key_proofs, verify_proof};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let (root, proofs) = trie_root_with_key_proofs::<KeccakHasher, _, _, _, _>(v, &["dog", "cat"]);
+/// assert_eq!(proofs[0].value, Some(b"puppy".to_vec()));
+/// assert!(verify_proof::<KeccakHasher, _>(&root, b"dog", Some(b"puppy"), &proofs[0].proof));
+/// assert_eq!(proofs[1].value, None);
+/// assert!(verify_proof::<KeccakHasher, _>(&root, b"cat", None, &proofs[1].proof));
+/// ```
+pub fn trie_root_with_key_proofs<H, I, A, B, K>(input: I, keys: &[K]) -> (H::Out, Vec<KeyProof>)
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	H: Hasher,
+	K: AsRef<[u8]>,
+{
+	let input = input.into_iter().collect::<BTreeMap<_, _>>();
+	let mut values = keys.iter().map(|key| (key.as_ref(), None)).collect::<BTreeMap<_, _>>();
+	for (key, value) in &input {
+		if let Some(slot) = values.get_mut(key.as_ref()) {
+			*slot = Some(value.as_ref().to_vec());
+		}
+	}
+
+	let mut recorder = ProofRecorder::new(keys);
+	let root = trie_root_with_visitor::<H, _, _, _, _>(input, &mut recorder);
+
+	let proofs = keys
+		.iter()
+		.zip(&recorder.keys)
+		.map(|(key, nibbles)| {
+			let mut nodes = recorder.nodes.iter().filter(|(path, _)| nibbles.starts_with(path)).collect::<Vec<_>>();
+			// nodes on the path to a key are at different depths
+			nodes.sort_by_key(|(path, _)| path.len());
+			KeyProof {
+				key: key.as_ref().to_vec(),
+				value: values[key.as_ref()].clone(),
+				proof: nodes.into_iter().map(|(_, node)| node.clone()).collect(),
+			}
+		})
+		.collect();
+	(root, proofs)
+}
+
+/// Checks a proof generated by `trie_root_with_proof`.
+///
+/// With `Some(value)`, returns whether the proof shows that `key` maps to `value` in the trie
+/// with the given `root`. With `None`, returns whether the proof shows that `key` is absent.
+/// The order of `proof` nodes does not matter.
+///
+/// ```
+/// use triehash::{trie_root_with_proof, verify_proof};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let (root, proof) = trie_root_with_proof::<KeccakHasher, _, _, _>(v.clone(), b"dog");
+/// assert!(verify_proof::<KeccakHasher, _>(&root, b"dog", Some(b"puppy"), &proof));
+/// assert!(!verify_proof::<KeccakHasher, _>(&root, b"dog", Some(b"kitten"), &proof));
+///
+/// let (root, proof) = trie_root_with_proof::<KeccakHasher, _, _, _>(v, b"do");
+/// assert!(verify_proof::<KeccakHasher, _>(&root, b"do", None, &proof));
+/// ```
+pub fn verify_proof<H, P>(root: &H::Out, key: &[u8], value: Option<&[u8]>, proof: &[P]) -> bool
+where
+	H: Hasher,
+	P: AsRef<[u8]>,
+{
+	let hashes = proof.iter().map(|node| H::hash(node.as_ref())).collect::<Vec<_>>();
+	verify_with_hashes(root.as_ref(), key, value, proof, &hashes)
+}
+
+/// Checks a proof generated by `trie_root_with_proof` like `verify_proof`, hashing all proof
+/// nodes in a single `H::hash_batch` call.
+pub fn verify_proof_batched<H, P>(root: &H::Out, key: &[u8], value: Option<&[u8]>, proof: &[P]) -> bool
+where
+	H: BatchHasher,
+	P: AsRef<[u8]>,
+{
+	let hashes = H::hash_batch(&proof.iter().map(AsRef::as_ref).collect::<Vec<_>>());
+	verify_with_hashes(root.as_ref(), key, value, proof, &hashes)
+}
+
+/// Walks the `proof` nodes, whose hashes are given in the same order.
+fn verify_with_hashes<P, O>(root: &[u8], key: &[u8], value: Option<&[u8]>, proof: &[P], hashes: &[O]) -> bool
+where
+	P: AsRef<[u8]>,
+	O: AsRef<[u8]>,
+{
+	verify_with_lookup(root, key, value, |hash, _path| {
+		hashes.iter().position(|h| h.as_ref() == hash).map(|i| Cow::Borrowed(proof[i].as_ref()))
+	})
+}
+
+/// Checks that `key` maps to `value` (or is absent, with `None`) in the trie with the given
+/// `root`, reading its nodes from `db`.
+///
+/// Nodes are looked up by hash and prefixed with the path leading to them, as inserted by
+/// `trie_root_into_db`. Only read access to the database is needed.
+///
+/// ```
+/// use triehash::{trie_root_into_db, verify_proof_in_db};
+/// use keccak_hasher::KeccakHasher;
+/// # use hash_db::{HashDB, Hasher, Prefix};
+/// # use std::collections::HashMap;
+/// #
+/// # #[derive(Default)]
+/// # struct Db(HashMap<(Vec<u8>, [u8; 32]), Vec<u8>>);
+/// #
+/// # fn prefixed(prefix: Prefix, key: &[u8; 32]) -> (Vec<u8>, [u8; 32]) {
+/// # 	(prefix.0.iter().cloned().chain(prefix.1).collect(), *key)
+/// # }
+/// #
+/// # impl HashDB<KeccakHasher, Vec<u8>> for Db {
+/// # 	fn get(&self, key: &[u8; 32], p: Prefix) -> Option<Vec<u8>> { self.0.get(&prefixed(p, key)).cloned() }
+/// # 	fn contains(&self, key: &[u8; 32], p: Prefix) -> bool { self.0.contains_key(&prefixed(p, key)) }
+/// # 	fn insert(&mut self, p: Prefix, value: &[u8]) -> [u8; 32] {
+/// # 		let key = KeccakHasher::hash(value);
+/// # 		self.0.insert(prefixed(p, &key), value.to_vec());
+/// # 		key
+/// # 	}
+/// # 	fn emplace(&mut self, key: [u8; 32], p: Prefix, value: Vec<u8>) { self.0.insert(prefixed(p, &key), value); }
+/// # 	fn remove(&mut self, key: &[u8; 32], p: Prefix) { self.0.remove(&prefixed(p, key)); }
+/// # }
+/// #
+/// # triehash::impl_as_hash_db!(Db, KeccakHasher, Vec<u8>);
+///
+/// let v = vec![(b"doe", vec![1; 40]), (b"dog", vec![2; 40])];
+/// let mut db = Db::default();
+/// let root = trie_root_into_db::<KeccakHasher, _, _, _, _>(v, &mut db);
+///
+/// let db: &dyn HashDB<KeccakHasher, Vec<u8>> = &db;
+/// assert!(verify_proof_in_db::<KeccakHasher, _>(&root, b"dog", Some(&[2; 40]), &db));
+/// assert!(verify_proof_in_db::<KeccakHasher, _>(&root, b"do", None, &db));
+/// ```
+pub fn verify_proof_in_db<H, T>(root: &H::Out, key: &[u8], value: Option<&[u8]>, db: &dyn HashDBRef<H, T>) -> bool
+where
+	H: Hasher,
+	T: AsRef<[u8]>,
+{
+	verify_with_lookup(root.as_ref(), key, value, |hash, path| {
+		let mut out = H::Out::default();
+		if out.as_ref().len() != hash.len() {
+			return None;
+		}
+		out.as_mut().copy_from_slice(hash);
+		let (packed, last) = node_prefix(&[], path);
+		db.get(&out, (&packed, last)).map(|node| Cow::Owned(node.as_ref().to_vec()))
+	})
+}
+
+/// Walks from the node with hash `root` towards `key`, looking up hashed nodes by their hash and
+/// path (in nibbles).
+fn verify_with_lookup<'a, F>(root: &[u8], key: &[u8], value: Option<&[u8]>, lookup: F) -> bool
+where
+	F: Fn(&[u8], &[u8]) -> Option<Cow<'a, [u8]>>,
+{
+	let key = key_nibbles(key);
+	let mut node = match lookup(root, &[]) {
+		Some(node) => node,
+		None => return false,
+	};
+	let mut pos = 0;
+
+	loop {
+		let rlp = Rlp::new(&node);
+		// empty trie
+		if rlp.is_data() && rlp.is_empty() {
+			return value.is_none();
+		}
+		let child = match rlp.item_count() {
+			Ok(2) => {
+				let (partial, leaf) = match rlp.at(0).and_then(|r| r.data()).ok().and_then(hex_prefix_decode) {
+					Some(decoded) => decoded,
+					None => return false,
+				};
+				if leaf {
+					return match rlp.at(1).and_then(|r| r.data()) {
+						Ok(data) if key[pos..] == partial[..] => value == Some(data),
+						Ok(_) => value.is_none(),
+						Err(_) => false,
+					};
+				}
+				if !key[pos..].starts_with(&partial) {
+					return value.is_none();
+				}
+				pos += partial.len();
+				rlp.at(1)
+			}
+			Ok(17) => {
+				if pos == key.len() {
+					return match rlp.at(16).and_then(|r| r.data()) {
+						Ok([]) => value.is_none(),
+						Ok(data) => value == Some(data),
+						Err(_) => false,
+					};
+				}
+				pos += 1;
+				rlp.at(key[pos - 1] as usize)
+			}
+			_ => return false,
+		};
+
+		node = match child {
+			// inline node
+			Ok(child) if child.is_list() => Cow::Owned(child.as_raw().to_vec()),
+			Ok(child) if child.is_empty() => return value.is_none(),
+			Ok(child) => match child.data().ok().and_then(|hash| lookup(hash, &key[..pos])) {
+				Some(node) => node,
+				None => return false,
+			},
+			Err(_) => return false,
+		};
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{trie_root_with_key_proofs, trie_root_with_multi_proof, trie_root_with_proof, verify_proof};
+	use hash_db::Hasher;
+	use keccak_hasher::KeccakHasher;
+
+	fn input() -> Vec<(Vec<u8>, Vec<u8>)> {
+		(0..100u32).map(|i| (KeccakHasher::hash(&i.to_le_bytes())[..4].to_vec(), vec![i as u8; 40])).collect()
+	}
+
+	/// Checks that every node is referenced by hash from the previous one.
+	fn assert_linked(root: [u8; 32], proof: &[Vec<u8>]) {
+		assert_eq!(KeccakHasher::hash(&proof[0]), root);
+		for pair in proof.windows(2) {
+			let hash = KeccakHasher::hash(&pair[1]);
+			assert!(pair[0].windows(32).any(|w| w == hash));
+		}
+	}
+
+	#[test]
+	fn proof_for_existing_key() {
+		let input = input();
+		let (key, value) = input[42].clone();
+		let (root, proof) = trie_root_with_proof::<KeccakHasher, _, _, _>(input, &key);
+		assert!(proof.len() > 1);
+		assert_linked(root, &proof);
+		assert!(proof.last().unwrap().windows(value.len()).any(|w| w == &value[..]));
+	}
+
+	#[test]
+	fn proof_for_missing_key() {
+		let (root, proof) = trie_root_with_proof::<KeccakHasher, _, _, _>(input(), b"missing");
+		assert!(!proof.is_empty());
+		assert_linked(root, &proof);
+	}
+
+	#[test]
+	fn proof_for_empty_trie() {
+		let (root, proof) = trie_root_with_proof::<KeccakHasher, Vec<(Vec<u8>, Vec<u8>)>, _, _>(vec![], b"key");
+		assert_eq!(proof, vec![vec![0x80]]);
+		assert_eq!(KeccakHasher::hash(&proof[0]), root);
+	}
+
+	#[test]
+	fn verify_membership() {
+		let input = input();
+		for (key, value) in input.iter().take(10) {
+			let (root, proof) = trie_root_with_proof::<KeccakHasher, _, _, _>(input.clone(), key);
+			assert!(verify_proof::<KeccakHasher, _>(&root, key, Some(value), &proof));
+			assert!(!verify_proof::<KeccakHasher, _>(&root, key, Some(b"other"), &proof));
+			assert!(!verify_proof::<KeccakHasher, _>(&root, key, None, &proof));
+		}
+	}
+
+	#[test]
+	fn verify_non_membership() {
+		let input = input();
+		for key in &[&b"missing"[..], &input[3].0[..2], b""] {
+			let (root, proof) = trie_root_with_proof::<KeccakHasher, _, _, _>(input.clone(), key);
+			assert!(verify_proof::<KeccakHasher, _>(&root, key, None, &proof));
+			assert!(!verify_proof::<KeccakHasher, _>(&root, key, Some(b"value"), &proof));
+		}
+
+		let (root, proof) = trie_root_with_proof::<KeccakHasher, Vec<(Vec<u8>, Vec<u8>)>, _, _>(vec![], b"key");
+		assert!(verify_proof::<KeccakHasher, _>(&root, b"key", None, &proof));
+	}
+
+	#[test]
+	fn verify_rejects_incomplete_proof() {
+		let input = input();
+		let (key, value) = input[7].clone();
+		let (root, mut proof) = trie_root_with_proof::<KeccakHasher, _, _, _>(input, &key);
+		proof.pop();
+		assert!(!verify_proof::<KeccakHasher, _>(&root, &key, Some(&value), &proof));
+		assert!(!verify_proof::<KeccakHasher, _>(&root, &key, None, &proof));
+	}
+
+	#[test]
+	fn verify_value_in_branch() {
+		let input = vec![(b"do".to_vec(), b"verb".to_vec()), (b"dog".to_vec(), b"puppy".to_vec())];
+		let (root, proof) = trie_root_with_proof::<KeccakHasher, _, _, _>(input, b"do");
+		assert!(verify_proof::<KeccakHasher, _>(&root, b"do", Some(b"verb"), &proof));
+	}
+
+	#[test]
+	fn multi_proof_shares_nodes() {
+		let input = input();
+		let keys =
+			input.iter().step_by(10).map(|(key, _)| key.clone()).chain(Some(b"missing".to_vec())).collect::<Vec<_>>();
+		let (root, proof) = trie_root_with_multi_proof::<KeccakHasher, _, _, _, _>(input.clone(), &keys);
+
+		let mut single_proofs_len = 0;
+		for key in &keys {
+			let value = input.iter().find(|(k, _)| k == key).map(|(_, v)| &v[..]);
+			assert!(verify_proof::<KeccakHasher, _>(&root, key, value, &proof));
+			single_proofs_len += trie_root_with_proof::<KeccakHasher, _, _, _>(input.clone(), key).1.len();
+		}
+		assert!(proof.len() < single_proofs_len);
+		assert_eq!(proof[0], trie_root_with_proof::<KeccakHasher, _, _, _>(input, &keys[0]).1[0]);
+		for (i, node) in proof.iter().enumerate() {
+			assert!(!proof[i + 1..].contains(node));
+		}
+	}
+
+	#[test]
+	fn multi_proof_includes_identical_subtries_once() {
+		// the subtries below the nibbles 1 and 2 are identical
+		let input = [[0x10, 0], [0x10, 1], [0x20, 0], [0x20, 1]].iter().map(|key| (key.to_vec(), vec![7; 40]));
+		let input = input.collect::<Vec<_>>();
+		let keys = [[0x10, 0], [0x20, 0]];
+		let (root, proof) = trie_root_with_multi_proof::<KeccakHasher, _, _, _, _>(input.clone(), &keys);
+
+		for key in &keys {
+			assert!(verify_proof::<KeccakHasher, _>(&root, key, Some(&[7; 40]), &proof));
+		}
+		let single = trie_root_with_proof::<KeccakHasher, _, _, _>(input, &keys[0]).1;
+		assert_eq!(proof, single);
+	}
+
+	#[test]
+	fn key_proofs_match_single_proofs() {
+		let input = input();
+		let mut keys = input.iter().step_by(7).map(|(key, _)| key.clone()).collect::<Vec<_>>();
+		keys.push(b"missing".to_vec());
+		let (root, proofs) = trie_root_with_key_proofs::<KeccakHasher, _, _, _, _>(input.clone(), &keys);
+		assert_eq!(proofs.len(), keys.len());
+
+		for (proof, key) in proofs.iter().zip(&keys) {
+			let (_, single) = trie_root_with_proof::<KeccakHasher, _, _, _>(input.clone(), key);
+			assert_eq!(&proof.key, key);
+			assert_eq!(proof.proof, single);
+			assert_linked(root, &proof.proof);
+
+			let value = input.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+			assert_eq!(proof.value, value);
+			assert!(verify_proof::<KeccakHasher, _>(&root, key, value.as_deref(), &proof.proof));
+		}
+	}
+}
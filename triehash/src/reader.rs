@@ -0,0 +1,158 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Generating a trie root from sorted records read from a file.
+
+use std::io::{self, Read, Write};
+
+use crate::trie_root_from_sorted_iter;
+
+use hash_db::Hasher;
+
+/// Writes a key-value record in the format read by `trie_root_from_reader`.
+pub fn write_record<W: Write>(mut writer: W, key: &[u8], value: &[u8]) -> io::Result<()> {
+	for data in &[key, value] {
+		writer.write_all(&(data.len() as u32).to_be_bytes())?;
+		writer.write_all(data)?;
+	}
+	Ok(())
+}
+
+/// Reads one length-prefixed item, returns `None` at the end of the input if `eof_allowed`.
+fn read_item<R: Read>(reader: &mut R, eof_allowed: bool) -> io::Result<Option<Vec<u8>>> {
+	let mut len = [0u8; 4];
+	let mut read = 0;
+	while read < len.len() {
+		match reader.read(&mut len[read..]) {
+			Ok(0) if read == 0 && eof_allowed => return Ok(None),
+			Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+			Ok(n) => read += n,
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+			Err(e) => return Err(e),
+		}
+	}
+	// the buffer grows with the data actually read, so a corrupt length doesn't allocate up to 4 GiB
+	let len = u64::from(u32::from_be_bytes(len));
+	let mut data = Vec::new();
+	reader.take(len).read_to_end(&mut data)?;
+	if data.len() as u64 != len {
+		return Err(io::ErrorKind::UnexpectedEof.into());
+	}
+	Ok(Some(data))
+}
+
+/// Records of a reader, stopping at the first error.
+pub(crate) struct Records<R> {
+	reader: R,
+	/// Error which stopped the iteration.
+	pub(crate) error: Option<io::Error>,
+}
+
+impl<R: Read> Records<R> {
+	pub(crate) fn new(reader: R) -> Self {
+		Records { reader, error: None }
+	}
+
+	fn read_record(&mut self) -> io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+		match read_item(&mut self.reader, true)? {
+			Some(key) => {
+				let value = read_item(&mut self.reader, false)?.expect("end of input is an error; qed");
+				Ok(Some((key, value)))
+			}
+			None => Ok(None),
+		}
+	}
+}
+
+impl<R: Read> Iterator for Records<R> {
+	type Item = (Vec<u8>, Vec<u8>);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.error.is_some() {
+			return None;
+		}
+		self.read_record().unwrap_or_else(|e| {
+			self.error = Some(e);
+			None
+		})
+	}
+}
+
+/// Generates a trie root hash for the key-value records read from `reader`.
+///
+/// Every record is a key followed by a value, each prefixed with its length as a big-endian `u32`
+/// (see `write_record`). Records must be sorted by key, as in `trie_root_from_sorted_iter`. They
+/// are consumed one at a time, so a snapshot file doesn't have to fit into memory. A memory-mapped
+/// file can be read through its `&[u8]`.
+///
+/// Fails if reading fails or the input ends in the middle of a record.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_from_reader, write_record};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let mut file = Vec::new();
+/// for (key, value) in &v {
+/// 	write_record(&mut file, key.as_bytes(), value.as_bytes()).unwrap();
+/// }
+/// let root = trie_root_from_reader::<KeccakHasher, _>(&file[..]).unwrap();
+/// assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(v));
+/// ```
+pub fn trie_root_from_reader<H: Hasher, R: Read>(reader: R) -> io::Result<H::Out> {
+	let mut records = Records::new(reader);
+	let root = trie_root_from_sorted_iter::<H, _, _, _>(&mut records);
+	match records.error {
+		Some(e) => Err(e),
+		None => Ok(root),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{trie_root_from_reader, write_record};
+	use crate::trie_root;
+	use keccak_hasher::KeccakHasher;
+	use std::io;
+
+	fn records(input: &[(Vec<u8>, Vec<u8>)]) -> Vec<u8> {
+		let mut file = Vec::new();
+		for (key, value) in input {
+			write_record(&mut file, key, value).unwrap();
+		}
+		file
+	}
+
+	#[test]
+	fn matches_trie_root() {
+		for &count in &[0, 1, 2, 100, 1000] {
+			let input = (0..count as u32).map(|i| (i.to_be_bytes().to_vec(), vec![i as u8; i as usize % 50]));
+			let input = input.collect::<Vec<_>>();
+			let root = trie_root_from_reader::<KeccakHasher, _>(&records(&input)[..]).unwrap();
+			assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(input));
+		}
+	}
+
+	#[test]
+	fn fails_on_truncated_record() {
+		let file = records(&[(b"doe".to_vec(), b"reindeer".to_vec()), (b"dog".to_vec(), b"puppy".to_vec())]);
+		for len in [1, 4, 8, file.len() - 1].iter().map(|cut| file.len() - cut) {
+			let error = trie_root_from_reader::<KeccakHasher, _>(&file[..len]).unwrap_err();
+			assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+		}
+
+		// a corrupt length isn't trusted
+		let error = trie_root_from_reader::<KeccakHasher, _>(&[0xff, 0xff, 0xff, 0xff, 1, 2, 3][..]).unwrap_err();
+		assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+	}
+}
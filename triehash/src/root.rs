@@ -0,0 +1,127 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Interchangeable implementations of the trie root functions.
+
+use core::cmp;
+use core::marker::PhantomData;
+
+use crate::{trie_root_with_stream, AsValue, RlpTrieStream, TrieStream};
+
+use hash_db::Hasher;
+
+/// Algorithm generating trie roots hashed with `H` and encoded by `S`.
+///
+/// Code generic over this trait can be given cached or parallel implementations instead of
+/// `DefaultTrieRoot`. Only `trie_root` has to be implemented, the other roots are derived from it.
+pub trait TrieRoot<H: Hasher, S: TrieStream> {
+	/// Generates a trie root hash for a vector of key-value tuples, see `trie_root`.
+	fn trie_root<I, A, B>(&mut self, input: I) -> H::Out
+	where
+		I: IntoIterator<Item = (A, B)>,
+		A: AsRef<[u8]> + Ord,
+		B: AsValue;
+
+	/// Generates a key-hashed (secure) trie root hash for a vector of key-value tuples, see `sec_trie_root`.
+	fn sec_trie_root<I, A, B>(&mut self, input: I) -> H::Out
+	where
+		I: IntoIterator<Item = (A, B)>,
+		A: AsRef<[u8]>,
+		B: AsValue,
+		H::Out: cmp::Ord,
+	{
+		self.trie_root(input.into_iter().map(|(k, v)| (H::hash(k.as_ref()), v)))
+	}
+
+	/// Generates a trie root hash for a vector of values, see `ordered_trie_root`.
+	fn ordered_trie_root<I>(&mut self, input: I) -> H::Out
+	where
+		I: IntoIterator,
+		I::Item: AsValue,
+	{
+		self.trie_root(input.into_iter().enumerate().map(|(i, v)| (rlp::encode(&i), v)))
+	}
+}
+
+/// The trie root functions of this crate.
+///
+/// ```
+/// use triehash::{sec_trie_root, DefaultTrieRoot, RlpTrieStream, TrieRoot};
+/// use keccak_hasher::KeccakHasher;
+///
+/// fn state_root<R: TrieRoot<KeccakHasher, RlpTrieStream>>(roots: &mut R) -> [u8; 32] {
+/// 	roots.sec_trie_root(vec![("doe", "reindeer"), ("dog", "puppy")])
+/// }
+///
+/// let root = state_root(&mut DefaultTrieRoot::new());
+/// assert_eq!(root, sec_trie_root::<KeccakHasher, _, _, _>(vec![("doe", "reindeer"), ("dog", "puppy")]));
+/// ```
+pub struct DefaultTrieRoot<S = RlpTrieStream> {
+	_stream: PhantomData<S>,
+}
+
+impl<S> DefaultTrieRoot<S> {
+	/// Creates the default implementation.
+	pub fn new() -> Self {
+		DefaultTrieRoot { _stream: PhantomData }
+	}
+}
+
+impl<S> Default for DefaultTrieRoot<S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<H: Hasher, S: TrieStream> TrieRoot<H, S> for DefaultTrieRoot<S> {
+	fn trie_root<I, A, B>(&mut self, input: I) -> H::Out
+	where
+		I: IntoIterator<Item = (A, B)>,
+		A: AsRef<[u8]> + Ord,
+		B: AsValue,
+	{
+		trie_root_with_stream::<H, S, _, _, _>(input)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{DefaultTrieRoot, TrieRoot};
+	use crate::{ordered_trie_root, sec_trie_root, trie_root, AsValue, RlpTrieStream};
+	use keccak_hasher::KeccakHasher;
+
+	/// Counts the roots generated by the default implementation.
+	#[derive(Default)]
+	struct CountingTrieRoot {
+		inner: DefaultTrieRoot,
+		calls: usize,
+	}
+
+	impl TrieRoot<KeccakHasher, RlpTrieStream> for CountingTrieRoot {
+		fn trie_root<I, A, B>(&mut self, input: I) -> [u8; 32]
+		where
+			I: IntoIterator<Item = (A, B)>,
+			A: AsRef<[u8]> + Ord,
+			B: AsValue,
+		{
+			self.calls += 1;
+			TrieRoot::<KeccakHasher, RlpTrieStream>::trie_root(&mut self.inner, input)
+		}
+	}
+
+	#[test]
+	fn derived_roots_use_trie_root() {
+		let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+		let mut roots = CountingTrieRoot::default();
+		assert_eq!(roots.trie_root(v.clone()), trie_root::<KeccakHasher, _, _, _>(v.clone()));
+		assert_eq!(roots.sec_trie_root(v.clone()), sec_trie_root::<KeccakHasher, _, _, _>(v.clone()));
+		let values = v.iter().map(|(_, value)| value).collect::<Vec<_>>();
+		assert_eq!(roots.ordered_trie_root(values.clone()), ordered_trie_root::<KeccakHasher, _>(values));
+		assert_eq!(roots.calls, 3);
+	}
+}
@@ -0,0 +1,276 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Sorting inputs larger than memory.
+
+use std::cmp::{self, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::reader::{write_record, Records};
+use crate::{trie_root, trie_root_from_sorted_iter};
+
+use hash_db::Hasher;
+
+/// Maximum number of runs merged at once, so that few files are open at the same time.
+const MAX_FAN_IN: usize = 64;
+
+/// Number of external sorts started, telling apart the files of sorts sharing a directory.
+static SORTS: AtomicUsize = AtomicUsize::new(0);
+
+/// How `trie_root_with_sort` sorts its input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SortStrategy {
+	/// Sorts all items in memory, like `trie_root`.
+	InMemory,
+	/// Sorts runs of items in memory, writes them to files in `dir` and merges the files.
+	External {
+		/// Directory of the temporary files, which are removed afterwards.
+		dir: PathBuf,
+		/// Number of bytes of keys and values sorted in memory at once.
+		memory_limit: usize,
+	},
+}
+
+/// Sorted runs written to files, removed when dropped.
+struct Runs<'a> {
+	dir: &'a Path,
+	/// Number of the sort, unique in the process.
+	sort: usize,
+	/// Number of files created so far.
+	created: usize,
+	/// Files of the runs, in input order.
+	paths: Vec<PathBuf>,
+}
+
+impl<'a> Runs<'a> {
+	fn new(dir: &'a Path) -> Self {
+		Runs { dir, sort: SORTS.fetch_add(1, Ordering::Relaxed), created: 0, paths: Vec::new() }
+	}
+
+	/// Creates the file of a new run at position `index` of the runs.
+	///
+	/// Fails rather than overwriting an existing file, e.g. one left behind by a crashed process.
+	fn create(&mut self, index: usize) -> io::Result<BufWriter<File>> {
+		let name = format!("triehash-{}-{}-{}.run", std::process::id(), self.sort, self.created);
+		let path = self.dir.join(name);
+		let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+		self.created += 1;
+		self.paths.insert(index, path);
+		Ok(BufWriter::new(file))
+	}
+
+	/// Sorts `items` and writes them to a new run after the other ones.
+	fn write(&mut self, items: &mut Vec<(Vec<u8>, Vec<u8>)>) -> io::Result<()> {
+		// the sort is stable, so the values of a key stay in input order
+		items.sort_by(|a, b| a.0.cmp(&b.0));
+		let mut file = self.create(self.paths.len())?;
+		for (key, value) in items.drain(..) {
+			write_record(&mut file, &key, &value)?;
+		}
+		file.flush()
+	}
+
+	/// Merges consecutive runs into one until at most `MAX_FAN_IN` runs are left.
+	fn reduce(&mut self) -> io::Result<()> {
+		let mut first = 0;
+		while self.paths.len() > MAX_FAN_IN {
+			// start another pass once less than two runs are left to merge in this one
+			if self.paths.len() - first < 2 {
+				first = 0;
+			}
+			let end = cmp::min(first + MAX_FAN_IN, self.paths.len());
+			let mut merge = Merge::open(&self.paths[first..end])?;
+			// the merged run takes the place of its inputs, so the values of a key stay in input order
+			let mut file = self.create(end)?;
+			for (key, value) in &mut merge {
+				write_record(&mut file, &key, &value)?;
+			}
+			file.flush()?;
+			if let Some(e) = merge.error() {
+				return Err(e);
+			}
+			drop(merge);
+			for path in &self.paths[first..end] {
+				fs::remove_file(path)?;
+			}
+			self.paths.drain(first..end);
+			first += 1;
+		}
+		Ok(())
+	}
+}
+
+impl Drop for Runs<'_> {
+	fn drop(&mut self) {
+		for path in &self.paths {
+			let _ = fs::remove_file(path);
+		}
+	}
+}
+
+/// Merges sorted runs, yielding equal keys in the order of the runs.
+struct Merge<R> {
+	runs: Vec<Records<R>>,
+	/// Next key of every run which isn't exhausted, with the run index.
+	heap: BinaryHeap<Reverse<(Vec<u8>, usize)>>,
+	/// Value of the next key of every run.
+	values: Vec<Vec<u8>>,
+}
+
+impl<R: io::Read> Merge<R> {
+	fn new(runs: Vec<Records<R>>) -> Self {
+		let mut merge = Merge { values: vec![Vec::new(); runs.len()], runs, heap: BinaryHeap::new() };
+		for run in 0..merge.runs.len() {
+			merge.advance(run);
+		}
+		merge
+	}
+
+	/// Reads the next item of a run.
+	fn advance(&mut self, run: usize) {
+		if let Some((key, value)) = self.runs[run].next() {
+			self.values[run] = value;
+			self.heap.push(Reverse((key, run)));
+		}
+	}
+
+	/// Returns the first error of any run.
+	fn error(&mut self) -> Option<io::Error> {
+		self.runs.iter_mut().find_map(|run| run.error.take())
+	}
+}
+
+impl Merge<BufReader<File>> {
+	/// Opens the runs stored in `paths` for merging.
+	fn open(paths: &[PathBuf]) -> io::Result<Self> {
+		let files = paths.iter().map(|path| File::open(path).map(|file| Records::new(BufReader::new(file))));
+		Ok(Merge::new(files.collect::<io::Result<Vec<_>>>()?))
+	}
+}
+
+impl<R: io::Read> Iterator for Merge<R> {
+	type Item = (Vec<u8>, Vec<u8>);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let Reverse((key, run)) = self.heap.pop()?;
+		let value = std::mem::take(&mut self.values[run]);
+		self.advance(run);
+		Some((key, value))
+	}
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, sorting them as given by `strategy`.
+///
+/// With `SortStrategy::External` at most about `memory_limit` bytes of the input are held in
+/// memory, so the roots of inputs larger than memory (e.g. a full state re-hash) can be generated.
+/// The sorted runs are merged in passes of at most 64 files, so few files are open at once.
+/// If a key is given more than once, its last value is used.
+///
+/// ```
+/// use triehash::{trie_root, trie_root_with_sort, SortStrategy};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("dogglesworth", "cat"), ("doe", "reindeer"), ("dog", "puppy")];
+///
+/// let strategy = SortStrategy::External { dir: std::env::temp_dir(), memory_limit: 16 };
+/// let root = trie_root_with_sort::<KeccakHasher, _, _, _>(v.clone(), &strategy).unwrap();
+/// assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(v));
+/// ```
+pub fn trie_root_with_sort<H, I, A, B>(input: I, strategy: &SortStrategy) -> io::Result<H::Out>
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	H: Hasher,
+	H::Out: Ord,
+{
+	let (dir, memory_limit) = match strategy {
+		SortStrategy::InMemory => return Ok(trie_root::<H, _, _, _>(input)),
+		SortStrategy::External { dir, memory_limit } => (dir, *memory_limit),
+	};
+
+	let mut runs = Runs::new(dir);
+	let mut items = Vec::new();
+	let mut size = 0;
+	for (key, value) in input {
+		let (key, value) = (key.as_ref().to_vec(), value.as_ref().to_vec());
+		size += key.len() + value.len();
+		items.push((key, value));
+		if size >= memory_limit {
+			runs.write(&mut items)?;
+			size = 0;
+		}
+	}
+	if !items.is_empty() {
+		runs.write(&mut items)?;
+	}
+
+	runs.reduce()?;
+	let mut merge = Merge::open(&runs.paths)?;
+	let root = trie_root_from_sorted_iter::<H, _, _, _>(&mut merge);
+	match merge.error() {
+		Some(e) => Err(e),
+		None => Ok(root),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{trie_root_with_sort, SortStrategy};
+	use crate::trie_root;
+	use hash_db::Hasher;
+	use keccak_hasher::KeccakHasher;
+
+	#[test]
+	fn external_sort_matches_trie_root() {
+		let dir = std::env::temp_dir().join(format!("triehash-sort-test-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+
+		// duplicated keys in different runs, the last value wins
+		let input = (0..1000u32)
+			.map(|i| (KeccakHasher::hash(&(i % 700).to_le_bytes())[..2].to_vec(), i.to_le_bytes().to_vec()))
+			.collect::<Vec<_>>();
+		for &memory_limit in &[0, 100, 1000, 1_000_000] {
+			let strategy = SortStrategy::External { dir: dir.clone(), memory_limit };
+			let root = trie_root_with_sort::<KeccakHasher, _, _, _>(input.clone(), &strategy).unwrap();
+			assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(input.clone()));
+		}
+
+		// temporary files are removed
+		assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+		std::fs::remove_dir(&dir).unwrap();
+	}
+
+	#[test]
+	fn concurrent_sorts_share_a_directory() {
+		let dir = std::env::temp_dir().join(format!("triehash-sort-concurrent-test-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+
+		let threads = (0..4u32)
+			.map(|t| {
+				let dir = dir.clone();
+				std::thread::spawn(move || {
+					let input = (0..500u32).map(|i| (i.to_le_bytes(), (i * t).to_le_bytes())).collect::<Vec<_>>();
+					let strategy = SortStrategy::External { dir, memory_limit: 16 };
+					let root = trie_root_with_sort::<KeccakHasher, _, _, _>(input.clone(), &strategy).unwrap();
+					assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(input));
+				})
+			})
+			.collect::<Vec<_>>();
+		for thread in threads {
+			thread.join().unwrap();
+		}
+
+		assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+		std::fs::remove_dir(&dir).unwrap();
+	}
+}
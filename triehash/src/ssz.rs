@@ -0,0 +1,167 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! SSZ merkleization of key-value sets.
+
+use crate::rstd::*;
+#[cfg(not(feature = "std"))]
+use alloc::vec;
+
+use crate::hasher::IncrementalHasher;
+
+/// Chunk of the SSZ merkleization.
+type Chunk = [u8; 32];
+
+/// Maximum sizes of the SSZ list of key-value pairs, which set the depth of its merkle trees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SszLimits {
+	/// Maximum length of a key in bytes.
+	pub key_bytes: usize,
+	/// Maximum length of a value in bytes.
+	pub value_bytes: usize,
+	/// Maximum number of key-value pairs.
+	pub items: usize,
+}
+
+/// Hashes two chunks into their parent.
+fn hash_pair<H: IncrementalHasher>(left: &[u8], right: &[u8]) -> Chunk {
+	let mut parent = [0; 32];
+	parent.copy_from_slice(H::hash_parts([left, right].iter().copied()).as_ref());
+	parent
+}
+
+/// Merkleizes `chunks`, padded with zero chunks to the next power of two of `limit`.
+fn merkleize<H: IncrementalHasher>(mut chunks: Vec<Chunk>, limit: usize) -> Chunk {
+	assert!(chunks.len() <= limit, "SSZ list of {} chunks exceeds its limit of {}", chunks.len(), limit);
+	let depth = limit.next_power_of_two().trailing_zeros();
+	let mut zero = [0; 32];
+	for _ in 0..depth {
+		if chunks.len() % 2 == 1 {
+			chunks.push(zero);
+		}
+		chunks = chunks.chunks(2).map(|pair| hash_pair::<H>(&pair[0], &pair[1])).collect();
+		zero = hash_pair::<H>(&zero, &zero);
+	}
+	chunks.first().cloned().unwrap_or(zero)
+}
+
+/// Hashes the root of a list together with its length.
+fn mix_in_length<H: IncrementalHasher>(root: &Chunk, len: usize) -> H::Out {
+	let mut length = [0; 32];
+	length[..8].copy_from_slice(&(len as u64).to_le_bytes());
+	H::hash_parts([&root[..], &length[..]].iter().copied())
+}
+
+/// Root of a byte list of at most `limit` bytes.
+fn byte_list_root<H: IncrementalHasher>(bytes: &[u8], limit: usize) -> Chunk {
+	let chunks = bytes
+		.chunks(32)
+		.map(|chunk| {
+			let mut padded = [0; 32];
+			padded[..chunk.len()].copy_from_slice(chunk);
+			padded
+		})
+		.collect();
+	let mut root = [0; 32];
+	root.copy_from_slice(mix_in_length::<H>(&merkleize::<H>(chunks, limit.div_ceil(32)), bytes.len()).as_ref());
+	root
+}
+
+/// Generates the SSZ root of a vector of key-value tuples.
+///
+/// The tuples are sorted by key (if a key is given more than once, its last value is used) and
+/// merkleized as `List[Container(key: ByteList[key_bytes], value: ByteList[value_bytes]), items]`,
+/// hashing with `H`. SSZ uses SHA-256, `H` has to produce 32 byte hashes. Every pair of chunks
+/// is hashed in two parts, so `H` is an `IncrementalHasher`, e.g. `sha256_hasher::Sha256Hasher`
+/// with its `triehash` feature.
+///
+/// This gives the SSZ root of the same input as `trie_root`, e.g. to cross-check bridges.
+///
+/// # Panics
+///
+/// Panics if a key, a value or the number of tuples exceeds its limit.
+///
+/// ```
+/// # #[cfg(feature = "keccak")] {
+/// use triehash::{ssz_root, SszLimits};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let limits = SszLimits { key_bytes: 32, value_bytes: 1024, items: 1 << 20 };
+/// let a = ssz_root::<KeccakHasher, _, _, _>(vec![("doe", "reindeer"), ("dog", "puppy")], limits);
+/// let b = ssz_root::<KeccakHasher, _, _, _>(vec![("dog", "puppy"), ("doe", "reindeer")], limits);
+/// assert_eq!(a, b);
+/// # }
+/// ```
+pub fn ssz_root<H, I, A, B>(input: I, limits: SszLimits) -> H::Out
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	H: IncrementalHasher,
+{
+	assert_eq!(H::LENGTH, 32, "SSZ merkleization needs 32 byte hashes");
+	let items = input.into_iter().collect::<BTreeMap<_, _>>();
+	let roots = items
+		.iter()
+		.map(|(key, value)| {
+			let key = byte_list_root::<H>(key.as_ref(), limits.key_bytes);
+			let value = byte_list_root::<H>(value.as_ref(), limits.value_bytes);
+			merkleize::<H>(vec![key, value], 2)
+		})
+		.collect();
+	mix_in_length::<H>(&merkleize::<H>(roots, limits.items), items.len())
+}
+
+#[cfg(all(test, feature = "keccak"))]
+mod tests {
+	use super::{ssz_root, SszLimits};
+	use hash_db::Hasher;
+	use keccak_hasher::KeccakHasher;
+
+	/// Hashes the concatenation of two chunks.
+	fn hash_pair(left: &[u8], right: &[u8]) -> [u8; 32] {
+		KeccakHasher::hash(&[left, right].concat())
+	}
+
+	/// Hashes a chunk with a length.
+	fn with_length(root: &[u8], len: u8) -> [u8; 32] {
+		let mut length = [0; 32];
+		length[0] = len;
+		hash_pair(root, &length)
+	}
+
+	#[test]
+	fn merkleizes_padded_lists() {
+		let limits = SszLimits { key_bytes: 32, value_bytes: 64, items: 3 };
+		let value = [7; 40];
+		let root = ssz_root::<KeccakHasher, _, _, _>(vec![(&b"key"[..], &value[..])], limits);
+
+		let zero = [0; 32];
+		let mut key = [0; 32];
+		key[..3].copy_from_slice(b"key");
+		let key = with_length(&key, 3);
+		let mut tail = [0; 32];
+		tail[..8].copy_from_slice(&value[32..]);
+		let value = with_length(&hash_pair(&value[..32], &tail), 40);
+		let item = hash_pair(&key, &value);
+		let zero_pair = hash_pair(&zero, &zero);
+		let list = hash_pair(&hash_pair(&item, &zero), &zero_pair);
+		assert_eq!(root, with_length(&list, 1));
+
+		let empty = Vec::<(Vec<u8>, Vec<u8>)>::new();
+		let zero_list = hash_pair(&zero_pair, &zero_pair);
+		assert_eq!(ssz_root::<KeccakHasher, _, _, _>(empty, limits), with_length(&zero_list, 0));
+	}
+
+	#[test]
+	#[should_panic(expected = "SSZ list of 2 chunks exceeds its limit of 1")]
+	fn rejects_long_keys() {
+		let limits = SszLimits { key_bytes: 32, value_bytes: 32, items: 1 };
+		ssz_root::<KeccakHasher, _, _, _>(vec![(vec![1; 33], vec![])], limits);
+	}
+}
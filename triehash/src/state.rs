@@ -0,0 +1,108 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! State root of accounts keyed by address.
+
+use core::marker::PhantomData;
+
+use crate::sec_trie_root;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use hash_db::Hasher;
+
+/// Encodes the body of an account stored in the state trie.
+pub trait AccountEncoder {
+	/// Account type.
+	type Account;
+
+	/// Returns the encoding of the account stored as the trie value.
+	fn encode(account: &Self::Account) -> Vec<u8>;
+}
+
+/// Encodes accounts with their `rlp::Encodable` implementation, as Ethereum does.
+pub struct RlpAccountEncoder<T>(PhantomData<T>);
+
+impl<T: rlp::Encodable> AccountEncoder for RlpAccountEncoder<T> {
+	type Account = T;
+
+	fn encode(account: &T) -> Vec<u8> {
+		rlp::encode(account).to_vec()
+	}
+}
+
+/// Generates the state root of `(address, account)` pairs.
+///
+/// Accounts are encoded with `E` and keyed by the hash of their address, like in `sec_trie_root`.
+///
+/// ```
+/// use triehash::{sec_trie_root, state_root, RlpAccountEncoder};
+/// use keccak_hasher::KeccakHasher;
+///
+/// struct Account {
+/// 	nonce: u64,
+/// 	balance: u64,
+/// }
+///
+/// impl rlp::Encodable for Account {
+/// 	fn rlp_append(&self, s: &mut rlp::RlpStream) {
+/// 		s.begin_list(2).append(&self.nonce).append(&self.balance);
+/// 	}
+/// }
+///
+/// let accounts = vec![([0x11u8; 20], Account { nonce: 1, balance: 100 })];
+/// let encoded = vec![([0x11u8; 20], rlp::encode_list::<u64, _>(&[1, 100]))];
+/// assert_eq!(
+/// 	state_root::<KeccakHasher, RlpAccountEncoder<Account>, _, _>(accounts),
+/// 	sec_trie_root::<KeccakHasher, _, _, _>(encoded),
+/// );
+/// ```
+pub fn state_root<H, E, I, A>(accounts: I) -> H::Out
+where
+	I: IntoIterator<Item = (A, E::Account)>,
+	A: AsRef<[u8]>,
+	H: Hasher,
+	<H as hash_db::Hasher>::Out: Ord,
+	E: AccountEncoder,
+{
+	sec_trie_root::<H, _, _, _>(accounts.into_iter().map(|(address, account)| (address, E::encode(&account))))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{state_root, AccountEncoder};
+	use hash_db::Hasher;
+	use keccak_hasher::KeccakHasher;
+
+	/// Stores only the balance, as a big-endian integer.
+	struct BalanceEncoder;
+
+	impl AccountEncoder for BalanceEncoder {
+		type Account = u64;
+
+		fn encode(balance: &u64) -> Vec<u8> {
+			balance.to_be_bytes().to_vec()
+		}
+	}
+
+	#[test]
+	fn empty_state_root() {
+		let root = state_root::<KeccakHasher, BalanceEncoder, Vec<([u8; 20], u64)>, _>(vec![]);
+		assert_eq!(root, KeccakHasher::hash(&rlp::NULL_RLP));
+	}
+
+	#[test]
+	fn custom_encoder() {
+		let single = state_root::<KeccakHasher, BalanceEncoder, _, _>(vec![([1u8; 20], 7)]);
+		let mut leaf = rlp::RlpStream::new_list(2);
+		let key = KeccakHasher::hash(&[1u8; 20]);
+		leaf.append(&[&[0x20][..], &key[..]].concat());
+		leaf.append(&7u64.to_be_bytes().to_vec());
+		assert_eq!(single, KeccakHasher::hash(&leaf.out()));
+	}
+}
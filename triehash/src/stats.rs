@@ -0,0 +1,131 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Statistics about the shape of a generated trie.
+
+use crate::hex_prefix::hex_prefix_is_leaf;
+use crate::{trie_root_with_visitor, NodeVisitor};
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use hash_db::Hasher;
+use rlp::Rlp;
+
+/// Node counts and sizes of a trie.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TrieStats {
+	/// Number of leaf nodes.
+	pub leaf_count: usize,
+	/// Number of branch nodes.
+	pub branch_count: usize,
+	/// Number of extension nodes.
+	pub extension_count: usize,
+	/// Number of nodes on the longest path from the root to a leaf.
+	pub max_depth: usize,
+	/// Total size of the nodes referenced by hash, i.e. of the nodes which would be stored
+	/// separately in a database. Nodes inlined into their parent are counted as part of it.
+	pub encoded_bytes: usize,
+}
+
+#[derive(Default)]
+struct StatsCollector {
+	stats: TrieStats,
+	/// Paths and heights of the subtries whose parent hasn't been visited yet.
+	subtries: Vec<(Vec<u8>, usize)>,
+}
+
+impl<H: Hasher> NodeVisitor<H> for StatsCollector {
+	fn visit(&mut self, path: &[u8], encoded: &[u8], hash: Option<&H::Out>) {
+		let rlp = Rlp::new(encoded);
+		match rlp.item_count() {
+			Ok(17) => self.stats.branch_count += 1,
+			Ok(2) => match rlp.at(0).and_then(|partial| partial.data()) {
+				Ok(partial) if hex_prefix_is_leaf(partial) => self.stats.leaf_count += 1,
+				_ => self.stats.extension_count += 1,
+			},
+			// empty trie
+			_ => return,
+		}
+		if hash.is_some() {
+			self.stats.encoded_bytes += encoded.len();
+		}
+
+		// nodes are visited bottom-up, so the children of this node are the
+		// pending subtries below its path
+		let mut height = 1;
+		while let Some((child_path, child_height)) = self.subtries.pop() {
+			if !child_path.starts_with(path) {
+				self.subtries.push((child_path, child_height));
+				break;
+			}
+			height = height.max(child_height + 1);
+		}
+		self.stats.max_depth = self.stats.max_depth.max(height);
+		self.subtries.push((path.to_vec(), height));
+	}
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, together with statistics
+/// about the nodes of the trie.
+///
+/// ```
+/// use triehash::trie_root_with_stats;
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![
+/// 	("doe", "reindeer"),
+/// 	("dog", "puppy"),
+/// 	("dogglesworth", "cat"),
+/// ];
+///
+/// let (_, stats) = trie_root_with_stats::<KeccakHasher, _, _, _>(v);
+/// assert_eq!(stats.leaf_count, 2);
+/// assert_eq!(stats.branch_count, 2);
+/// assert_eq!(stats.extension_count, 1);
+/// assert_eq!(stats.max_depth, 4);
+/// ```
+pub fn trie_root_with_stats<H, I, A, B>(input: I) -> (H::Out, TrieStats)
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsRef<[u8]>,
+	H: Hasher,
+{
+	let mut collector = StatsCollector::default();
+	let root = trie_root_with_visitor::<H, _, _, _, _>(input, &mut collector);
+	(root, collector.stats)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{trie_root_with_stats, TrieStats};
+	use crate::trie_root_with_multi_proof;
+	use keccak_hasher::KeccakHasher;
+
+	#[test]
+	fn empty_trie_stats() {
+		let (_, stats) = trie_root_with_stats::<KeccakHasher, Vec<(Vec<u8>, Vec<u8>)>, _, _>(vec![]);
+		assert_eq!(stats, TrieStats::default());
+	}
+
+	#[test]
+	fn single_leaf_stats() {
+		let (_, stats) = trie_root_with_stats::<KeccakHasher, _, _, _>(vec![(b"key", b"value")]);
+		assert_eq!(stats, TrieStats { leaf_count: 1, max_depth: 1, encoded_bytes: 12, ..Default::default() });
+	}
+
+	#[test]
+	fn encoded_bytes_match_stored_nodes() {
+		let input = (0..500u32).map(|i| (i.to_be_bytes(), i.to_le_bytes())).collect::<Vec<_>>();
+		let keys = input.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+		let (_, nodes) = trie_root_with_multi_proof::<KeccakHasher, _, _, _, _>(input.clone(), &keys);
+		let (_, stats) = trie_root_with_stats::<KeccakHasher, _, _, _>(input);
+		assert_eq!(stats.encoded_bytes, nodes.iter().map(|node| node.len()).sum::<usize>());
+		assert_eq!(stats.leaf_count, 500);
+	}
+}
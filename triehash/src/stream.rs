@@ -0,0 +1,318 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Encoding of trie nodes.
+
+use core::cmp;
+use core::marker::PhantomData;
+
+use crate::key::{HexNibbles, KeyEncode};
+
+use bytes::BytesMut;
+use hash_db::Hasher;
+use rlp::RlpStream;
+
+/// Value stored in a leaf or branch node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value<'a> {
+	/// The value itself.
+	Inline(&'a [u8]),
+	/// Hash of a value too long to be stored in the node.
+	Hashed(&'a [u8]),
+}
+
+impl<'a> Value<'a> {
+	/// Bytes stored in the node, either the value or its hash.
+	pub fn as_bytes(&self) -> &'a [u8] {
+		match *self {
+			Value::Inline(bytes) | Value::Hashed(bytes) => bytes,
+		}
+	}
+}
+
+/// Builds the encoding of a single trie node.
+///
+/// Every node is encoded into its own stream. Once a child node is complete, its stream is
+/// appended to the stream of its parent with `append_substream`. Partial keys are given in
+/// digits of the radix of the trie, e.g. in nibbles for the Ethereum trie.
+pub trait TrieStream {
+	/// Creates an empty stream.
+	fn new() -> Self;
+
+	/// Creates an empty stream for a node with `items` items below it, whose partial keys have
+	/// `key_len` digits in total, so the stream can reserve its buffer up front.
+	///
+	/// Streams are reused for other nodes once they are complete, so this is just a hint.
+	/// Defaults to `new`.
+	fn with_capacity(items: usize, key_len: usize) -> Self
+	where
+		Self: Sized,
+	{
+		let _ = (items, key_len);
+		Self::new()
+	}
+
+	/// Empties the stream, so it can be reused for another node.
+	fn clear(&mut self);
+
+	/// Encodes the empty node, i.e. the root of an empty trie.
+	fn append_empty_data(&mut self);
+
+	/// Encodes a leaf node holding the rest of a key and its value.
+	fn append_leaf(&mut self, partial: &[u8], value: Value);
+
+	/// Starts encoding an extension node, its only child is appended next.
+	fn begin_extension(&mut self, partial: &[u8]);
+
+	/// Starts encoding a branch node.
+	///
+	/// `children` tells for every digit of the radix whether the branch has a child in that
+	/// slot. The children are then appended in order, either with `append_empty_child` or with
+	/// `append_substream`, followed by `end_branch`.
+	fn begin_branch(&mut self, has_value: bool, children: impl Iterator<Item = bool>);
+
+	/// Starts encoding a branch node of `arity` slots, the radix of the trie, e.g. 2 for binary
+	/// tries and 256 for tries keyed by bytes.
+	///
+	/// This is how branches are started while building a trie. Defaults to `begin_branch`, which
+	/// `children` gives `arity` slots.
+	fn begin_branch_with_arity(&mut self, arity: usize, has_value: bool, children: impl Iterator<Item = bool>) {
+		let _ = arity;
+		self.begin_branch(has_value, children)
+	}
+
+	/// Appends an empty slot of a branch node.
+	fn append_empty_child(&mut self);
+
+	/// Completes a branch node, with the value of the key ending at the branch.
+	fn end_branch(&mut self, value: Option<Value>);
+
+	/// Whether branch nodes hold the value of the key ending at them, given to `end_branch`.
+	///
+	/// Otherwise the value is encoded as a leaf with an empty partial key, which is appended to
+	/// the branch after its last child, and `end_branch` is given no value. Defaults to true.
+	fn has_value_slot(&self) -> bool {
+		true
+	}
+
+	/// Whether extension nodes are encoded as nodes of their own.
+	///
+	/// Otherwise the child of an extension, always a branch, is stored in the extension itself:
+	/// it is given to `append_substream`, which returns `None`, and is never referenced by hash.
+	/// Defaults to true.
+	fn has_extension_nodes(&self) -> bool {
+		true
+	}
+
+	/// Appends a reference to the complete node encoded in `child`.
+	///
+	/// Returns the hash the child is referenced by, or `None` if it is inlined.
+	fn append_substream<H: Hasher>(&mut self, child: &Self) -> Option<H::Out>;
+
+	/// Appends a reference to a child node by its already computed `hash`, e.g. a subtrie which
+	/// hasn't changed since it was last encoded.
+	///
+	/// The child is referenced by hash even if it would be inlined by `append_substream`.
+	fn append_hashed_subtree(&mut self, hash: &[u8]);
+
+	/// Encoding of the node.
+	fn as_raw(&self) -> &[u8];
+}
+
+/// `INLINE` parameter of `RlpTrieStream` inlining the nodes shorter than `Hasher::LENGTH`.
+pub const HASH_LENGTH: usize = usize::MAX;
+
+/// RLP encoding of the Ethereum trie, with partial keys encoded by `E`.
+///
+/// Nodes shorter than `INLINE` bytes are inlined into their parents. By default that is the
+/// length of the hash the nodes are referenced by, i.e. 32 bytes for Keccak-256; other values
+/// set a fixed threshold, e.g. `RlpTrieStream<HexNibbles, 0>` references every node by hash.
+///
+/// Branches end with a slot for the value of the key ending at them. Without `VALUE_SLOT`, the
+/// value is stored in a separate leaf instead, whose reference ends the branch if it has a value.
+pub struct RlpTrieStream<E = HexNibbles, const INLINE: usize = HASH_LENGTH, const VALUE_SLOT: bool = true> {
+	stream: RlpStream,
+	_key: PhantomData<E>,
+}
+
+impl<E: KeyEncode, const INLINE: usize, const VALUE_SLOT: bool> TrieStream for RlpTrieStream<E, INLINE, VALUE_SLOT> {
+	fn new() -> Self {
+		RlpTrieStream { stream: RlpStream::new(), _key: PhantomData }
+	}
+
+	fn with_capacity(items: usize, key_len: usize) -> Self {
+		// a leaf holds the key (with a value of about the size of a hash), a branch references
+		// up to `RADIX` children of at most 33 bytes each
+		let capacity = match items {
+			0 | 1 => key_len + 40,
+			_ => cmp::min(items, E::RADIX) * 33 + 40,
+		};
+		RlpTrieStream { stream: RlpStream::new_with_buffer(BytesMut::with_capacity(capacity)), _key: PhantomData }
+	}
+
+	fn clear(&mut self) {
+		self.stream.clear();
+	}
+
+	fn append_empty_data(&mut self) {
+		self.stream.append_empty_data();
+	}
+
+	fn append_leaf(&mut self, partial: &[u8], value: Value) {
+		self.stream.begin_list(2);
+		E::append_partial(partial, true, &mut self.stream);
+		self.stream.append(&value.as_bytes());
+	}
+
+	fn begin_extension(&mut self, partial: &[u8]) {
+		self.stream.begin_list(2);
+		E::append_partial(partial, false, &mut self.stream);
+	}
+
+	fn begin_branch(&mut self, has_value: bool, _children: impl Iterator<Item = bool>) {
+		self.stream.begin_list(E::RADIX + (VALUE_SLOT || has_value) as usize);
+	}
+
+	fn append_empty_child(&mut self) {
+		self.stream.append_empty_data();
+	}
+
+	fn end_branch(&mut self, value: Option<Value>) {
+		match value {
+			Some(value) => {
+				self.stream.append(&value.as_bytes());
+			}
+			None if VALUE_SLOT => {
+				self.stream.append_empty_data();
+			}
+			None => {}
+		}
+	}
+
+	fn has_value_slot(&self) -> bool {
+		VALUE_SLOT
+	}
+
+	fn append_substream<H: Hasher>(&mut self, child: &Self) -> Option<H::Out> {
+		let encoded = child.stream.as_raw();
+		let inline = if INLINE == HASH_LENGTH { H::LENGTH } else { INLINE };
+		if encoded.len() < inline {
+			self.stream.append_raw(encoded, 1);
+			return None;
+		}
+		let hash = H::hash(encoded);
+		self.append_hashed_subtree(hash.as_ref());
+		Some(hash)
+	}
+
+	fn append_hashed_subtree(&mut self, hash: &[u8]) {
+		self.stream.append(&hash);
+	}
+
+	fn as_raw(&self) -> &[u8] {
+		self.stream.as_raw()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{RlpTrieStream, TrieStream, Value, HASH_LENGTH};
+	use crate::hex_prefix::hex_prefix_encode;
+	use crate::{trie_nodes, trie_root_stream, HexNibbles};
+	use hash_db::Hasher;
+	use keccak_hasher::KeccakHasher;
+	use rlp::RlpStream;
+
+	#[test]
+	fn hashed_subtrees_are_appended_by_hash() {
+		let mut child = RlpTrieStream::<HexNibbles>::new();
+		child.append_leaf(&[1, 2, 3], Value::Inline(&[7; 40]));
+
+		let mut branch = RlpTrieStream::<HexNibbles>::new();
+		branch.begin_branch(false, (0..16).map(|digit| digit == 0));
+		let hash = branch.append_substream::<KeccakHasher>(&child);
+		assert_eq!(hash, Some(KeccakHasher::hash(child.as_raw())));
+
+		let mut reused = RlpTrieStream::<HexNibbles>::new();
+		reused.begin_branch(false, (0..16).map(|digit| digit == 0));
+		reused.append_hashed_subtree(&KeccakHasher::hash(child.as_raw()));
+		for stream in [&mut branch, &mut reused].iter_mut() {
+			for _ in 1..16 {
+				stream.append_empty_child();
+			}
+			stream.end_branch(None);
+		}
+		assert_eq!(branch.as_raw(), reused.as_raw());
+	}
+
+	#[test]
+	fn inline_threshold_is_configurable() {
+		let input = vec![(&b"doe"[..], &b"reindeer"[..]), (b"dog", b"puppy")];
+		// the leaves are inlined into the branch
+		assert_eq!(trie_nodes::<KeccakHasher, RlpTrieStream, _, _, _>(input.clone()).len(), 2);
+		assert_eq!(trie_nodes::<KeccakHasher, RlpTrieStream<HexNibbles, 8>, _, _, _>(input.clone()).len(), 4);
+		assert_eq!(trie_nodes::<KeccakHasher, RlpTrieStream<HexNibbles, 0>, _, _, _>(input).len(), 4);
+	}
+
+	#[test]
+	fn inline_threshold_defaults_to_hash_length() {
+		// a 25 byte leaf is inlined below 32 byte hashes, but referenced by 20 byte ones
+		let mut child = RlpTrieStream::<HexNibbles>::new();
+		child.append_leaf(&[1, 2, 3], Value::Inline(&[7; 20]));
+		assert_eq!(child.as_raw().len(), 25);
+
+		let mut branch = RlpTrieStream::<HexNibbles>::new();
+		assert_eq!(branch.append_substream::<KeccakHasher>(&child), None);
+		assert_eq!(branch.append_substream::<Keccak160>(&child), Some(Keccak160::hash(child.as_raw())));
+		let mut child = RlpTrieStream::<HexNibbles, 32>::new();
+		child.append_leaf(&[1, 2, 3], Value::Inline(&[7; 20]));
+		let mut fixed = RlpTrieStream::<HexNibbles, 32>::new();
+		assert_eq!(fixed.append_substream::<Keccak160>(&child), None);
+	}
+
+	#[test]
+	fn branch_values_can_be_separate_leaves() {
+		let leaf = |partial: &[u8], value: &[u8]| {
+			let mut leaf = RlpStream::new_list(2);
+			leaf.append_iter(hex_prefix_encode(partial, true));
+			leaf.append(&value);
+			leaf.out()
+		};
+		let mut branch = RlpStream::new_list(17);
+		branch.append_empty_data();
+		branch.append_raw(&leaf(&[0], b"b"), 1);
+		for _ in 2..16 {
+			branch.append_empty_data();
+		}
+		branch.append_raw(&leaf(&[], b"a"), 1);
+
+		let input = vec![(&b""[..], &b"a"[..]), (b"\x10", b"b")];
+		let stream = trie_root_stream::<KeccakHasher, RlpTrieStream<HexNibbles, HASH_LENGTH, false>, _, _, _>(input);
+		assert_eq!(stream.as_raw(), &branch.out()[..]);
+
+		// branches without a value have no slot for it
+		let input = vec![(&b"\x00"[..], &b"a"[..]), (b"\x10", b"b")];
+		let stream = trie_root_stream::<KeccakHasher, RlpTrieStream<HexNibbles, HASH_LENGTH, false>, _, _, _>(input);
+		assert_eq!(rlp::Rlp::new(stream.as_raw()).item_count(), Ok(16));
+	}
+
+	/// Keccak-256 truncated to 20 bytes.
+	struct Keccak160;
+
+	impl Hasher for Keccak160 {
+		type Out = [u8; 20];
+		type StdHasher = <KeccakHasher as Hasher>::StdHasher;
+		const LENGTH: usize = 20;
+
+		fn hash(x: &[u8]) -> [u8; 20] {
+			let mut out = [0; 20];
+			out.copy_from_slice(&KeccakHasher::hash(x)[..20]);
+			out
+		}
+	}
+}
@@ -0,0 +1,286 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reference implementation of the trie root for differential testing.
+//!
+//! The reference builder follows the definition of the trie directly: it is slow, but
+//! short enough to be checked by reading. Enable the `test-support` feature to use it
+//! from other crates.
+
+use crate::hex_prefix::hex_prefix_encode;
+use crate::iter_build::key_nibbles;
+use crate::rstd::*;
+use crate::{shared_prefix_len, stream_root_with_visitor, NodeVisitor, TrieStream};
+#[cfg(not(feature = "std"))]
+use alloc::vec;
+
+use hash_db::Hasher;
+use rlp::RlpStream;
+
+/// Key-value pairs of a trie.
+pub type Items = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Generates the trie root hash of the input with the reference builder.
+///
+/// If a key is given more than once, its last value is used.
+pub fn reference_trie_root<H: Hasher>(input: &[(Vec<u8>, Vec<u8>)]) -> H::Out {
+	let items = input.iter().map(|(k, v)| (key_nibbles(k), v.clone())).collect::<BTreeMap<_, _>>();
+	let items = items.into_iter().collect::<Vec<_>>();
+	H::hash(&encode_node::<H>(&items, 0))
+}
+
+/// Encodes the node holding `items`, whose keys (in nibbles) share the first `depth` nibbles.
+fn encode_node<H: Hasher>(items: &[(Vec<u8>, Vec<u8>)], depth: usize) -> Vec<u8> {
+	let mut stream = RlpStream::new();
+	match items {
+		[] => {
+			stream.append_empty_data();
+		}
+		[(key, value)] => {
+			stream.begin_list(2);
+			stream.append_iter(hex_prefix_encode(&key[depth..], true));
+			stream.append(value);
+		}
+		_ => {
+			let common = items.iter().map(|(key, _)| shared_prefix_len(&items[0].0, key)).min().unwrap_or(0);
+			if common > depth {
+				stream.begin_list(2);
+				stream.append_iter(hex_prefix_encode(&items[0].0[depth..common], false));
+				stream.append_raw(&reference::<H>(encode_node::<H>(items, common)), 1);
+			} else {
+				stream.begin_list(17);
+				for nibble in 0..16 {
+					let children = items
+						.iter()
+						.filter(|(key, _)| key.len() > depth && key[depth] == nibble)
+						.cloned()
+						.collect::<Vec<_>>();
+					match children.is_empty() {
+						true => stream.append_empty_data(),
+						false => stream.append_raw(&reference::<H>(encode_node::<H>(&children, depth + 1)), 1),
+					};
+				}
+				match items.iter().find(|(key, _)| key.len() == depth) {
+					Some((_, value)) => stream.append(value),
+					None => stream.append_empty_data(),
+				};
+			}
+		}
+	}
+	stream.out().to_vec()
+}
+
+/// Returns the node itself if it is shorter than a hash, its encoded hash otherwise.
+fn reference<H: Hasher>(encoded: Vec<u8>) -> Vec<u8> {
+	match encoded.len() {
+		0..=31 => encoded,
+		_ => rlp::encode(&H::hash(&encoded).as_ref()).to_vec(),
+	}
+}
+
+/// Inputs covering the different node kinds: empty and single item tries, keys which are
+/// prefixes of other keys, long shared prefixes, and values shorter and longer than a hash.
+///
+/// `H` only seeds the pseudo-random inputs.
+pub fn test_inputs<H: Hasher>() -> Vec<Items> {
+	let mut inputs = vec![
+		vec![],
+		vec![(b"key".to_vec(), b"value".to_vec())],
+		vec![(vec![], b"empty key".to_vec()), (vec![0x12], b"one".to_vec())],
+		vec![(vec![0x12; 40], vec![1; 40]), (vec![0x12; 41], vec![2; 40])],
+		vec![(b"doe".to_vec(), b"reindeer".to_vec()), (b"dog".to_vec(), b"puppy".to_vec())],
+	];
+	for &count in &[16, 100, 500] {
+		let mut input = Vec::with_capacity(count);
+		for i in 0..count as u32 {
+			let seed = H::hash(&i.to_le_bytes());
+			let seed = seed.as_ref();
+			let key = seed[..1 + seed[0] as usize % 6].to_vec();
+			let value = seed[..1 + seed[1] as usize % 32].repeat(1 + seed[2] as usize % 2);
+			input.push((key[..key.len() / 2].to_vec(), value.clone()));
+			input.push((key, value));
+		}
+		inputs.push(input);
+	}
+	inputs.push(indexed_input(64));
+	inputs
+}
+
+/// `count` keys of big-endian indices with 40 byte values, so the trie is made of full branches
+/// referencing their leaves by hash.
+pub fn indexed_input(count: u32) -> Items {
+	(0..count).map(|i| (i.to_be_bytes().to_vec(), vec![i as u8; 40])).collect()
+}
+
+/// Checks `root` against the reference implementation for every input of `test_inputs`.
+///
+/// # Panics
+///
+/// Panics with the offending input if the roots differ.
+///
+/// ```
+/// use triehash::{test_support::assert_matches_reference, trie_root};
+/// use keccak_hasher::KeccakHasher;
+///
+/// assert_matches_reference::<KeccakHasher, _>(|input| trie_root::<KeccakHasher, _, _, _>(input.to_vec()));
+/// ```
+pub fn assert_matches_reference<H, F>(root: F)
+where
+	H: Hasher,
+	F: Fn(&[(Vec<u8>, Vec<u8>)]) -> H::Out,
+{
+	for input in test_inputs::<H>() {
+		let expected = reference_trie_root::<H>(&input);
+		assert!(root(&input) == expected, "root differs from the reference for input {:?}", input);
+	}
+}
+
+/// Node of a trie, as built.
+struct Node<H: Hasher> {
+	path: Vec<u8>,
+	encoded: Vec<u8>,
+	/// Hash of the node, unless it is inlined into its parent.
+	hash: Option<H::Out>,
+}
+
+/// Records the nodes of a trie in the order they are built.
+struct NodeRecorder<H: Hasher> {
+	nodes: Vec<Node<H>>,
+}
+
+impl<H: Hasher> NodeVisitor<H> for NodeRecorder<H> {
+	fn visit(&mut self, path: &[u8], encoded: &[u8], hash: Option<&H::Out>) {
+		self.nodes.push(Node { path: path.to_vec(), encoded: encoded.to_vec(), hash: hash.cloned() });
+	}
+}
+
+/// Builds the trie of `input` with both streams and checks that they agree on every node.
+///
+/// # Panics
+///
+/// Panics at the first node (in the order they are built, children first) on which the streams
+/// disagree, reporting whether only one of them inlines the node or how their encodings differ.
+///
+/// ```
+/// use triehash::{test_support::assert_streams_agree, RlpTrieStream};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let input = vec![(b"doe".to_vec(), b"reindeer".to_vec()), (b"dog".to_vec(), b"puppy".to_vec())];
+/// assert_streams_agree::<KeccakHasher, RlpTrieStream, RlpTrieStream>(&input);
+/// ```
+pub fn assert_streams_agree<H, S1, S2>(input: &[(Vec<u8>, Vec<u8>)])
+where
+	H: Hasher,
+	S1: TrieStream,
+	S2: TrieStream,
+{
+	let mut first = NodeRecorder::<H> { nodes: Vec::new() };
+	let mut second = NodeRecorder::<H> { nodes: Vec::new() };
+	stream_root_with_visitor::<H, S1, _, _, _, _>(input.iter().cloned(), &mut first);
+	stream_root_with_visitor::<H, S2, _, _, _, _>(input.iter().cloned(), &mut second);
+
+	for (i, (a, b)) in first.nodes.iter().zip(&second.nodes).enumerate() {
+		assert!(
+			a.path == b.path,
+			"node {} is at path {:?} in the first trie and at {:?} in the second",
+			i,
+			a.path,
+			b.path
+		);
+		assert!(
+			a.hash.is_some() == b.hash.is_some(),
+			"node {} at path {:?} is inlined by one stream only: {:?} and {:?}",
+			i,
+			a.path,
+			a.encoded,
+			b.encoded,
+		);
+		assert!(
+			a.encoded == b.encoded,
+			"node {} at path {:?} is encoded differently: {:?} and {:?}",
+			i,
+			a.path,
+			a.encoded,
+			b.encoded,
+		);
+	}
+	assert!(
+		first.nodes.len() == second.nodes.len(),
+		"the tries have {} and {} nodes",
+		first.nodes.len(),
+		second.nodes.len(),
+	);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{assert_matches_reference, assert_streams_agree, reference_trie_root, test_inputs};
+	use crate::{trie_root, trie_root_from_sorted_iter, HexNibbles, KeyEncode, RlpTrieStream, TrieRootBuilder};
+	use hex_literal::hex;
+	use keccak_hasher::KeccakHasher;
+	use rlp::RlpStream;
+	use std::collections::BTreeMap;
+
+	#[test]
+	fn reference_matches_known_root() {
+		let input = vec![
+			(b"doe".to_vec(), b"reindeer".to_vec()),
+			(b"dog".to_vec(), b"puppy".to_vec()),
+			(b"dogglesworth".to_vec(), b"cat".to_vec()),
+		];
+		assert_eq!(
+			reference_trie_root::<KeccakHasher>(&input),
+			hex!("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"),
+		);
+	}
+
+	#[test]
+	fn roots_match_reference() {
+		assert_matches_reference::<KeccakHasher, _>(|input| trie_root::<KeccakHasher, _, _, _>(input.to_vec()));
+		assert_matches_reference::<KeccakHasher, _>(|input| {
+			let sorted = input.iter().cloned().collect::<BTreeMap<_, _>>();
+			trie_root_from_sorted_iter::<KeccakHasher, _, _, _>(sorted)
+		});
+		assert_matches_reference::<KeccakHasher, _>(|input| {
+			let mut builder = TrieRootBuilder::<KeccakHasher>::new();
+			for (key, value) in input {
+				builder.insert(key, value.clone());
+			}
+			builder.root()
+		});
+	}
+
+	/// Hex keys with partial paths stored as plain nibbles.
+	struct PlainNibbles;
+
+	impl KeyEncode for PlainNibbles {
+		const RADIX: usize = 16;
+
+		fn encode_key(key: &[u8], path: &mut Vec<u8>) {
+			HexNibbles::encode_key(key, path);
+		}
+
+		fn append_partial(partial: &[u8], _leaf: bool, stream: &mut RlpStream) {
+			stream.append(&partial);
+		}
+	}
+
+	#[test]
+	fn same_streams_agree() {
+		for input in test_inputs::<KeccakHasher>() {
+			assert_streams_agree::<KeccakHasher, RlpTrieStream, RlpTrieStream<HexNibbles>>(&input);
+		}
+	}
+
+	#[test]
+	#[should_panic(expected = "node 0 at path [6, 4, 6, 15, 6, 5, 6] is encoded differently")]
+	fn reports_first_differing_node() {
+		let input = vec![(b"doe".to_vec(), b"reindeer".to_vec()), (b"doe".repeat(20), b"cat".to_vec())];
+		assert_streams_agree::<KeccakHasher, RlpTrieStream, RlpTrieStream<PlainNibbles>>(&input);
+	}
+}
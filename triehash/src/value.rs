@@ -0,0 +1,78 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Values which are only materialized when their node is encoded.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+/// Value of a trie item.
+///
+/// Implemented for everything that can be referenced as bytes, and for `LazyValue`,
+/// which produces its bytes on demand.
+pub trait AsValue {
+	/// Calls `f` with the bytes of the value.
+	fn with_value<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R;
+}
+
+impl<T: AsRef<[u8]>> AsValue for T {
+	fn with_value<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
+		f(self.as_ref())
+	}
+}
+
+/// Value produced by a closure when the node holding it is encoded, and dropped right after.
+///
+/// Every value is encoded exactly once while generating a root, so wrapping expensive
+/// encodings (e.g. receipts) avoids keeping all of them in memory at the same time.
+///
+/// ```
+/// use triehash::{ordered_trie_root, LazyValue};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let receipts = vec![1u64, 2, 3];
+/// let lazy = receipts.iter().map(|r| LazyValue(move || rlp::encode(r).to_vec()));
+/// let eager = receipts.iter().map(|r| rlp::encode(r).to_vec());
+/// assert_eq!(
+/// 	ordered_trie_root::<KeccakHasher, _>(lazy),
+/// 	ordered_trie_root::<KeccakHasher, _>(eager),
+/// );
+/// ```
+pub struct LazyValue<F>(pub F);
+
+impl<F: Fn() -> Vec<u8>> AsValue for LazyValue<F> {
+	fn with_value<R, G: FnOnce(&[u8]) -> R>(&self, f: G) -> R {
+		f(&(self.0)())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::LazyValue;
+	use crate::trie_root;
+	use keccak_hasher::KeccakHasher;
+	use std::cell::Cell;
+
+	#[test]
+	fn lazy_values_are_produced_once() {
+		let calls = Cell::new(0);
+		let input = (0..300u32).map(|i| (i.to_be_bytes(), i.to_le_bytes().repeat(i as usize % 10))).collect::<Vec<_>>();
+		let lazy = input.iter().map(|(key, value)| {
+			let calls = &calls;
+			(
+				*key,
+				LazyValue(move || {
+					calls.set(calls.get() + 1);
+					value.clone()
+				}),
+			)
+		});
+		assert_eq!(trie_root::<KeccakHasher, _, _, _>(lazy), trie_root::<KeccakHasher, _, _, _>(input));
+		assert_eq!(calls.get(), 300);
+	}
+}
@@ -0,0 +1,115 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Writing the nodes of a trie as soon as they are encoded.
+
+use std::io::{self, Write};
+
+use crate::reader::write_record;
+use crate::stream::TrieStream;
+use crate::value::AsValue;
+use crate::{stream_root_with_visitor, NodeVisitor};
+
+use hash_db::Hasher;
+
+/// Writes every node referenced by hash, stopping at the first error.
+struct NodeWriter<W> {
+	writer: W,
+	written: u64,
+	error: Option<io::Error>,
+}
+
+impl<H: Hasher, W: Write> NodeVisitor<H> for NodeWriter<W> {
+	fn visit(&mut self, _path: &[u8], encoded: &[u8], hash: Option<&H::Out>) {
+		if let (Some(hash), None) = (hash, &self.error) {
+			match write_record(&mut self.writer, hash.as_ref(), encoded) {
+				Ok(()) => self.written += (8 + hash.as_ref().len() + encoded.len()) as u64,
+				Err(e) => self.error = Some(e),
+			}
+		}
+	}
+
+	fn is_cancelled(&mut self) -> bool {
+		self.error.is_some()
+	}
+}
+
+/// Generates a trie root hash for a vector of key-value tuples, writing every node referenced by
+/// hash (encoded by `S`) to `writer` as soon as it is encoded.
+///
+/// Nodes are written as `write_record` records of their hash and their encoding, children before
+/// their parents and the root node last, so the trie doesn't have to be kept in memory, e.g. while
+/// creating a snapshot. Returns the root together with the number of bytes written.
+///
+/// ```
+/// use triehash::{trie_nodes, trie_root, write_record, write_trie_nodes, RlpTrieStream};
+/// use keccak_hasher::KeccakHasher;
+///
+/// let v = vec![("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")];
+///
+/// let mut out = Vec::new();
+/// let (root, written) = write_trie_nodes::<KeccakHasher, RlpTrieStream, _, _, _, _>(v.clone(), &mut out).unwrap();
+/// assert_eq!(root, trie_root::<KeccakHasher, _, _, _>(v.clone()));
+/// assert_eq!(written, out.len() as u64);
+///
+/// let mut expected = Vec::new();
+/// for (hash, encoded) in trie_nodes::<KeccakHasher, RlpTrieStream, _, _, _>(v) {
+/// 	write_record(&mut expected, &hash, &encoded).unwrap();
+/// }
+/// assert_eq!(out, expected);
+/// ```
+pub fn write_trie_nodes<H, S, I, A, B, W>(input: I, writer: W) -> io::Result<(H::Out, u64)>
+where
+	I: IntoIterator<Item = (A, B)>,
+	A: AsRef<[u8]> + Ord,
+	B: AsValue,
+	H: Hasher,
+	S: TrieStream,
+	W: Write,
+{
+	let mut node_writer = NodeWriter { writer, written: 0, error: None };
+	let root = stream_root_with_visitor::<H, S, _, _, _, _>(input, &mut node_writer);
+	match node_writer.error {
+		Some(e) => Err(e),
+		None => Ok((root, node_writer.written)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::write_trie_nodes;
+	use crate::RlpTrieStream;
+	use keccak_hasher::KeccakHasher;
+	use std::io::{self, Write};
+
+	/// Writer failing after `limit` bytes.
+	struct Limited {
+		limit: usize,
+	}
+
+	impl Write for Limited {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			if buf.len() > self.limit {
+				return Err(io::ErrorKind::WriteZero.into());
+			}
+			self.limit -= buf.len();
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn stops_at_first_error() {
+		let input = (0..1000u32).map(|i| (i.to_be_bytes(), i.to_le_bytes())).collect::<Vec<_>>();
+		let result = write_trie_nodes::<KeccakHasher, RlpTrieStream, _, _, _, _>(input, Limited { limit: 1000 });
+		assert_eq!(result.unwrap_err().kind(), io::ErrorKind::WriteZero);
+	}
+}
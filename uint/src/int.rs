@@ -0,0 +1,480 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Big signed integer types.
+//!
+//! Signed integers are stored in two's complement in an unsigned integer type of the same
+//! width, constructed with `construct_uint!`.
+
+/// Constructs a signed integer type stored in two's complement in the given unsigned type.
+///
+/// Arithmetic follows the primitive signed integers: division truncates towards zero, the
+/// remainder has the sign of the dividend, `>>` is an arithmetic shift and the operators panic
+/// on overflow.
+///
+/// ```
+/// use uint::{construct_int, construct_uint};
+///
+/// construct_uint! {
+/// 	pub struct U256(4);
+/// }
+///
+/// construct_int! {
+/// 	pub struct I256(U256);
+/// }
+///
+/// let a = I256::from(-7);
+/// assert_eq!(a / I256::from(2), I256::from(-3));
+/// assert_eq!(a % I256::from(2), I256::from(-1));
+/// assert!(a < I256::zero());
+/// assert_eq!(a.into_bits(), U256::max_value() - U256::from(6));
+/// ```
+#[macro_export]
+macro_rules! construct_int {
+	( $(#[$attr:meta])* $visibility:vis struct $name:ident ( $uint:ty ); ) => {
+		/// Signed large integer type, stored in two's complement
+		$(#[$attr])*
+		#[derive(Copy, Clone, Eq, PartialEq, Hash, Default)]
+		$visibility struct $name($uint);
+
+		impl $name {
+			/// Creates the integer with the two's complement representation `bits`.
+			#[inline]
+			pub const fn from_bits(bits: $uint) -> Self {
+				$name(bits)
+			}
+
+			/// Two's complement representation of the integer.
+			#[inline]
+			pub const fn into_bits(self) -> $uint {
+				self.0
+			}
+
+			/// Zero (additive identity) of this type.
+			#[inline]
+			pub fn zero() -> Self {
+				$name(<$uint>::zero())
+			}
+
+			/// One (multiplicative identity) of this type.
+			#[inline]
+			pub fn one() -> Self {
+				$name(<$uint>::one())
+			}
+
+			/// Minus one.
+			#[inline]
+			pub fn minus_one() -> Self {
+				$name(<$uint>::max_value())
+			}
+
+			/// The smallest value, `-2^(BITS - 1)`.
+			pub fn min_value() -> Self {
+				$name(!(<$uint>::max_value() >> 1usize))
+			}
+
+			/// The largest value, `2^(BITS - 1) - 1`.
+			pub fn max_value() -> Self {
+				$name(<$uint>::max_value() >> 1usize)
+			}
+
+			/// Convert from a decimal string, with an optional leading `-`.
+			pub fn from_dec_str(value: &str) -> $crate::core_::result::Result<Self, $crate::FromDecStrErr> {
+				let (negative, digits) = match value.strip_prefix('-') {
+					Some(digits) => (true, digits),
+					None => (false, value),
+				};
+				let abs = <$uint>::from_dec_str(digits)?;
+				let min = Self::min_value().0;
+				if abs > min || (abs == min && !negative) {
+					return Err($crate::FromDecStrErr::InvalidLength);
+				}
+				Ok(if negative { $name(Self::negate_bits(abs)) } else { $name(abs) })
+			}
+
+			/// Whether this is zero.
+			#[inline]
+			pub fn is_zero(&self) -> bool {
+				self.0.is_zero()
+			}
+
+			/// Whether this is less than zero.
+			#[inline]
+			pub fn is_negative(&self) -> bool {
+				self.0.leading_zeros() == 0
+			}
+
+			/// Whether this is greater than zero.
+			#[inline]
+			pub fn is_positive(&self) -> bool {
+				!self.is_negative() && !self.is_zero()
+			}
+
+			/// Returns -1, 0 or 1 depending on the sign.
+			pub fn signum(self) -> Self {
+				if self.is_negative() {
+					Self::minus_one()
+				} else if self.is_zero() {
+					Self::zero()
+				} else {
+					Self::one()
+				}
+			}
+
+			/// Absolute value as an unsigned integer, which can't overflow.
+			pub fn unsigned_abs(self) -> $uint {
+				if self.is_negative() {
+					Self::negate_bits(self.0)
+				} else {
+					self.0
+				}
+			}
+
+			/// Absolute value.
+			///
+			/// # Panics
+			///
+			/// Panics if `self` is the minimum value.
+			pub fn abs(self) -> Self {
+				let (abs, overflow) = self.overflowing_abs();
+				$crate::panic_on_overflow!(overflow);
+				abs
+			}
+
+			/// Absolute value, with a flag set if it overflows, i.e. for the minimum value.
+			pub fn overflowing_abs(self) -> (Self, bool) {
+				if self.is_negative() {
+					self.overflowing_neg()
+				} else {
+					(self, false)
+				}
+			}
+
+			/// Negation with overflow, i.e. for the minimum value.
+			pub fn overflowing_neg(self) -> (Self, bool) {
+				let neg = $name(Self::negate_bits(self.0));
+				(neg, !self.is_zero() && neg.is_negative() == self.is_negative())
+			}
+
+			/// Checked negation. Returns `None` if overflow occurred.
+			pub fn checked_neg(self) -> Option<Self> {
+				match self.overflowing_neg() {
+					(_, true) => None,
+					(val, false) => Some(val),
+				}
+			}
+
+			/// Add with overflow.
+			pub fn overflowing_add(self, other: Self) -> (Self, bool) {
+				let sum = $name(self.0.overflowing_add(other.0).0);
+				let overflow = self.is_negative() == other.is_negative() && sum.is_negative() != self.is_negative();
+				(sum, overflow)
+			}
+
+			/// Checked addition. Returns `None` if overflow occurred.
+			pub fn checked_add(self, other: Self) -> Option<Self> {
+				match self.overflowing_add(other) {
+					(_, true) => None,
+					(val, false) => Some(val),
+				}
+			}
+
+			/// Subtraction with overflow.
+			pub fn overflowing_sub(self, other: Self) -> (Self, bool) {
+				let difference = $name(self.0.overflowing_sub(other.0).0);
+				let overflow = self.is_negative() != other.is_negative() && difference.is_negative() != self.is_negative();
+				(difference, overflow)
+			}
+
+			/// Checked subtraction. Returns `None` if overflow occurred.
+			pub fn checked_sub(self, other: Self) -> Option<Self> {
+				match self.overflowing_sub(other) {
+					(_, true) => None,
+					(val, false) => Some(val),
+				}
+			}
+
+			/// Multiplication with overflow.
+			pub fn overflowing_mul(self, other: Self) -> (Self, bool) {
+				// the low bits of the product are the same for signed and unsigned operands
+				let product = $name(self.0.overflowing_mul(other.0).0);
+				let (abs, overflow) = self.unsigned_abs().overflowing_mul(other.unsigned_abs());
+				(product, overflow || Self::abs_overflows(abs, self.is_negative() != other.is_negative()))
+			}
+
+			/// Checked multiplication. Returns `None` if overflow occurred.
+			pub fn checked_mul(self, other: Self) -> Option<Self> {
+				match self.overflowing_mul(other) {
+					(_, true) => None,
+					(val, false) => Some(val),
+				}
+			}
+
+			/// Division truncating towards zero, with overflow, i.e. for the minimum value
+			/// divided by -1.
+			///
+			/// # Panics
+			///
+			/// Panics if `other` is zero.
+			pub fn overflowing_div(self, other: Self) -> (Self, bool) {
+				let negative = self.is_negative() != other.is_negative();
+				let abs = self.unsigned_abs() / other.unsigned_abs();
+				let quotient = if negative { $name(Self::negate_bits(abs)) } else { $name(abs) };
+				(quotient, Self::abs_overflows(abs, negative))
+			}
+
+			/// Checked division. Returns `None` if `other` is zero or overflow occurred.
+			pub fn checked_div(self, other: Self) -> Option<Self> {
+				if other.is_zero() {
+					return None;
+				}
+				match self.overflowing_div(other) {
+					(_, true) => None,
+					(val, false) => Some(val),
+				}
+			}
+
+			/// Remainder of the division truncating towards zero, which has the sign of `self`.
+			/// The overflow flag is set for the minimum value divided by -1, whose remainder is
+			/// zero.
+			///
+			/// # Panics
+			///
+			/// Panics if `other` is zero.
+			pub fn overflowing_rem(self, other: Self) -> (Self, bool) {
+				let abs = self.unsigned_abs() % other.unsigned_abs();
+				let remainder = if self.is_negative() { $name(Self::negate_bits(abs)) } else { $name(abs) };
+				(remainder, self == Self::min_value() && other == Self::minus_one())
+			}
+
+			/// Checked remainder. Returns `None` if `other` is zero or overflow occurred.
+			pub fn checked_rem(self, other: Self) -> Option<Self> {
+				if other.is_zero() {
+					return None;
+				}
+				match self.overflowing_rem(other) {
+					(_, true) => None,
+					(val, false) => Some(val),
+				}
+			}
+
+			// Two's complement negation of `bits`.
+			#[inline]
+			fn negate_bits(bits: $uint) -> $uint {
+				(!bits).overflowing_add(<$uint>::one()).0
+			}
+
+			// Whether the absolute value `abs` of a result of the given sign doesn't fit.
+			#[inline]
+			fn abs_overflows(abs: $uint, negative: bool) -> bool {
+				let min = Self::min_value().0;
+				abs > min || (abs == min && !negative)
+			}
+		}
+
+		impl $crate::core_::convert::From<i64> for $name {
+			fn from(value: i64) -> $name {
+				if value < 0 {
+					$name(!<$uint>::from(!value as u64))
+				} else {
+					$name(<$uint>::from(value as u64))
+				}
+			}
+		}
+
+		$crate::impl_map_from!($name, i8, i64);
+		$crate::impl_map_from!($name, i16, i64);
+		$crate::impl_map_from!($name, i32, i64);
+		$crate::impl_map_from!($name, isize, i64);
+
+		impl $crate::core_::convert::TryFrom<$uint> for $name {
+			type Error = &'static str;
+
+			fn try_from(u: $uint) -> $crate::core_::result::Result<$name, &'static str> {
+				if u.leading_zeros() == 0 {
+					Err(concat!("integer overflow when casting to ", stringify!($name)))
+				} else {
+					Ok($name(u))
+				}
+			}
+		}
+
+		impl $crate::core_::convert::TryFrom<$name> for $uint {
+			type Error = &'static str;
+
+			fn try_from(i: $name) -> $crate::core_::result::Result<$uint, &'static str> {
+				if i.is_negative() {
+					Err("negative integer can't be cast to an unsigned type")
+				} else {
+					Ok(i.0)
+				}
+			}
+		}
+
+		impl $crate::core_::convert::TryFrom<$name> for i64 {
+			type Error = &'static str;
+
+			fn try_from(i: $name) -> $crate::core_::result::Result<i64, &'static str> {
+				let err_str = "integer overflow when casting to i64";
+				let abs = i.unsigned_abs();
+				if abs.bits() > 64 {
+					return Err(err_str);
+				}
+				let abs = abs.low_u64();
+				match (i.is_negative(), abs) {
+					(true, abs) if abs <= 1 << 63 => Ok((abs as i64).wrapping_neg()),
+					(false, abs) if abs < 1 << 63 => Ok(abs as i64),
+					_ => Err(err_str),
+				}
+			}
+		}
+
+		impl $crate::core_::ops::Neg for $name {
+			type Output = $name;
+
+			fn neg(self) -> $name {
+				let (neg, overflow) = self.overflowing_neg();
+				$crate::panic_on_overflow!(overflow);
+				neg
+			}
+		}
+
+		impl $crate::core_::ops::Add for $name {
+			type Output = $name;
+
+			fn add(self, other: $name) -> $name {
+				let (sum, overflow) = self.overflowing_add(other);
+				$crate::panic_on_overflow!(overflow);
+				sum
+			}
+		}
+
+		impl $crate::core_::ops::AddAssign for $name {
+			fn add_assign(&mut self, other: $name) {
+				*self = *self + other;
+			}
+		}
+
+		impl $crate::core_::ops::Sub for $name {
+			type Output = $name;
+
+			fn sub(self, other: $name) -> $name {
+				let (difference, overflow) = self.overflowing_sub(other);
+				$crate::panic_on_overflow!(overflow);
+				difference
+			}
+		}
+
+		impl $crate::core_::ops::SubAssign for $name {
+			fn sub_assign(&mut self, other: $name) {
+				*self = *self - other;
+			}
+		}
+
+		impl $crate::core_::ops::Mul for $name {
+			type Output = $name;
+
+			fn mul(self, other: $name) -> $name {
+				let (product, overflow) = self.overflowing_mul(other);
+				$crate::panic_on_overflow!(overflow);
+				product
+			}
+		}
+
+		impl $crate::core_::ops::MulAssign for $name {
+			fn mul_assign(&mut self, other: $name) {
+				*self = *self * other;
+			}
+		}
+
+		impl $crate::core_::ops::Div for $name {
+			type Output = $name;
+
+			fn div(self, other: $name) -> $name {
+				let (quotient, overflow) = self.overflowing_div(other);
+				$crate::panic_on_overflow!(overflow);
+				quotient
+			}
+		}
+
+		impl $crate::core_::ops::DivAssign for $name {
+			fn div_assign(&mut self, other: $name) {
+				*self = *self / other;
+			}
+		}
+
+		impl $crate::core_::ops::Rem for $name {
+			type Output = $name;
+
+			fn rem(self, other: $name) -> $name {
+				let (remainder, overflow) = self.overflowing_rem(other);
+				$crate::panic_on_overflow!(overflow);
+				remainder
+			}
+		}
+
+		impl $crate::core_::ops::RemAssign for $name {
+			fn rem_assign(&mut self, other: $name) {
+				*self = *self % other;
+			}
+		}
+
+		impl $crate::core_::ops::Shl<usize> for $name {
+			type Output = $name;
+
+			fn shl(self, shift: usize) -> $name {
+				$name(self.0 << shift)
+			}
+		}
+
+		impl $crate::core_::ops::Shr<usize> for $name {
+			type Output = $name;
+
+			/// Arithmetic shift, filling the high bits with the sign bit.
+			fn shr(self, shift: usize) -> $name {
+				if self.is_negative() {
+					$name(!(!self.0 >> shift))
+				} else {
+					$name(self.0 >> shift)
+				}
+			}
+		}
+
+		impl $crate::core_::cmp::Ord for $name {
+			fn cmp(&self, other: &$name) -> $crate::core_::cmp::Ordering {
+				match (self.is_negative(), other.is_negative()) {
+					(true, false) => $crate::core_::cmp::Ordering::Less,
+					(false, true) => $crate::core_::cmp::Ordering::Greater,
+					// two's complement preserves the order of numbers of the same sign
+					_ => self.0.cmp(&other.0),
+				}
+			}
+		}
+
+		impl $crate::core_::cmp::PartialOrd for $name {
+			fn partial_cmp(&self, other: &$name) -> Option<$crate::core_::cmp::Ordering> {
+				Some(self.cmp(other))
+			}
+		}
+
+		impl $crate::core_::fmt::Debug for $name {
+			fn fmt(&self, f: &mut $crate::core_::fmt::Formatter) -> $crate::core_::fmt::Result {
+				$crate::core_::fmt::Display::fmt(self, f)
+			}
+		}
+
+		impl $crate::core_::fmt::Display for $name {
+			fn fmt(&self, f: &mut $crate::core_::fmt::Formatter) -> $crate::core_::fmt::Result {
+				if self.is_negative() {
+					f.write_str("-")?;
+				}
+				$crate::core_::fmt::Display::fmt(&self.unsigned_abs(), f)
+			}
+		}
+	};
+}
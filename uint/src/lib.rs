@@ -42,3 +42,7 @@ pub use crunchy::unroll;
 #[rustfmt::skip]
 mod uint;
 pub use crate::uint::*;
+
+#[macro_use]
+#[rustfmt::skip]
+mod int;
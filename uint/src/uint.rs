@@ -763,66 +763,12 @@ macro_rules! construct_uint {
 
 				// quotient
 				let mut q = Self::zero();
-				let v_n_1 = v.0[n - 1];
-				let v_n_2 = v.0[n - 2];
 
 				// D2. D7.
 				// iterate from m downto 0
 				for j in (0..=m).rev() {
-					let u_jn = u[j + n];
-
-					// D3.
-					// q_hat is our guess for the j-th quotient digit
-					// q_hat = min(b - 1, (u_{j+n} * b + u_{j+n-1}) / v_{n-1})
-					// b = 1 << WORD_BITS
-					// Theorem B: q_hat >= q_j >= q_hat - 2
-					let mut q_hat = if u_jn < v_n_1 {
-						let (mut q_hat, mut r_hat) = Self::div_mod_word(u_jn, u[j + n - 1], v_n_1);
-						// this loop takes at most 2 iterations
-						loop {
-							// check if q_hat * v_{n-2} > b * r_hat + u_{j+n-2}
-							let (hi, lo) = Self::split_u128(u128::from(q_hat) * u128::from(v_n_2));
-							if (hi, lo) <= (r_hat, u[j + n - 2]) {
-								break;
-							}
-							// then iterate till it doesn't hold
-							q_hat -= 1;
-							let (new_r_hat, overflow) = r_hat.overflowing_add(v_n_1);
-							r_hat = new_r_hat;
-							// if r_hat overflowed, we're done
-							if overflow {
-								break;
-							}
-						}
-						q_hat
-					} else {
-						// here q_hat >= q_j >= q_hat - 1
-						u64::max_value()
-					};
-
-					// ex. 20:
-					// since q_hat * v_{n-2} <= b * r_hat + u_{j+n-2},
-					// either q_hat == q_j, or q_hat == q_j + 1
-
-					// D4.
-					// let's assume optimistically q_hat == q_j
-					// subtract (q_hat * v) from u[j..]
-					let q_hat_v = v.full_mul_u64(q_hat);
-					// u[j..] -= q_hat_v;
-					let c = Self::sub_slice(&mut u[j..], &q_hat_v[..n + 1]);
-
-					// D6.
-					// actually, q_hat == q_j + 1 and u[j..] has overflowed
-					// highly unlikely ~ (1 / 2^63)
-					if c {
-						q_hat -= 1;
-						// add v to u[j..]
-						let c = Self::add_slice(&mut u[j..], &v.0[..n]);
-						u[j + n] = u[j + n].wrapping_add(u64::from(c));
-					}
-
 					// D5.
-					q.0[j] = q_hat;
+					q.0[j] = Self::div_mod_knuth_step(&mut u[j..], v, n);
 				}
 
 				// D8.
@@ -831,6 +777,67 @@ macro_rules! construct_uint {
 				(q, remainder)
 			}
 
+			// Steps D3. to D6. of Algorithm D for the j-th quotient digit, with `u` starting at the
+			// j-th word of the dividend: divides `u[..=n]` by the normalized `n` words of `v`, leaving
+			// the remainder in `u`. Returns the quotient digit.
+			fn div_mod_knuth_step(u: &mut [u64], v: Self, n: usize) -> u64 {
+				let v_n_1 = v.0[n - 1];
+				let v_n_2 = v.0[n - 2];
+				let u_jn = u[n];
+
+				// D3.
+				// q_hat is our guess for the j-th quotient digit
+				// q_hat = min(b - 1, (u_{j+n} * b + u_{j+n-1}) / v_{n-1})
+				// b = 1 << WORD_BITS
+				// Theorem B: q_hat >= q_j >= q_hat - 2
+				let mut q_hat = if u_jn < v_n_1 {
+					let (mut q_hat, mut r_hat) = Self::div_mod_word(u_jn, u[n - 1], v_n_1);
+					// this loop takes at most 2 iterations
+					loop {
+						// check if q_hat * v_{n-2} > b * r_hat + u_{j+n-2}
+						let (hi, lo) = Self::split_u128(u128::from(q_hat) * u128::from(v_n_2));
+						if (hi, lo) <= (r_hat, u[n - 2]) {
+							break;
+						}
+						// then iterate till it doesn't hold
+						q_hat -= 1;
+						let (new_r_hat, overflow) = r_hat.overflowing_add(v_n_1);
+						r_hat = new_r_hat;
+						// if r_hat overflowed, we're done
+						if overflow {
+							break;
+						}
+					}
+					q_hat
+				} else {
+					// here q_hat >= q_j >= q_hat - 1
+					u64::max_value()
+				};
+
+				// ex. 20:
+				// since q_hat * v_{n-2} <= b * r_hat + u_{j+n-2},
+				// either q_hat == q_j, or q_hat == q_j + 1
+
+				// D4.
+				// let's assume optimistically q_hat == q_j
+				// subtract (q_hat * v) from u[j..]
+				let q_hat_v = v.full_mul_u64(q_hat);
+				// u[j..] -= q_hat_v;
+				let c = Self::sub_slice(u, &q_hat_v[..n + 1]);
+
+				// D6.
+				// actually, q_hat == q_j + 1 and u[j..] has overflowed
+				// highly unlikely ~ (1 / 2^63)
+				if c {
+					q_hat -= 1;
+					// add v to u[j..]
+					let c = Self::add_slice(u, &v.0[..n]);
+					u[n] = u[n].wrapping_add(u64::from(c));
+				}
+
+				q_hat
+			}
+
 			// Returns the least number of words needed to represent the nonzero number
 			fn words(bits: usize) -> usize {
 				debug_assert!(bits > 0);
@@ -868,6 +875,69 @@ macro_rules! construct_uint {
 				self.div_mod_knuth(other, n, m)
 			}
 
+			/// Returns `self * other % modulus`, computed from the double-width product, so the
+			/// result is exact even if `self * other` overflows.
+			///
+			/// # Panics
+			///
+			/// Panics if `modulus` is zero.
+			pub fn mul_mod(self, other: Self, modulus: Self) -> Self {
+				let product: [u64; $n_words * 2] = $crate::uint_full_mul_reg!($name, $n_words, self, other);
+				Self::rem_wide(product, modulus)
+			}
+
+			/// Returns `(self + other) % modulus`, computed from the double-width sum, so the
+			/// result is exact even if `self + other` overflows.
+			///
+			/// # Panics
+			///
+			/// Panics if `modulus` is zero.
+			pub fn add_mod(self, other: Self, modulus: Self) -> Self {
+				let (sum, carry) = self.overflowing_add(other);
+				let mut wide = [0u64; $n_words * 2];
+				wide[..$n_words].copy_from_slice(&sum.0);
+				wide[$n_words] = u64::from(carry);
+				Self::rem_wide(wide, modulus)
+			}
+
+			// Returns the remainder of the double-width number `u` divided by `v`.
+			fn rem_wide(u: [u64; $n_words * 2], mut v: Self) -> Self {
+				let bits = v.bits();
+				assert!(bits != 0, "division by zero");
+
+				if bits <= Self::WORD_BITS {
+					let v = v.low_u64();
+					let rem = u.iter().rev().fold(0u64, |rem, d| Self::div_mod_word(rem, *d, v).1);
+					return rem.into();
+				}
+
+				// Same as `div_mod_knuth`, for the double-width dividend and without the quotient.
+				let n = Self::words(bits);
+				let shift = v.0[n - 1].leading_zeros();
+				v <<= shift;
+				let mut w = [0u64; $n_words * 2 + 1];
+				w[..$n_words * 2].copy_from_slice(&u);
+				if shift > 0 {
+					for i in (1..=$n_words * 2).rev() {
+						w[i] = (w[i] << shift) | (w[i - 1] >> (Self::WORD_BITS as u32 - shift));
+					}
+					w[0] <<= shift;
+				}
+
+				for j in (0..=$n_words * 2 - n).rev() {
+					Self::div_mod_knuth_step(&mut w[j..], v, n);
+				}
+
+				let mut rem = Self::zero();
+				for i in 0..n {
+					rem.0[i] = w[i] >> shift;
+					if shift > 0 {
+						rem.0[i] |= w[i + 1] << (Self::WORD_BITS as u32 - shift);
+					}
+				}
+				rem
+			}
+
 			/// Fast exponentiation by squaring
 			/// https://en.wikipedia.org/wiki/Exponentiation_by_squaring
 			///
@@ -933,6 +1003,115 @@ macro_rules! construct_uint {
 				}
 			}
 
+			/// Exponentiation which saturates at the maximum value (Self::max_value()).
+			pub fn saturating_pow(self, expon: $name) -> $name {
+				match self.overflowing_pow(expon) {
+					(_, true) => $name::max_value(),
+					(val, false) => val,
+				}
+			}
+
+			/// Integer square root, the largest `r` such that `r * r <= self`, computed exactly by
+			/// Newton's method.
+			pub fn integer_sqrt(self) -> $name {
+				self.nth_root(2)
+			}
+
+			/// Integer `n`th root, the largest `r` such that `r.pow(n) <= self`, computed exactly by
+			/// Newton's method.
+			///
+			/// # Panics
+			///
+			/// Panics if `n` is zero.
+			pub fn nth_root(self, n: u32) -> $name {
+				assert!(n != 0, "zeroth root is undefined");
+				let bits = self.bits();
+				if n == 1 || bits <= 1 {
+					return self;
+				}
+				if n as usize >= bits {
+					return $name::one();
+				}
+				// Start above the root, from where the iterates decrease down to it.
+				let mut x = $name::one() << ((bits + n as usize - 1) / n as usize);
+				let n_less_one = $name::from(n - 1);
+				loop {
+					let quotient = x.checked_pow(n_less_one).map_or($name::zero(), |power| self / power);
+					let next = (x * n_less_one + quotient) / $name::from(n);
+					if next >= x {
+						return x;
+					}
+					x = next;
+				}
+			}
+
+			/// Returns `self.pow(expon) % modulus`, computed by square-and-multiply with `mul_mod`,
+			/// so intermediate results never overflow.
+			///
+			/// # Panics
+			///
+			/// Panics if `modulus` is zero.
+			pub fn pow_mod(self, expon: Self, modulus: Self) -> Self {
+				let mut result = Self::one() % modulus;
+				for i in (0..expon.bits()).rev() {
+					result = result.mul_mod(result, modulus);
+					if (expon.0[i / Self::WORD_BITS] >> (i % Self::WORD_BITS)) & 1 == 1 {
+						result = result.mul_mod(self, modulus);
+					}
+				}
+				result
+			}
+
+			/// Extended Euclidean algorithm. Returns `(gcd, x, y)`, where `gcd` is the greatest
+			/// common divisor of `self` and `other`, and `self * x - other * y == gcd`, with
+			/// `x <= other / gcd` and `y <= self / gcd`.
+			///
+			/// If `self` is zero, there are no such coefficients and `(other, 0, 0)` is returned.
+			pub fn egcd(self, other: Self) -> (Self, Self, Self) {
+				// remainders, and the magnitudes of the coefficients, whose signs alternate
+				let (mut r0, mut r1) = (self, other);
+				let (mut s0, mut s1) = (Self::one(), Self::zero());
+				let (mut t0, mut t1) = (Self::zero(), Self::one());
+				let mut odd = false;
+				while !r1.is_zero() {
+					let (q, r) = r0.div_mod(r1);
+					r0 = r1;
+					r1 = r;
+					let s = s0 + q * s1;
+					s0 = s1;
+					s1 = s;
+					let t = t0 + q * t1;
+					t0 = t1;
+					t1 = t;
+					odd = !odd;
+				}
+
+				// after an even number of steps, self * s0 - other * t0 == gcd, otherwise
+				// other * t0 - self * s0 == gcd, which is shifted by the final coefficients
+				// s1 == other / gcd and t1 == self / gcd
+				if !odd {
+					(r0, s0, t0)
+				} else if self.is_zero() {
+					(r0, Self::zero(), Self::zero())
+				} else {
+					(r0, s1 - s0, t1 - t0)
+				}
+			}
+
+			/// Returns the inverse of `self` modulo `modulus`, i.e. `x < modulus` such that
+			/// `self * x % modulus == 1 % modulus`, or `None` if `self` and `modulus` aren't coprime.
+			///
+			/// # Panics
+			///
+			/// Panics if `modulus` is zero.
+			pub fn inv_mod(self, modulus: Self) -> Option<Self> {
+				assert!(!modulus.is_zero(), "division by zero");
+				match self.egcd(modulus) {
+					(gcd, x, _) if gcd == Self::one() => Some(x % modulus),
+					_ => None,
+				}
+			}
+
 			/// Add with overflow.
 			#[inline(always)]
 			pub fn overflowing_add(self, other: $name) -> ($name, bool) {
@@ -1046,6 +1225,69 @@ macro_rules! construct_uint {
 				}
 			}
 
+			/// Checked left shift. Returns `None` if `shift` is not less than the number of bits
+			/// of the type, like the primitive integers. Bits shifted out are discarded.
+			pub fn checked_shl(self, shift: u32) -> Option<$name> {
+				if shift as usize >= Self::WORD_BITS * $n_words {
+					None
+				} else {
+					Some(self << shift)
+				}
+			}
+
+			/// Checked right shift. Returns `None` if `shift` is not less than the number of bits
+			/// of the type, like the primitive integers.
+			pub fn checked_shr(self, shift: u32) -> Option<$name> {
+				if shift as usize >= Self::WORD_BITS * $n_words {
+					None
+				} else {
+					Some(self >> shift)
+				}
+			}
+
+			/// Left shift by `shift` modulo the number of bits of the type, with a flag set if
+			/// `shift` is not less than the number of bits, like the primitive integers.
+			pub fn overflowing_shl(self, shift: u32) -> ($name, bool) {
+				let bits = (Self::WORD_BITS * $n_words) as u32;
+				(self << (shift % bits), shift >= bits)
+			}
+
+			/// Right shift by `shift` modulo the number of bits of the type, with a flag set if
+			/// `shift` is not less than the number of bits, like the primitive integers.
+			pub fn overflowing_shr(self, shift: u32) -> ($name, bool) {
+				let bits = (Self::WORD_BITS * $n_words) as u32;
+				(self >> (shift % bits), shift >= bits)
+			}
+
+			/// Left shift by `shift` modulo the number of bits of the type.
+			pub fn wrapping_shl(self, shift: u32) -> $name {
+				self.overflowing_shl(shift).0
+			}
+
+			/// Right shift by `shift` modulo the number of bits of the type.
+			pub fn wrapping_shr(self, shift: u32) -> $name {
+				self.overflowing_shr(shift).0
+			}
+
+			/// Rotates the bits left by `n` modulo the number of bits of the type, wrapping the
+			/// high bits around to the low end.
+			pub fn rotate_left(self, n: u32) -> $name {
+				let bits = (Self::WORD_BITS * $n_words) as u32;
+				let n = n % bits;
+				if n == 0 {
+					self
+				} else {
+					(self << n) | (self >> (bits - n))
+				}
+			}
+
+			/// Rotates the bits right by `n` modulo the number of bits of the type, wrapping the
+			/// low bits around to the high end.
+			pub fn rotate_right(self, n: u32) -> $name {
+				let bits = (Self::WORD_BITS * $n_words) as u32;
+				self.rotate_left(bits - n % bits)
+			}
+
 			#[inline(always)]
 			fn div_mod_word(hi: u64, lo: u64, y: u64) -> (u64, u64) {
 				debug_assert!(hi < y);
@@ -1428,8 +1670,13 @@ macro_rules! construct_uint {
 		impl<T> $crate::core_::ops::Shl<T> for $name where T: Into<$name> {
 			type Output = $name;
 
+			/// Shifting by the bit width of the type or more gives zero.
 			fn shl(self, shift: T) -> $name {
-				let shift = shift.into().as_usize();
+				let shift: $name = shift.into();
+				if !shift.fits_word() || shift.low_u64() >= (Self::WORD_BITS * $n_words) as u64 {
+					return $name::zero();
+				}
+				let shift = shift.low_u64() as usize;
 				let $name(ref original) = self;
 				let mut ret = [0u64; $n_words];
 				let word_shift = shift / 64;
@@ -1465,8 +1712,13 @@ macro_rules! construct_uint {
 		impl<T> $crate::core_::ops::Shr<T> for $name where T: Into<$name> {
 			type Output = $name;
 
+			/// Shifting by the bit width of the type or more gives zero.
 			fn shr(self, shift: T) -> $name {
-				let shift = shift.into().as_usize();
+				let shift: $name = shift.into();
+				if !shift.fits_word() || shift.low_u64() >= (Self::WORD_BITS * $n_words) as u64 {
+					return $name::zero();
+				}
+				let shift = shift.low_u64() as usize;
 				let $name(ref original) = self;
 				let mut ret = [0u64; $n_words];
 				let word_shift = shift / 64;
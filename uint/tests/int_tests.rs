@@ -0,0 +1,165 @@
+// Copyright 2020 Parity Technologies
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use core::convert::TryFrom;
+use uint::{construct_int, construct_uint, FromDecStrErr};
+
+construct_uint! {
+	pub struct U64(1);
+}
+
+construct_uint! {
+	pub struct U256(4);
+}
+
+construct_int! {
+	pub struct I64(U64);
+}
+
+construct_int! {
+	pub struct I256(U256);
+}
+
+#[test]
+fn constants() {
+	assert_eq!(I256::minus_one().into_bits(), U256::max_value());
+	assert_eq!(I256::max_value().into_bits(), U256::max_value() >> 1);
+	assert_eq!(I256::min_value().into_bits(), U256::one() << 255);
+	assert_eq!(I256::from(-1), I256::minus_one());
+	assert_eq!(I256::from(i64::MIN).into_bits(), !U256::from(i64::MAX));
+	assert_eq!(I64::min_value().into_bits(), U64::from(i64::MIN as u64));
+}
+
+#[test]
+fn signs() {
+	assert!(I256::from(-3).is_negative());
+	assert!(I256::from(3).is_positive());
+	assert!(!I256::zero().is_negative() && !I256::zero().is_positive());
+	assert_eq!(I256::from(-3).signum(), I256::minus_one());
+	assert_eq!(I256::from(-3).abs(), I256::from(3));
+	assert_eq!(I256::min_value().unsigned_abs(), U256::one() << 255);
+	assert_eq!(I256::min_value().overflowing_abs(), (I256::min_value(), true));
+	assert_eq!(-I256::from(5), I256::from(-5));
+	assert_eq!(I256::min_value().checked_neg(), None);
+	assert_eq!(I256::zero().checked_neg(), Some(I256::zero()));
+}
+
+#[test]
+fn ordering() {
+	let mut values = vec![I256::max_value(), I256::from(-1), I256::zero(), I256::min_value(), I256::from(7)];
+	values.sort();
+	assert_eq!(values, vec![I256::min_value(), I256::from(-1), I256::zero(), I256::from(7), I256::max_value()]);
+}
+
+#[test]
+fn arithmetic() {
+	assert_eq!(I256::from(-7) + I256::from(3), I256::from(-4));
+	assert_eq!(I256::from(3) - I256::from(7), I256::from(-4));
+	assert_eq!(I256::from(-7) * I256::from(-3), I256::from(21));
+	assert_eq!(I256::from(-7) * I256::from(3), I256::from(-21));
+	assert_eq!(I256::from(-7) / I256::from(2), I256::from(-3));
+	assert_eq!(I256::from(7) / I256::from(-2), I256::from(-3));
+	assert_eq!(I256::from(-7) % I256::from(2), I256::from(-1));
+	assert_eq!(I256::from(7) % I256::from(-2), I256::from(1));
+
+	let mut x = I256::from(10);
+	x -= I256::from(15);
+	x *= I256::from(4);
+	x /= I256::from(3);
+	x %= I256::from(4);
+	assert_eq!(x, I256::from(-2));
+}
+
+#[test]
+fn overflows() {
+	let (min, max) = (I256::min_value(), I256::max_value());
+	assert_eq!(max.overflowing_add(I256::one()), (min, true));
+	assert_eq!(min.overflowing_sub(I256::one()), (max, true));
+	assert_eq!(min.checked_add(I256::minus_one()), None);
+	assert_eq!(max.checked_sub(I256::minus_one()), None);
+	assert_eq!(min.checked_sub(min), Some(I256::zero()));
+
+	assert_eq!(min.overflowing_mul(I256::minus_one()), (min, true));
+	assert_eq!((min / I256::from(2)).checked_mul(I256::from(2)), Some(min));
+	assert_eq!((min / I256::from(2)).checked_mul(I256::from(-2)), None);
+	assert_eq!(max.checked_mul(I256::from(2)), None);
+
+	assert_eq!(min.overflowing_div(I256::minus_one()), (min, true));
+	assert_eq!(min.overflowing_rem(I256::minus_one()), (I256::zero(), true));
+	assert_eq!(min.checked_div(I256::zero()), None);
+	assert_eq!(min.checked_rem(I256::from(3)), Some(I256::from(-2)));
+}
+
+#[test]
+#[should_panic(expected = "arithmetic operation overflow")]
+fn add_overflow_panic() {
+	let _ = I256::max_value() + I256::one();
+}
+
+#[test]
+#[should_panic(expected = "arithmetic operation overflow")]
+fn div_overflow_panic() {
+	let _ = I256::min_value() / I256::minus_one();
+}
+
+#[test]
+fn shifts() {
+	assert_eq!(I256::from(-16) >> 2, I256::from(-4));
+	assert_eq!(I256::from(-1) >> 300, I256::minus_one());
+	assert_eq!(I256::from(16) >> 2, I256::from(4));
+	assert_eq!(I256::from(-3) << 2, I256::from(-12));
+}
+
+#[test]
+fn matches_i64() {
+	let values = [i64::MIN, i64::MIN + 1, -1_000_003, -2, -1, 0, 1, 3, 1_000_003, i64::MAX];
+	for &a in &values {
+		for &b in &values {
+			let (x, y) = (I64::from(a), I64::from(b));
+			let check = |(result, overflow): (I64, bool), (expected, expected_overflow): (i64, bool)| {
+				assert_eq!((i64::try_from(result), overflow), (Ok(expected), expected_overflow), "{} {}", a, b);
+			};
+			check(x.overflowing_add(y), a.overflowing_add(b));
+			check(x.overflowing_sub(y), a.overflowing_sub(b));
+			check(x.overflowing_mul(y), a.overflowing_mul(b));
+			if b != 0 {
+				check(x.overflowing_div(y), a.overflowing_div(b));
+				check(x.overflowing_rem(y), a.overflowing_rem(b));
+			}
+			assert_eq!(x.cmp(&y), a.cmp(&b));
+		}
+	}
+}
+
+#[test]
+fn conversions() {
+	assert_eq!(I256::try_from(U256::from(5)), Ok(I256::from(5)));
+	assert!(I256::try_from(U256::one() << 255).is_err());
+	assert_eq!(U256::try_from(I256::from(5)), Ok(U256::from(5)));
+	assert!(U256::try_from(I256::from(-5)).is_err());
+	assert_eq!(i64::try_from(I256::from(i64::MIN)), Ok(i64::MIN));
+	assert!(i64::try_from(I256::from(i64::MIN) - I256::one()).is_err());
+	assert_eq!(I256::from_bits(U256::max_value()), I256::minus_one());
+}
+
+#[test]
+fn formatting_and_parsing() {
+	assert_eq!(format!("{}", I256::from(-1234)), "-1234");
+	assert_eq!(format!("{:?}", I256::from(1234)), "1234");
+	assert_eq!(
+		format!("{}", I256::min_value()),
+		"-57896044618658097711785492504343953926634992332820282019728792003956564819968"
+	);
+
+	assert_eq!(I256::from_dec_str("-1234"), Ok(I256::from(-1234)));
+	assert_eq!(I256::from_dec_str("1234"), Ok(I256::from(1234)));
+	let min = "-57896044618658097711785492504343953926634992332820282019728792003956564819968";
+	assert_eq!(I256::from_dec_str(min), Ok(I256::min_value()));
+	assert_eq!(I256::from_dec_str(&min[1..]), Err(FromDecStrErr::InvalidLength));
+	assert_eq!(I256::from_dec_str("-12a"), Err(FromDecStrErr::InvalidCharacter));
+}
@@ -91,6 +91,86 @@ fn uint256_checked_ops() {
 
 	assert_eq!(a.checked_neg(), None);
 	assert_eq!(z.checked_neg(), Some(z));
+
+	assert_eq!(a.checked_shl(1), Some(20.into()));
+	assert_eq!(b.checked_shl(255), Some(U256::zero()));
+	assert_eq!(a.checked_shl(256), None);
+
+	assert_eq!(a.checked_shr(1), Some(5.into()));
+	assert_eq!(b.checked_shr(255), Some(U256::one()));
+	assert_eq!(a.checked_shr(256), None);
+}
+
+#[test]
+fn uint256_shifts_by_bit_width_or_more() {
+	let a = U256::max_value();
+	assert_eq!(a << 256, U256::zero());
+	assert_eq!(a >> 256, U256::zero());
+	assert_eq!(a << U256::max_value(), U256::zero());
+	assert_eq!(a >> (U256::one() << 64), U256::zero());
+
+	assert_eq!(a.overflowing_shl(255), (U256::one() << 255, false));
+	assert_eq!(a.overflowing_shl(257), (a << 1, true));
+	assert_eq!(a.overflowing_shr(256), (a, true));
+	assert_eq!(a.wrapping_shl(256 + 4), a << 4);
+	assert_eq!(a.wrapping_shr(512 + 250), U256::from(63));
+}
+
+#[test]
+fn uint256_rotate() {
+	let a = U256([0x8000_0000_0000_0001, 0, 0, 0xf000_0000_0000_0000]);
+	assert_eq!(a.rotate_left(4), U256([0x0000_0000_0000_001f, 0x8, 0, 0]));
+	assert_eq!(a.rotate_right(4), U256([0x0800_0000_0000_0000, 0, 0, 0x1f00_0000_0000_0000]));
+	assert_eq!(a.rotate_left(0), a);
+	assert_eq!(a.rotate_left(256), a);
+	assert_eq!(a.rotate_right(256 + 4), a.rotate_right(4));
+	assert_eq!(a.rotate_left(100).rotate_right(100), a);
+	assert_eq!(a.rotate_left(64), U256([0xf000_0000_0000_0000, 0x8000_0000_0000_0001, 0, 0]));
+}
+
+#[test]
+fn uint256_saturating_ops() {
+	let a = U256::from(10);
+	let max = U256::max_value();
+
+	assert_eq!(a.saturating_add(a), U256::from(20));
+	assert_eq!(max.saturating_add(a), max);
+
+	assert_eq!(a.saturating_sub(U256::from(3)), U256::from(7));
+	assert_eq!(a.saturating_sub(max), U256::zero());
+
+	assert_eq!(a.saturating_mul(a), U256::from(100));
+	assert_eq!((max / 2).saturating_mul(U256::from(3)), max);
+
+	assert_eq!(a.saturating_pow(U256::from(20)), U256::exp10(20));
+	assert_eq!(U256::from(2).saturating_pow(U256::from(256)), max);
+	assert_eq!(max.saturating_pow(U256::one()), max);
+}
+
+#[test]
+fn uint256_roots() {
+	assert_eq!(U256::zero().integer_sqrt(), U256::zero());
+	assert_eq!(U256::one().integer_sqrt(), U256::one());
+	assert_eq!(U256::from(15).integer_sqrt(), U256::from(3));
+	assert_eq!(U256::from(16).integer_sqrt(), U256::from(4));
+	assert_eq!(U256::exp10(38).integer_sqrt(), U256::exp10(19));
+	assert_eq!((U256::exp10(38) - 1).integer_sqrt(), U256::exp10(19) - 1);
+	assert_eq!(U256::max_value().integer_sqrt(), U256::from(u128::max_value()));
+
+	assert_eq!(U256::exp10(30).nth_root(3), U256::exp10(10));
+	assert_eq!((U256::exp10(30) - 1).nth_root(3), U256::exp10(10) - 1);
+	assert_eq!(U256::from(1023).nth_root(10), U256::one());
+	assert_eq!(U256::from(1024).nth_root(10), U256::from(2));
+	assert_eq!(U256::max_value().nth_root(1), U256::max_value());
+	assert_eq!(U256::max_value().nth_root(255), U256::from(2));
+	assert_eq!(U256::max_value().nth_root(256), U256::one());
+	assert_eq!(U256::max_value().nth_root(u32::max_value()), U256::one());
+}
+
+#[test]
+#[should_panic(expected = "zeroth root is undefined")]
+fn uint256_zeroth_root_panic() {
+	let _ = U256::from(8).nth_root(0);
 }
 
 #[test]
@@ -768,6 +848,108 @@ fn u512_div() {
 	assert_eq!((x, y), (q, r));
 }
 
+#[test]
+fn uint256_mul_mod() {
+	let widen = |x: U256| {
+		let mut words = [0; 8];
+		words[..4].copy_from_slice(&x.0);
+		U512(words)
+	};
+	let max = U256::max_value();
+	assert_eq!(max.mul_mod(max, U256::from(7)), U256::from(1));
+	assert_eq!(max.mul_mod(max, max), U256::zero());
+
+	let values = [
+		U256::from(3),
+		U256::from(MAX),
+		U256::from_dec_str("340282366920938463463374607431768211507").unwrap(),
+		U256([0x0123_4567_89ab_cdef, 0xfedc_ba98_7654_3210, 0, 0x8000_0000_0000_0001]),
+		max - U256::from(42),
+	];
+	for &x in &values {
+		for &y in &values {
+			for &m in &values {
+				let expected = widen(x) * widen(y) % widen(m);
+				assert_eq!(widen(x.mul_mod(y, m)), expected);
+			}
+		}
+	}
+}
+
+#[test]
+fn uint256_add_mod() {
+	let max = U256::max_value();
+	assert_eq!(max.add_mod(U256::one(), U256::from(10)), U256::from(6));
+	assert_eq!(max.add_mod(max, max), U256::zero());
+	assert_eq!(max.add_mod(U256::from(3), max - U256::one()), U256::from(4));
+	assert_eq!(U256::from(5).add_mod(U256::from(6), U256::from(7)), U256::from(4));
+}
+
+#[test]
+fn uint256_pow_mod() {
+	let p =
+		U256::from_dec_str("38873241744847760218045702002058062581688990428170398542849190507947196700873").unwrap();
+	assert_eq!(U256::from(3).pow_mod(U256::from(200), U256::from(1000)), U256::from(1));
+	assert_eq!(U256::from(7).pow_mod(U256::from(20), p), U256::from(7).pow(U256::from(20)));
+	assert_eq!(U256::zero().pow_mod(U256::zero(), p), U256::one());
+	assert_eq!(U256::from(5).pow_mod(U256::from(3), U256::one()), U256::zero());
+
+	// Fermat's little theorem
+	let a = U256::max_value() - U256::from(12345);
+	assert_eq!(a.pow_mod(p - U256::one(), p), U256::one());
+	assert_eq!(a.pow_mod(p, p), a % p);
+}
+
+#[test]
+fn uint256_egcd() {
+	let widen = |x: U256| {
+		let mut words = [0; 8];
+		words[..4].copy_from_slice(&x.0);
+		U512(words)
+	};
+	assert_eq!(U256::from(3).egcd(U256::from(5)), (U256::from(1), U256::from(2), U256::from(1)));
+	assert_eq!(U256::from(240).egcd(U256::from(46)), (U256::from(2), U256::from(14), U256::from(73)));
+	assert_eq!(U256::from(7).egcd(U256::zero()), (U256::from(7), U256::one(), U256::zero()));
+	assert_eq!(U256::zero().egcd(U256::from(7)), (U256::from(7), U256::zero(), U256::zero()));
+
+	let values = [
+		U256::from(12),
+		U256::from(MAX) * U256::from(18),
+		U256::from_dec_str("38873241744847760218045702002058062581688990428170398542849190507947196700873").unwrap(),
+		U256([0, 0x0123_4567_89ab_cdef, 0xfedc_ba98_7654_3210, 0x8000_0000_0000_0000]),
+		U256::max_value(),
+	];
+	for &a in &values {
+		for &b in &values {
+			let (gcd, x, y) = a.egcd(b);
+			assert!((a % gcd).is_zero() && (b % gcd).is_zero());
+			assert!(x <= b / gcd && y <= a / gcd);
+			assert_eq!(widen(a) * widen(x) - widen(b) * widen(y), widen(gcd));
+		}
+	}
+}
+
+#[test]
+fn uint256_inv_mod() {
+	let p =
+		U256::from_dec_str("38873241744847760218045702002058062581688990428170398542849190507947196700873").unwrap();
+	let a = U256::max_value() - U256::from(12345);
+	let inverse = a.inv_mod(p).unwrap();
+	assert!(inverse < p);
+	assert_eq!(a.mul_mod(inverse, p), U256::one());
+
+	assert_eq!(U256::from(3).inv_mod(U256::from(7)), Some(U256::from(5)));
+	assert_eq!(U256::from(2).inv_mod(U256::from(4)), None);
+	assert_eq!(U256::zero().inv_mod(U256::from(7)), None);
+	assert_eq!(U256::from(5).inv_mod(U256::one()), Some(U256::zero()));
+}
+
+#[test]
+#[should_panic]
+fn uint256_mul_mod_by_zero_panic() {
+	U256::one().mul_mod(U256::one(), U256::zero());
+}
+
 #[test]
 fn big_endian() {
 	let source = U256([1, 0, 0, 0]);
@@ -1145,6 +1327,71 @@ pub mod laws {
 					}
 				}
 
+				quickcheck! {
+					fn mul_mod_matches_mul(x: $uint_ty, y: $uint_ty, m: $uint_ty) -> TestResult {
+						if m.is_zero() || x.overflowing_mul(y).1 {
+							return TestResult::discard();
+						}
+
+						TestResult::from_bool(
+							x.mul_mod(y, m) == (x * y) % m
+						)
+					}
+				}
+
+				quickcheck! {
+					fn add_mod_matches_add(x: $uint_ty, y: $uint_ty, m: $uint_ty) -> TestResult {
+						if m.is_zero() || x.overflowing_add(y).1 {
+							return TestResult::discard();
+						}
+
+						TestResult::from_bool(
+							x.add_mod(y, m) == (x + y) % m
+						)
+					}
+				}
+
+				quickcheck! {
+					fn pow_mod_matches_pow(x: $uint_ty, y: u8, m: $uint_ty) -> TestResult {
+						let y = $uint_ty::from(y);
+						if m.is_zero() || x.overflowing_pow(y).1 {
+							return TestResult::discard();
+						}
+
+						TestResult::from_bool(
+							x.pow_mod(y, m) == x.pow(y) % m
+						)
+					}
+				}
+
+				quickcheck! {
+					fn inv_mod_is_inverse(x: $uint_ty, m: $uint_ty) -> TestResult {
+						if m.is_zero() {
+							return TestResult::discard();
+						}
+
+						let (gcd, _, _) = x.egcd(m);
+						TestResult::from_bool(match x.inv_mod(m) {
+							Some(inverse) => gcd == $uint_ty::one() && x.mul_mod(inverse, m) == $uint_ty::one() % m,
+							None => gcd != $uint_ty::one(),
+						})
+					}
+				}
+
+				quickcheck! {
+					fn nth_root_is_floor(x: $uint_ty, n: u8) -> TestResult {
+						let n = u32::from(n % 8) + 1;
+						let root = x.nth_root(n);
+						let exp = $uint_ty::from(n);
+
+						TestResult::from_bool(
+							root.pow(exp) <= x
+								&& root.checked_add(1.into()).and_then(|next| next.checked_pow(exp)).map_or(true, |next| next > x)
+								&& (n != 2 || x.integer_sqrt() == root)
+						)
+					}
+				}
+
 				quickcheck! {
 					fn add_increases(x: $uint_ty, y: $uint_ty) -> TestResult {
 						if y.is_zero() || x.overflowing_add(y).1 {